@@ -0,0 +1,77 @@
+//! Measures the cost of formatting and writing records, in time and allocations per record.
+//!
+//! Run with `cargo bench --bench format`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use log::{Level, Log, Record};
+use pretty_flexible_env_logger::{Buffering, Builder, Format};
+
+/// The system allocator, counting allocations.
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const RECORDS: u64 = 200_000;
+
+fn bench(name: &str, builder: &mut Builder) {
+    let logger = builder.writer(Box::new(io::sink())).build();
+    let log = |i: u64| {
+        logger.log(
+            &Record::builder()
+                .level(Level::Info)
+                .target("bench::format")
+                .args(format_args!("request {} handled", black_box(i)))
+                .build(),
+        )
+    };
+    // Warms up the buffers of the thread.
+    log(0);
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for i in 0..RECORDS {
+        log(i);
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{:<24} {:>8.0} ns/record {:>6.2} allocations/record",
+        name,
+        elapsed.as_nanos() as f64 / RECORDS as f64,
+        allocations as f64 / RECORDS as f64,
+    );
+}
+
+fn main() {
+    bench("pretty", &mut Builder::with("info"));
+    bench("pretty, timed", Builder::with("info").timed(true));
+    bench("compact", Builder::with("info").format(Format::Compact));
+    bench("plain", Builder::with("info").format(Format::Plain));
+    bench("json", Builder::with("info").format(Format::Json));
+    bench("logfmt", Builder::with("info").format(Format::Logfmt));
+    bench(
+        "pretty, block buffered",
+        Builder::with("info").buffering(Buffering::Block {
+            max_delay: std::time::Duration::from_secs(1),
+        }),
+    );
+}
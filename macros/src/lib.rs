@@ -0,0 +1,104 @@
+#![deny(missing_docs)]
+
+//! Procedural macros of [pretty_flexible_env_logger][], re-exported by it with the `macros`
+//! feature.
+//!
+//! [pretty_flexible_env_logger]: https://docs.rs/pretty_flexible_env_logger
+
+use proc_macro::{Delimiter, Group, Ident, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Turns a function into a test which initializes the logger first, following `RUST_LOG`, in
+/// test mode so `cargo test` captures the records of each test.
+///
+/// The logger is only initialized by the first test, so tests can all use the attribute. The
+/// attribute of the test itself is `#[test]` unless another one is given, as in
+/// `#[pretty_log_test(tokio::test)]`.
+///
+/// The logger is reached through `::pretty_flexible_env_logger`, so a dependency renamed in
+/// `Cargo.toml` has to be given with `crate`, as in
+/// `#[pretty_log_test(tokio::test, crate = logger)]`.
+///
+/// ```ignore
+/// use pretty_flexible_env_logger::pretty_log_test;
+///
+/// #[pretty_log_test]
+/// fn reconnects() {
+///     log::debug!("captured, and shown if the test fails");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn pretty_log_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+    let body = match tokens.pop() {
+        Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Brace => body,
+        _ => return compile_error("#[pretty_log_test] expects a function"),
+    };
+
+    let (attr, krate) = match split_crate(attr) {
+        Ok(split) => split,
+        Err(message) => return compile_error(message),
+    };
+    let krate = krate.map_or("::pretty_flexible_env_logger".to_string(), |krate| {
+        krate.to_string()
+    });
+    let init: TokenStream = match format!("{}::__init_test();", krate).parse() {
+        Ok(init) => init,
+        Err(_) => return compile_error("#[pretty_log_test] expects a path after `crate =`"),
+    };
+    let mut statements = init;
+    statements.extend(body.stream());
+    let mut body_with_init = Group::new(Delimiter::Brace, statements);
+    body_with_init.set_span(body.span());
+
+    let attr = if attr.is_empty() {
+        TokenStream::from(TokenTree::Ident(Ident::new("test", Span::call_site())))
+    } else {
+        attr
+    };
+    let mut out = TokenStream::new();
+    out.extend([
+        TokenTree::Punct(Punct::new('#', Spacing::Alone)),
+        TokenTree::Group(Group::new(Delimiter::Bracket, attr)),
+    ]);
+    out.extend(tokens);
+    out.extend([TokenTree::Group(body_with_init)]);
+    out
+}
+
+/// Splits the arguments of the attribute into the attribute of the test and the path given
+/// with `crate = ...`, if any.
+fn split_crate(attr: TokenStream) -> Result<(TokenStream, Option<TokenStream>), &'static str> {
+    let mut args = vec![Vec::new()];
+    for token in attr {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => args.push(Vec::new()),
+            _ => args.last_mut().expect("never empty").push(token),
+        }
+    }
+
+    let mut test = None;
+    let mut krate = None;
+    for arg in args.into_iter().filter(|arg| !arg.is_empty()) {
+        match arg.as_slice() {
+            [TokenTree::Ident(name), TokenTree::Punct(eq), path @ ..]
+                if name.to_string() == "crate" && eq.as_char() == '=' =>
+            {
+                if path.is_empty() || krate.is_some() {
+                    return Err("#[pretty_log_test] expects `crate = path` once");
+                }
+                krate = Some(path.iter().cloned().collect());
+            }
+            _ if test.is_some() => {
+                return Err("#[pretty_log_test] expects a single test attribute")
+            }
+            _ => test = Some(arg.into_iter().collect()),
+        }
+    }
+    Ok((test.unwrap_or_default(), krate))
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    format!("::core::compile_error!({:?});", message)
+        .parse()
+        .unwrap()
+}
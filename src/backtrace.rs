@@ -0,0 +1,127 @@
+//! Capturing backtraces, attached to error records.
+
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+
+use log::kv::{Error, Key, Source, Value, VisitSource};
+use log::Record;
+
+use crate::fmt::short_path;
+
+/// The key backtraces are attached to records under.
+pub(crate) const KEY: &str = "backtrace";
+
+/// Crates whose frames are left out of backtraces, as they only show how the record was logged,
+/// how the error was wrapped or how the program was started.
+const HIDDEN_CRATES: &[&str] = &[
+    "std",
+    "core",
+    "alloc",
+    "log",
+    "anyhow",
+    "eyre",
+    "pretty_flexible_env_logger",
+];
+
+/// The key-values of a record, followed by a backtrace.
+pub(crate) struct WithBacktrace<'a> {
+    pub(crate) backtrace: &'a str,
+    pub(crate) source: &'a dyn Source,
+}
+
+impl Source for WithBacktrace<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        self.source.visit(visitor)?;
+        visitor.visit_pair(Key::from_str(KEY), Value::from(self.backtrace))
+    }
+}
+
+/// Whether a backtrace is attached to the record already.
+pub(crate) fn attached(record: &Record) -> bool {
+    record.key_values().get(Key::from_str(KEY)).is_some()
+}
+
+/// Captures the backtrace of the current thread, see [`render`].
+pub(crate) fn capture() -> String {
+    render(&Backtrace::force_capture())
+}
+
+/// Renders the frames of a backtrace one per line, as `function (file:line)`.
+///
+/// Frames of the standard library, of `log` and of this crate are left out, generic parameters
+/// are stripped from function names and paths are shortened as for source locations, so what
+/// remains is the code which logged the record and its callers.
+pub(crate) fn render(backtrace: &Backtrace) -> String {
+    let mut out = String::new();
+    // The standard library renders frames as `N: function`, followed by `at file:line:column`
+    // lines for the inlined calls and the frame itself.
+    let text = backtrace.to_string();
+    let mut lines = text.lines().map(str::trim).peekable();
+    while let Some(line) = lines.next() {
+        let Some((_, function)) = line.split_once(": ") else {
+            continue;
+        };
+        let location = lines
+            .next_if(|line| line.starts_with("at "))
+            .map(|at| &at[3..]);
+        while lines.next_if(|line| line.starts_with("at ")).is_some() {}
+
+        let function = shorten(function);
+        if hidden(&function) {
+            continue;
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&function);
+        if let Some(location) = location {
+            // Drops the column, keeping `file:line`.
+            let location = location.rsplit_once(':').map_or(location, |(rest, _)| rest);
+            let location = location.strip_prefix("./").unwrap_or(location);
+            let _ = write!(out, " ({})", short_path(location));
+        }
+    }
+    out
+}
+
+/// Strips the generic parameters and the hash from a function name, keeping the `<T as Trait>`
+/// part of trait methods.
+fn shorten(function: &str) -> String {
+    let function = match function.rsplit_once("::h") {
+        Some((rest, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            rest
+        }
+        _ => function,
+    };
+    let mut out = String::with_capacity(function.len());
+    let mut depth = 0;
+    for (i, c) in function.char_indices() {
+        match c {
+            // A bracket at the start opens a qualified path rather than generic parameters.
+            '<' if i == 0 => out.push(c),
+            '<' => depth += 1,
+            '>' if depth > 0 => depth -= 1,
+            _ if depth > 0 => {}
+            _ => out.push(c),
+        }
+    }
+    out.replace("::::", "::").trim_end_matches("::").to_string()
+}
+
+/// Whether a frame is left out, see [`HIDDEN_CRATES`].
+fn hidden(function: &str) -> bool {
+    if function == "main" || function.starts_with('_') || function.starts_with("<unknown>") {
+        return true;
+    }
+    let path = function.trim_start_matches(['<', '&']);
+    let path = path.strip_prefix("dyn ").unwrap_or(path);
+    let path = path.strip_prefix("mut ").unwrap_or(path);
+    // The methods of a trait implemented for a type parameter, as in `<E as Trait>::method`,
+    // belong to the crate of the trait.
+    let path = match path.split_once(" as ") {
+        Some((ty, path)) if !ty.contains("::") => path,
+        _ => path,
+    };
+    let krate = path.split("::").next().unwrap_or(path);
+    HIDDEN_CRATES.contains(&krate)
+}
@@ -0,0 +1,127 @@
+//! Background batching of records for sinks pushing them to remote services.
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How entries are grouped before being pushed.
+#[derive(Clone, Copy)]
+pub(crate) struct BatchConfig {
+    /// Number of entries pushed at once.
+    pub(crate) size: usize,
+    /// How long the oldest entry waits before an incomplete batch is pushed.
+    pub(crate) wait: Duration,
+}
+
+enum Message<E> {
+    Entry(E),
+    Flush(mpsc::SyncSender<()>),
+}
+
+/// Collects entries on a background thread, pushing them in batches.
+///
+/// Failed batches are retried after the batch wait. While the service is unreachable, up to ten
+/// batches are kept around, beyond which the oldest entries are dropped.
+pub(crate) struct Batcher<E> {
+    sender: Option<Sender<Message<E>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<E: Send + 'static> Batcher<E> {
+    /// Spawns the thread calling `push` with each batch.
+    pub(crate) fn spawn<F>(name: &str, config: BatchConfig, push: F) -> io::Result<Batcher<E>>
+    where
+        F: FnMut(&[E]) -> io::Result<()> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let worker = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || crate::sink::inside(|| run(config, receiver, push)))?;
+        Ok(Batcher {
+            sender: Some(sender),
+            worker: Some(worker),
+        })
+    }
+
+    pub(crate) fn send(&self, entry: E) -> io::Result<()> {
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(Message::Entry(entry)).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "the batch thread is gone"))
+    }
+
+    /// Pushes pending entries, waiting for the push to complete.
+    pub(crate) fn flush(&self) {
+        let (ack, done) = mpsc::sync_channel(1);
+        if let Some(sender) = &self.sender {
+            if sender.send(Message::Flush(ack)).is_ok() {
+                let _ = done.recv();
+            }
+        }
+    }
+}
+
+impl<E> Drop for Batcher<E> {
+    fn drop(&mut self) {
+        // Closing the channel makes the thread push what is left and exit.
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run<E, F>(config: BatchConfig, receiver: Receiver<Message<E>>, mut push: F)
+where
+    F: FnMut(&[E]) -> io::Result<()>,
+{
+    let max_pending = config.size * 10;
+    let mut pending: Vec<E> = Vec::new();
+    let mut deadline: Option<Instant> = None;
+
+    let mut push_pending = |pending: &mut Vec<E>, deadline: &mut Option<Instant>| {
+        if pending.is_empty() {
+            *deadline = None;
+            return;
+        }
+        match push(pending) {
+            Ok(()) => {
+                pending.clear();
+                *deadline = None;
+            }
+            Err(_) => {
+                if pending.len() > max_pending {
+                    pending.drain(..pending.len() - max_pending);
+                }
+                *deadline = Some(Instant::now() + config.wait);
+            }
+        }
+    };
+
+    loop {
+        let timeout = deadline.map_or(Duration::from_secs(3600), |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(Message::Entry(entry)) => {
+                if pending.is_empty() {
+                    deadline = Some(Instant::now() + config.wait);
+                }
+                pending.push(entry);
+                if pending.len() >= config.size {
+                    push_pending(&mut pending, &mut deadline);
+                }
+            }
+            Ok(Message::Flush(ack)) => {
+                push_pending(&mut pending, &mut deadline);
+                let _ = ack.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => push_pending(&mut pending, &mut deadline),
+            Err(RecvTimeoutError::Disconnected) => {
+                push_pending(&mut pending, &mut deadline);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Information about the build of the program, attached to records or to a startup banner.
+
+use std::fmt;
+
+/// The version, build profile and commit hash of the program.
+///
+/// It is created with the [`build_info!`](crate::build_info!) macro, which captures the values
+/// set by cargo when the calling crate is compiled. The commit hash is taken from the
+/// `GIT_HASH` (or `VERGEN_GIT_SHA`) environment variable at compile time, usually set by a
+/// build script:
+///
+/// ```no_run
+/// // build.rs
+/// use std::process::Command;
+///
+/// let output = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output().unwrap();
+/// let hash = String::from_utf8(output.stdout).unwrap();
+/// println!("cargo:rustc-env=GIT_HASH={}", hash.trim());
+/// ```
+///
+/// ```
+/// use pretty_flexible_env_logger::{build_info, Builder};
+///
+/// Builder::with("info").startup_banner(build_info!()).init();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    name: &'static str,
+    version: &'static str,
+    profile: &'static str,
+    commit: Option<&'static str>,
+}
+
+impl BuildInfo {
+    /// Creates build information from its parts, usually through
+    /// [`build_info!`](crate::build_info!).
+    pub const fn new(name: &'static str, version: &'static str, profile: &'static str) -> Self {
+        BuildInfo {
+            name,
+            version,
+            profile,
+            commit: None,
+        }
+    }
+
+    /// Sets the hash of the commit the program was built from.
+    pub const fn with_commit(mut self, commit: Option<&'static str>) -> Self {
+        self.commit = commit;
+        self
+    }
+
+    /// The name of the package.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The version of the package.
+    pub fn version(&self) -> &'static str {
+        self.version
+    }
+
+    /// The build profile, `debug` or `release`.
+    pub fn profile(&self) -> &'static str {
+        self.profile
+    }
+
+    /// The hash of the commit the program was built from, if known.
+    pub fn commit(&self) -> Option<&'static str> {
+        self.commit
+    }
+}
+
+/// Renders the fields as `version=1.4.2 profile=release commit=4f2c1a9`.
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "version={} profile={}", self.version, self.profile)?;
+        if let Some(commit) = self.commit {
+            write!(f, " commit={}", commit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Creates a [`BuildInfo`] for the calling crate.
+///
+/// The profile is `debug` when debug assertions are enabled, and `release` otherwise. The
+/// commit hash is read from the `GIT_HASH` or `VERGEN_GIT_SHA` environment variables.
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::BuildInfo::new(
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+        )
+        .with_commit(match option_env!("GIT_HASH") {
+            Some(hash) => Some(hash),
+            None => option_env!("VERGEN_GIT_SHA"),
+        })
+    };
+}
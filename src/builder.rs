@@ -0,0 +1,2261 @@
+//! Run-time configuration of the logger.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+
+use env_logger::filter;
+use env_logger::WriteStyle;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use termcolor::WriteColor;
+
+use crate::backtrace::{self, WithBacktrace};
+use crate::build_info::BuildInfo;
+use crate::capture;
+use crate::fmt::{
+    Format, FormatFn, Formatter, Labels, Multiline, Options, Separators, TargetWidth,
+};
+use crate::kv::{self, Stamped};
+use crate::layer::{Layer, RecordView};
+use crate::mask::Masks;
+use crate::process::app_name;
+use crate::recorder::{FlightRecorder, Recorder};
+#[cfg(feature = "redact")]
+use crate::redact::Redactions;
+use crate::ring::{Ring, RingBuffer};
+use crate::sink::{self, Buffering, Destination, Discard, Forward, Outputs, Sink};
+use crate::stats::{Counters, LogStats};
+use crate::template::{Template, TemplateError};
+use crate::thread_level;
+use crate::throttle::{Dedup, LossReport, RateLimit, Repeated, Sampler, Suppressed};
+use crate::worker::{self, Losses, Overflow, Worker};
+
+/// `Builder` acts as a builder for initializing a [`Logger`].
+///
+/// It starts from the directives resolved the same way as in [`init_with`](crate::init_with),
+/// and allows tweaking where and how records are written before installing the logger.
+///
+/// ## Example
+///
+/// ```
+/// use log::info;
+/// use pretty_flexible_env_logger::Builder;
+///
+/// Builder::with("info")
+///     .writer(Box::new(std::io::stdout()))
+///     .init();
+///
+/// info!("written to stdout");
+/// ```
+pub struct Builder {
+    filter: filter::Builder,
+    format: Format,
+    format_fn: Option<Arc<FormatFn>>,
+    timed: bool,
+    options: Options,
+    aliases: Vec<(String, String)>,
+    strip_prefixes: Vec<String>,
+    labels: Labels,
+    app_name: Option<String>,
+    separators: Separators,
+    destination: Option<Destination>,
+    routes: Vec<(String, Destination)>,
+    sinks: Vec<Box<dyn Sink>>,
+    ring: Option<RingBuffer>,
+    log_panics: bool,
+    recorder: Option<FlightRecorder>,
+    banner: Option<BuildInfo>,
+    max_message_len: Option<usize>,
+    sequence: bool,
+    backtraces: bool,
+    layers: Vec<Box<Layer>>,
+    filter_fns: Vec<Box<FilterFn>>,
+    quiet: bool,
+    on_error: Vec<Box<RecordFn>>,
+    masks: Masks,
+    #[cfg(feature = "redact")]
+    redactions: Redactions,
+    #[cfg(feature = "tracing")]
+    capture_tracing: bool,
+    rate_limits: Vec<RateLimit>,
+    dedup: bool,
+    sample_rates: [Option<f64>; 5],
+    summary: bool,
+    loss_report: Option<Duration>,
+    source: Option<String>,
+    directives: Vec<String>,
+    log_config: bool,
+    non_blocking: Option<usize>,
+    overflow: Overflow,
+    buffering: Buffering,
+    sync_on: Option<Level>,
+    disabled: bool,
+    is_test: bool,
+    write_style: WriteStyle,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+impl Builder {
+    /// Initializes the builder with directives taken from the `RUST_LOG` environment variable.
+    pub fn new() -> Builder {
+        Builder::with("RUST_LOG")
+    }
+
+    /// Initializes the builder with a custom configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `environment_or_inline_value` - A string slice that holds the name of environment variable, or
+    ///   the directives string in the same form as the `RUST_LOG` environment variable.
+    pub fn with(environment_or_inline_value: &str) -> Builder {
+        let mut builder = Builder::empty();
+        builder.parse_filters(&crate::resolve(environment_or_inline_value));
+        builder.source = Some(crate::describe_source(environment_or_inline_value));
+        builder
+    }
+
+    /// Initializes the builder without any directives.
+    ///
+    /// The line layout is taken from the `RUST_LOG_FORMAT` environment variable when set, see
+    /// [`format`](Builder::format), and the optional parts of lines from their own variables.
+    pub(crate) fn empty() -> Builder {
+        let mut builder = Builder {
+            filter: filter::Builder::new(),
+            format: Format::default(),
+            format_fn: None,
+            timed: false,
+            options: Options::default(),
+            aliases: Vec::new(),
+            strip_prefixes: Vec::new(),
+            labels: Labels::default(),
+            app_name: None,
+            separators: Separators::default(),
+            destination: None,
+            routes: Vec::new(),
+            sinks: Vec::new(),
+            ring: None,
+            log_panics: false,
+            recorder: None,
+            banner: None,
+            max_message_len: None,
+            sequence: false,
+            backtraces: false,
+            layers: Vec::new(),
+            filter_fns: Vec::new(),
+            quiet: false,
+            on_error: Vec::new(),
+            masks: Masks::default(),
+            rate_limits: Vec::new(),
+            dedup: false,
+            sample_rates: [None; 5],
+            summary: false,
+            loss_report: Some(Duration::from_secs(10)),
+            source: None,
+            directives: Vec::new(),
+            log_config: false,
+            non_blocking: None,
+            overflow: Overflow::Block,
+            buffering: Buffering::Line,
+            sync_on: None,
+            #[cfg(feature = "redact")]
+            redactions: Redactions::default(),
+            #[cfg(feature = "tracing")]
+            capture_tracing: false,
+            disabled: false,
+            is_test: false,
+            write_style: WriteStyle::Auto,
+        };
+        if let Ok(value) = std::env::var("RUST_LOG_FORMAT") {
+            builder.parse_format(&value);
+        }
+        if let Some(enabled) = env_flag("RUST_LOG_SOURCE_LOCATION") {
+            builder.with_source_location(enabled);
+        }
+        if let Some(enabled) = env_flag("RUST_LOG_SHOW_TARGET") {
+            builder.show_target(enabled);
+        }
+        if let Some(enabled) = env_flag("RUST_LOG_BACKTRACE") {
+            builder.with_backtraces(enabled);
+        }
+        if let Some(quiet) = env_flag("RUST_LOG_QUIET") {
+            builder.quiet(quiet);
+        }
+        builder
+    }
+
+    /// Adds directives to the filter, in the same form as the `RUST_LOG` environment variable.
+    pub fn parse_filters(&mut self, filters: &str) -> &mut Self {
+        self.filter.parse(filters);
+        self.directives.push(filters.to_string());
+        self
+    }
+
+    /// Adds a directive to the filter for a specific module, or for all modules if `None`.
+    pub fn filter(&mut self, module: Option<&str>, level: LevelFilter) -> &mut Self {
+        self.filter.filter(module, level);
+        let level = level.to_string().to_lowercase();
+        self.directives.push(match module {
+            Some(module) => format!("{}={}", module, level),
+            None => level,
+        });
+        self
+    }
+
+    /// Writes a record describing where the directives came from, the directives themselves,
+    /// the format and the outputs when the logger is installed, whatever the filters, which
+    /// makes "why am I not seeing logs?" easy to answer.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// // Writes a record such as: logging configured from the `RUST_LOG` environment
+    /// // variable: directives `info`, format Pretty, output to stderr
+    /// Builder::new().log_config_on_init(true).init();
+    /// ```
+    pub fn log_config_on_init(&mut self, enabled: bool) -> &mut Self {
+        self.log_config = enabled;
+        self
+    }
+
+    /// Describes the configuration, for [`log_config_on_init`](Builder::log_config_on_init).
+    fn describe(&self) -> String {
+        let directives = match self.directives.join(",") {
+            directives if directives.is_empty() => "none".to_string(),
+            directives => format!("`{}`", directives),
+        };
+        let format = match self.format_fn {
+            Some(_) => "custom".to_string(),
+            None => format!("{:?}", self.format),
+        };
+        let output = match &self.destination {
+            _ if self.disabled => "nowhere, output is disabled",
+            Some(Destination::Writer(_)) => "a custom writer",
+            Some(Destination::Stdout) => "stdout",
+            Some(Destination::File(_)) => "a file",
+            Some(Destination::Sink(_)) => "a custom sink",
+            #[cfg(feature = "indicatif")]
+            Some(Destination::Progress(_)) => "stderr, above progress bars",
+            None if cfg!(all(target_arch = "wasm32", feature = "wasm")) => "the browser console",
+            None => "stderr",
+        };
+        let mut description = format!(
+            "logging configured from {}: directives {}, format {}, output to {}",
+            self.source.as_deref().unwrap_or("code"),
+            directives,
+            format,
+            output
+        );
+        if !self.disabled && !self.routes.is_empty() {
+            description.push_str(&format!(", {} routes", self.routes.len()));
+        }
+        if !self.disabled && !self.sinks.is_empty() {
+            description.push_str(&format!(", {} additional sinks", self.sinks.len()));
+        }
+        if self.quiet {
+            description.push_str(", quiet");
+        }
+        description
+    }
+
+    /// Writes at most `max` records of the targets starting with `target` in every `window`,
+    /// protecting terminals and collectors from log storms.
+    ///
+    /// Records over the limit are dropped. When the next window starts, or the logger is
+    /// flushed, a warning tells how many were suppressed since the last one. The most specific
+    /// limit applies when several targets match.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info")
+    ///     .rate_limit("noisy_crate", 10, Duration::from_secs(1))
+    ///     .init();
+    /// ```
+    pub fn rate_limit(&mut self, target: &str, max: u32, window: Duration) -> &mut Self {
+        self.rate_limits.push(RateLimit::new(target, max, window));
+        self
+    }
+
+    /// Sets how often a warning tells how many records were discarded since the previous one,
+    /// every ten seconds by default, so losses are never silent. `None` turns the warning off.
+    ///
+    /// This covers records left out by [`sample`](Builder::sample) and records dropped by the
+    /// [`non_blocking`](Builder::non_blocking) writer when its queue is full. Rate limits and
+    /// deduplication write their own summaries.
+    pub fn dropped_report(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.loss_report = interval;
+        self
+    }
+
+    /// Writes records on a background thread, fed through a queue of up to `capacity` records,
+    /// so logging never blocks on a slow terminal, pipe or network sink unless the queue is
+    /// full.
+    ///
+    /// The key-values and diagnostic context of records are captured when they are logged, as
+    /// strings, numbers and booleans. Records still queued when the program exits are lost,
+    /// unless the logger is shut down first, see [`try_init_guarded`](Builder::try_init_guarded).
+    /// Where threads are not available, records are written directly.
+    pub fn non_blocking(&mut self, capacity: usize) -> &mut Self {
+        self.non_blocking = Some(capacity);
+        self
+    }
+
+    /// Sets what the [`non_blocking`](Builder::non_blocking) writer does when its queue is full:
+    /// wait for room, which is the default, or drop the newest or the oldest record, trading
+    /// completeness for latency. Dropped records are counted in [`stats`](crate::stats) and
+    /// reported as set with [`dropped_report`](Builder::dropped_report).
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::{Builder, Overflow};
+    ///
+    /// Builder::with("info")
+    ///     .non_blocking(1024)
+    ///     .overflow(Overflow::DropOldest)
+    ///     .init();
+    /// ```
+    pub fn overflow(&mut self, overflow: Overflow) -> &mut Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Writes a one-line account of the warnings and errors of the run when the logger shuts
+    /// down, as in `42 warnings, 3 errors during this run, first error at …`, which is handy at
+    /// the end of batch jobs and command line tools.
+    ///
+    /// Loggers built with [`build`](Builder::build) write it when dropped. The global logger is
+    /// never dropped, and writes it when [`shutdown`](crate::shutdown) is called.
+    pub fn summary_on_shutdown(&mut self, enabled: bool) -> &mut Self {
+        self.summary = enabled;
+        self
+    }
+
+    /// Keeps only a random fraction of the records of `level`, between `0.0` and `1.0`, so
+    /// verbose logging can stay on at a manageable volume.
+    ///
+    /// Kept records carry a `sampled` key-value with the number of records they stand for,
+    /// themselves included, to scale counts back up downstream.
+    ///
+    /// ```
+    /// use log::Level;
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("trace")
+    ///     .sample(Level::Debug, 0.01)
+    ///     .sample(Level::Trace, 0.001)
+    ///     .init();
+    /// ```
+    pub fn sample(&mut self, level: Level, rate: f64) -> &mut Self {
+        self.sample_rates[level as usize - 1] = Some(rate);
+        self
+    }
+
+    /// Collapses runs of identical consecutive records into a single
+    /// `last message repeated N times` record, like classic syslog daemons, so tight retry loops
+    /// don't flood the output.
+    ///
+    /// The summary is written when a different record comes in, or when the logger is flushed.
+    pub fn dedup(&mut self, enabled: bool) -> &mut Self {
+        self.dedup = enabled;
+        self
+    }
+
+    /// Adds a closure deciding whether records are written, on top of the directives.
+    ///
+    /// Records are only written when they are enabled by the directives and accepted by every
+    /// closure, which can implement logic directives can't express, such as feature flags or
+    /// per-tenant verbosity. Closures never enable records the directives leave out.
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// static VERBOSE_SQL: AtomicBool = AtomicBool::new(false);
+    ///
+    /// Builder::with("debug")
+    ///     .filter_fn(|metadata| {
+    ///         !metadata.target().starts_with("sqlx") || VERBOSE_SQL.load(Ordering::Relaxed)
+    ///     })
+    ///     .init();
+    /// ```
+    pub fn filter_fn<F>(&mut self, filter: F) -> &mut Self
+    where
+        F: Fn(&Metadata) -> bool + Send + Sync + 'static,
+    {
+        self.filter_fns.push(Box::new(filter));
+        self
+    }
+
+    /// Leaves out every record less severe than warnings, whatever the directives and the
+    /// [thread levels](crate::set_thread_level), for a single switch keeping programs quiet
+    /// unless something goes wrong.
+    ///
+    /// It can also be enabled at run time by setting the `RUST_LOG_QUIET` environment variable
+    /// to `1`, which this method overrides.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("debug").quiet(true).init();
+    ///
+    /// log::info!("left out");
+    /// log::warn!("written");
+    /// ```
+    pub fn quiet(&mut self, quiet: bool) -> &mut Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Adds a callback invoked with every error record passing the filters, once it has been
+    /// written.
+    ///
+    /// This is the place to bump an error metric, flush buffers, or ring a bell during
+    /// development. The callback runs on the thread which logged the record, and must not log
+    /// errors itself.
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// static ERRORS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// Builder::with("info")
+    ///     .on_error(|_| {
+    ///         ERRORS.fetch_add(1, Ordering::Relaxed);
+    ///     })
+    ///     .init();
+    /// ```
+    pub fn on_error<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(&Record) + Send + Sync + 'static,
+    {
+        self.on_error.push(Box::new(callback));
+        self
+    }
+
+    /// Sets the layout of records written to the standard error and custom writers.
+    ///
+    /// The layout can also be chosen at run time through the `RUST_LOG_FORMAT` environment
+    /// variable, holding one of `pretty`, `plain`, `compact`, `json`, `logfmt`, `google_cloud`
+    /// and `datadog`, or a [template](crate::template). Setting it in code overrides the
+    /// variable.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::{Builder, Format};
+    ///
+    /// Builder::with("info").format(Format::GoogleCloud).init();
+    /// ```
+    pub fn format(&mut self, format: Format) -> &mut Self {
+        self.format = format;
+        self.format_fn = None;
+        self
+    }
+
+    /// Sets the layout from its name, or from a template, as in the `RUST_LOG_FORMAT` environment
+    /// variable. Invalid values are reported on the standard error and ignored.
+    fn parse_format(&mut self, value: &str) {
+        let format = match Format::from_name(value) {
+            Some(format) => format,
+            None if value.contains('{') => {
+                if let Err(e) = self.template(value) {
+                    warn(format_args!("ignoring RUST_LOG_FORMAT, {}", e));
+                }
+                return;
+            }
+            None => {
+                warn(format_args!(
+                    "ignoring RUST_LOG_FORMAT, unknown format `{}`",
+                    value
+                ));
+                return;
+            }
+        };
+        self.format(format);
+    }
+
+    /// Sets the destination from the `RUST_LOG_TARGET` environment variable if set, over the one
+    /// set in code, so operators can redirect the output without changing it.
+    fn env_target(&mut self) {
+        if let Ok(value) = std::env::var("RUST_LOG_TARGET") {
+            self.parse_target(&value);
+        }
+    }
+
+    /// Sets the destination from the value of the `RUST_LOG_TARGET` environment variable,
+    /// warning about values it doesn't know.
+    fn parse_target(&mut self, value: &str) {
+        let value = value.trim();
+        match value.to_lowercase().as_str() {
+            "stderr" => self.destination = None,
+            "stdout" => self.destination = Some(Destination::Stdout),
+            "null" => self.destination = Some(Destination::Sink(Box::new(Discard))),
+            _ => match value.strip_prefix("file:") {
+                Some(path) => match std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                {
+                    Ok(file) => self.destination = Some(Destination::File(file)),
+                    Err(e) => warn(format_args!(
+                        "ignoring RUST_LOG_TARGET, cannot open {}: {}",
+                        path, e
+                    )),
+                },
+                None => warn(format_args!(
+                    "ignoring RUST_LOG_TARGET, unknown target `{}`",
+                    value
+                )),
+            },
+        }
+    }
+
+    /// Sets a closure rendering records written to the standard error and custom writers,
+    /// replacing the [`format`](Builder::format).
+    ///
+    /// The closure is expected to write a whole line, including the trailing newline. Colors
+    /// are set through the [`WriteColor`](termcolor::WriteColor) methods of the buffer, and are
+    /// stripped when the destination does not support them.
+    ///
+    /// ```
+    /// use std::io::Write;
+    ///
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info")
+    ///     .format_fn(|buf, record| writeln!(buf, "[{}] {}", record.level(), record.args()))
+    ///     .init();
+    /// ```
+    pub fn format_fn<F>(&mut self, format: F) -> &mut Self
+    where
+        F: Fn(&mut dyn WriteColor, &Record) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.format_fn = Some(Arc::new(format));
+        self
+    }
+
+    /// Sets the line layout from a [template](crate::template), replacing the
+    /// [`format`](Builder::format).
+    ///
+    /// The layout can also be set at run time through the `RUST_LOG_FORMAT` environment
+    /// variable, which this method overrides.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info")
+    ///     .template("{timestamp} [{level:>5}] {target} — {message}")?
+    ///     .init();
+    /// # Ok::<(), pretty_flexible_env_logger::TemplateError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the template refers to unknown fields or has unbalanced braces.
+    pub fn template(&mut self, template: &str) -> Result<&mut Self, TemplateError> {
+        let template = Template::parse(template)?;
+        Ok(self.format_fn(move |buf, record| template.write(buf, record)))
+    }
+
+    /// Whether to append the source file and line each record originates from, as in
+    /// `(src/db/pool.rs:142)`, to the pretty, plain and compact layouts.
+    ///
+    /// Paths into the cargo registry, git checkouts and the standard library are shortened to
+    /// `crate-name/src/...`, and absolute paths within the working directory are made relative.
+    ///
+    /// It can also be enabled at run time by setting the `RUST_LOG_SOURCE_LOCATION` environment
+    /// variable to `1`, which this method overrides.
+    pub fn with_source_location(&mut self, enabled: bool) -> &mut Self {
+        self.options.source_location = enabled;
+        self
+    }
+
+    /// Whether to prefix lines of the pretty, plain and compact layouts with the name of the
+    /// current thread, or with its ID if it is unnamed.
+    pub fn with_thread_name(&mut self, enabled: bool) -> &mut Self {
+        self.options.thread = enabled;
+        self
+    }
+
+    /// Whether to prefix lines of the pretty, plain and compact layouts with the name of the
+    /// machine, telling apart hosts whose logs are aggregated into one stream.
+    ///
+    /// The name is looked up once, and cached for the lifetime of the process.
+    pub fn with_hostname(&mut self, enabled: bool) -> &mut Self {
+        self.options.hostname = enabled;
+        self
+    }
+
+    /// Whether to prefix lines of the pretty, plain and compact layouts with the process ID,
+    /// telling apart instances of the same program appending to a shared file.
+    pub fn with_pid(&mut self, enabled: bool) -> &mut Self {
+        self.options.pid = enabled;
+        self
+    }
+
+    /// Appends the version, build profile and commit hash of the program, as in
+    /// `version=1.4.2 profile=release commit=4f2c1a9`, to lines of the pretty, plain and compact
+    /// layouts.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::{build_info, Builder};
+    ///
+    /// Builder::with("info").with_build_info(build_info!()).init();
+    /// ```
+    pub fn with_build_info(&mut self, build_info: BuildInfo) -> &mut Self {
+        self.options.build_info = Some(build_info);
+        self
+    }
+
+    /// Logs the version, build profile and commit hash of the program once, when the logger is
+    /// installed by [`try_init`](Builder::try_init) or [`init`](Builder::init).
+    ///
+    /// The banner is an info record whose target is the package name, so it is only written
+    /// when the directives enable it.
+    pub fn startup_banner(&mut self, build_info: BuildInfo) -> &mut Self {
+        self.banner = Some(build_info);
+        self
+    }
+
+    /// Sets how continuation lines of messages spanning several lines are laid out in the
+    /// pretty, plain and compact layouts, so stack traces and pretty-printed structures stay
+    /// readable.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::{Builder, Multiline};
+    ///
+    /// Builder::with("info").multiline(Multiline::Indent).init();
+    ///
+    /// log::info!("config: {:#?}", vec![1, 2, 3]);
+    /// ```
+    pub fn multiline(&mut self, multiline: Multiline) -> &mut Self {
+        self.options.multiline = multiline;
+        self
+    }
+
+    /// Sets how the target column of the pretty and plain layouts is padded.
+    ///
+    /// By default the column grows to fit the longest target seen so far, which shifts the
+    /// messages of later records whenever a module with a longer name logs for the first time.
+    /// A fixed width keeps the columns stable across long sessions and between runs.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::{Builder, TargetWidth};
+    ///
+    /// Builder::with("info").target_width(TargetWidth::Fixed(24)).init();
+    /// ```
+    pub fn target_width(&mut self, target_width: TargetWidth) -> &mut Self {
+        self.options.target_width = target_width;
+        self
+    }
+
+    /// Wraps messages longer than the width of the terminal between words, aligning the
+    /// continuation lines under the start of the message, instead of letting the terminal break
+    /// them mid-word under the timestamp.
+    ///
+    /// This only applies to the pretty, plain and compact layouts when writing to a terminal;
+    /// output redirected to files or pipes is left as is.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info").wrap(true).init();
+    /// ```
+    pub fn wrap(&mut self, wrap: bool) -> &mut Self {
+        self.options.wrap = wrap;
+        self
+    }
+
+    /// Stamps each written record with an incrementing sequence number, starting at 1, as a
+    /// `seq` key-value.
+    ///
+    /// Gaps or reordering in the numbers tell records were lost or shuffled on their way, for
+    /// instance over a lossy network sink. Every format and sink receives the number.
+    pub fn sequence_numbers(&mut self, enabled: bool) -> &mut Self {
+        self.sequence = enabled;
+        self
+    }
+
+    /// Captures a backtrace when an error record is logged, attached to it as a `backtrace`
+    /// key-value and written beneath the record by the pretty, plain and compact layouts.
+    ///
+    /// Frames of the standard library, of `log` and of this crate are left out, and function
+    /// names and paths are shortened, so the backtrace starts at the code which logged the
+    /// record. Capturing one is slow, so it is only done for records which get written.
+    ///
+    /// It can also be enabled at run time by setting the `RUST_LOG_BACKTRACE` environment
+    /// variable to `1`, which this method overrides.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info").with_backtraces(true).init();
+    ///
+    /// // ERROR app > cannot open the config
+    /// //     at app::config::load (src/config.rs:42)
+    /// //     at app::main (src/main.rs:7)
+    /// log::error!("cannot open the config");
+    /// ```
+    pub fn with_backtraces(&mut self, enabled: bool) -> &mut Self {
+        self.backtraces = enabled;
+        self
+    }
+
+    /// Adds a hook run on every record reaching the logger, before it is formatted, kept in the
+    /// ring buffer or the flight recorder, captured, or handed to the error callbacks.
+    ///
+    /// Hooks run in the order they were added, and can rewrite the message or attach
+    /// key-values through the [`RecordView`] they receive, to enrich records without a custom
+    /// format.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info")
+    ///     .layer(|record| record.insert("region", "eu-west-1"))
+    ///     .layer(|record| {
+    ///         let message = record.message().replace("password", "********");
+    ///         record.set_message(message);
+    ///     })
+    ///     .init();
+    /// ```
+    pub fn layer<F>(&mut self, layer: F) -> &mut Self
+    where
+        F: Fn(&mut RecordView) + Send + Sync + 'static,
+    {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Sets the keys whose values are masked as `***` in every format and sink, so sensitive
+    /// structured fields never end up in the logs in plain text.
+    ///
+    /// Keys match when they contain one of the given strings, ignoring case: `token` masks
+    /// `access_token` and `X-Token` alike. By default `password`, `passwd`, `secret`, `token`,
+    /// `api_key`, `apikey`, `authorization` and `cookie` are masked; an empty list turns
+    /// masking off.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info").mask_keys(&["password", "ssn", "card_number"]).init();
+    ///
+    /// // Written as `card_number=***`.
+    /// log::info!(card_number = "4111111111111111"; "payment accepted");
+    /// ```
+    pub fn mask_keys(&mut self, keys: &[&str]) -> &mut Self {
+        self.masks = Masks::new(keys);
+        self
+    }
+
+    /// Replaces the matches of a regular expression in messages and key-values with
+    /// `replacement`, before any sink sees them, to keep credentials out of the logs.
+    ///
+    /// The replacement can refer to capture groups, as in `$1`. Patterns apply in the order they
+    /// were added, after the hooks added with [`layer`](Builder::layer).
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info")
+    ///     .redact(r"(?i)bearer [a-z0-9._-]+", "[REDACTED]")
+    ///     .unwrap()
+    ///     .init();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pattern is not a valid regular expression.
+    #[cfg(feature = "redact")]
+    pub fn redact(&mut self, pattern: &str, replacement: &str) -> Result<&mut Self, regex::Error> {
+        self.redactions
+            .push(regex::Regex::new(pattern)?, replacement);
+        Ok(self)
+    }
+
+    /// Truncates messages longer than `max_len` bytes, marking them with an ellipsis and their
+    /// original length, as in `… (5242880 bytes)`.
+    ///
+    /// This protects terminals and collectors from accidental multi-megabyte dumps, in every
+    /// format and sink.
+    pub fn max_message_len(&mut self, max_len: usize) -> &mut Self {
+        self.max_message_len = Some(max_len);
+        self
+    }
+
+    /// Whether to escape control characters and ANSI sequences in messages, as in `\x1b[2J`,
+    /// preventing untrusted data from forging records or corrupting terminals.
+    ///
+    /// By default, messages are escaped unless written to a terminal.
+    pub fn escape_control_chars(&mut self, escape: bool) -> &mut Self {
+        self.options.escape = Some(escape);
+        self
+    }
+
+    /// Whether to show the target of records in the pretty, plain and compact layouts, which
+    /// small programs made of a single crate can do without.
+    ///
+    /// It can also be hidden at run time by setting the `RUST_LOG_SHOW_TARGET` environment
+    /// variable to `0`, which this method overrides.
+    pub fn show_target(&mut self, show: bool) -> &mut Self {
+        self.options.hide_target = !show;
+        self
+    }
+
+    /// Whether to write info records like normal program output, without their level and
+    /// target, while other levels keep their colored tags. This suits user-facing programs.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info").clean_info(true).init();
+    ///
+    /// log::info!("3 files copied"); // 3 files copied
+    /// log::warn!("1 file skipped"); //  WARN  my_cli > 1 file skipped
+    /// ```
+    pub fn clean_info(&mut self, clean: bool) -> &mut Self {
+        self.options.clean_info = clean;
+        self
+    }
+
+    /// Replaces the text shown for `level` in the pretty, plain and compact layouts, for
+    /// example with `WARNING` or a localized label.
+    ///
+    /// Labels are padded to the width of the longest one, so the columns stay aligned.
+    ///
+    /// ```
+    /// use log::Level;
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info")
+    ///     .level_label(Level::Warn, "WARNING")
+    ///     .init();
+    /// ```
+    pub fn level_label(&mut self, level: Level, label: &str) -> &mut Self {
+        self.labels.0[level as usize - 1] = Some(label.to_string());
+        self
+    }
+
+    /// Whether to write levels in lowercase, as in `info` and `warn`, in the pretty, plain and
+    /// compact layouts.
+    pub fn lowercase_levels(&mut self, lowercase: bool) -> &mut Self {
+        self.options.lowercase_levels = lowercase;
+        self
+    }
+
+    /// Prefixes every line of the pretty, plain and compact layouts with the given name, to tell
+    /// processes apart when several of them share a terminal, as under foreman or overmind.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// // Lines look like ` ingestd INFO  ingestd::queue > drained`.
+    /// Builder::with("info").app_name("ingestd").init();
+    /// ```
+    pub fn app_name(&mut self, name: &str) -> &mut Self {
+        self.app_name = Some(name.to_string());
+        self
+    }
+
+    /// Sets the strings written between the columns of the pretty, plain and compact layouts:
+    /// `column` between the timestamp, level, target and other columns, a space by default,
+    /// and `message` before the message, ` > ` by default.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// // Lines look like ` INFO | my_app — started`.
+    /// Builder::with("info").separators(" | ", " — ").init();
+    /// ```
+    pub fn separators(&mut self, column: &str, message: &str) -> &mut Self {
+        self.separators = Separators {
+            column: column.to_string(),
+            message: message.to_string(),
+        };
+        self
+    }
+
+    /// Shows records of `module` and its submodules under a shorter name in the target column
+    /// of the pretty, plain and compact layouts.
+    ///
+    /// Filters and routes still apply to the original target.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info")
+    ///     .alias("my_app::server::http::handlers", "http")
+    ///     .init();
+    ///
+    /// // Shown as ` INFO  http::users > listed`.
+    /// log::info!(target: "my_app::server::http::handlers::users", "listed");
+    /// ```
+    pub fn alias(&mut self, module: &str, alias: &str) -> &mut Self {
+        self.aliases.push((module.to_string(), alias.to_string()));
+        self
+    }
+
+    /// Hides `prefix` from targets in the target column of the pretty, plain and compact
+    /// layouts, reclaiming horizontal space in deep module trees.
+    ///
+    /// Targets are shown whole when equal to the prefix, and [aliases](Builder::alias) take
+    /// precedence over stripped prefixes.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info").strip_prefix("my_company::my_app::").init();
+    ///
+    /// // Shown as ` INFO  db::pool > connected`.
+    /// log::info!(target: "my_company::my_app::db::pool", "connected");
+    /// ```
+    pub fn strip_prefix(&mut self, prefix: &str) -> &mut Self {
+        self.strip_prefixes.push(prefix.to_string());
+        self
+    }
+
+    /// Whether to prefix each record with a timestamp.
+    pub fn timed(&mut self, timed: bool) -> &mut Self {
+        self.timed = timed;
+        self
+    }
+
+    /// Sets the sink records are written to, instead of the standard error.
+    ///
+    /// Any `Write` implementation can be used, be it a pipe, a network stream, or an in-memory
+    /// buffer. Colors are only emitted into custom writers when requested explicitly with
+    /// [`write_style`](Builder::write_style).
+    ///
+    /// Each record is rendered in full before being handed to the writer in a single
+    /// `write_all` call, under a lock, so records logged from several threads at once never
+    /// interleave.
+    ///
+    /// The destination can also be chosen at run time with the `RUST_LOG_TARGET` environment
+    /// variable, set to `stderr`, `stdout`, `file:` followed by the path of a file records are
+    /// appended to, or `null` to drop them. It overrides this method and the others setting the
+    /// destination, when the logger is installed with [`try_init`](Builder::try_init) or
+    /// [`swap`].
+    pub fn writer(&mut self, writer: Box<dyn Write + Send>) -> &mut Self {
+        self.destination = Some(Destination::Writer(writer));
+        self
+    }
+
+    /// Sets the file records are written to, instead of the standard error.
+    ///
+    /// Unlike a file passed to [`writer`](Builder::writer), it is synced to disk after the
+    /// records selected with [`sync_on`](Builder::sync_on).
+    pub fn file(&mut self, file: std::fs::File) -> &mut Self {
+        self.destination = Some(Destination::File(file));
+        self
+    }
+
+    /// Writes records to the standard error above the progress bars of an `indicatif`
+    /// [`MultiProgress`](indicatif::MultiProgress), which are cleared while a record is written
+    /// and drawn again below it, so records don't shred the bars of command line tools.
+    ///
+    /// Bars have to be added to `bars` to be cleared, a single `ProgressBar` with
+    /// `MultiProgress::add`.
+    ///
+    /// ```
+    /// use indicatif::{MultiProgress, ProgressBar};
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// let bars = MultiProgress::new();
+    /// Builder::with("info").progress_bars(bars.clone()).init();
+    ///
+    /// let bar = bars.add(ProgressBar::new(3));
+    /// for file in ["a.txt", "b.txt", "c.txt"] {
+    ///     log::info!("downloading {}", file);
+    ///     bar.inc(1);
+    /// }
+    /// bar.finish();
+    /// ```
+    #[cfg(feature = "indicatif")]
+    pub fn progress_bars(&mut self, bars: indicatif::MultiProgress) -> &mut Self {
+        self.destination = Some(Destination::Progress(bars));
+        self
+    }
+
+    /// Syncs the sinks after every record at `level` or above, so the records which matter most
+    /// when a program crashes survive a power loss too, while less severe records stay fast.
+    ///
+    /// Files set with [`file`](Builder::file) or written by [`Output::file`](crate::Output::file)
+    /// are synced to disk. Other sinks are flushed, unless they implement
+    /// [`Sink::sync`] themselves.
+    ///
+    /// ```
+    /// use log::Level;
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// let file = std::fs::File::create(std::env::temp_dir().join("app.log")).unwrap();
+    /// Builder::with("info").file(file).sync_on(Level::Error).init();
+    ///
+    /// log::error!("on disk before the program goes on");
+    /// ```
+    pub fn sync_on(&mut self, level: Level) -> &mut Self {
+        self.sync_on = Some(level);
+        self
+    }
+
+    /// Sets how records are handed to the standard error or the custom writers: one write per
+    /// record by default, optionally followed by a flush, or in blocks, which saves system calls
+    /// in chatty programs at the cost of records showing up later.
+    ///
+    /// Blocks are written at least every `max_delay` by a background thread, and by
+    /// [`flush`](log::Log::flush). Sinks added with [`sink`](Builder::sink) do their own
+    /// buffering.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use pretty_flexible_env_logger::{Buffering, Builder};
+    ///
+    /// Builder::with("info")
+    ///     .buffering(Buffering::Block {
+    ///         max_delay: Duration::from_millis(100),
+    ///     })
+    ///     .init();
+    /// ```
+    pub fn buffering(&mut self, buffering: Buffering) -> &mut Self {
+        self.buffering = buffering;
+        self
+    }
+
+    /// Sends records to a log collector listening on TCP at `addr`, given as `host:port`.
+    ///
+    /// See the [`net`](crate::net) module for how connection failures are handled.
+    #[cfg(feature = "net")]
+    pub fn write_to_tcp(&mut self, addr: &str) -> &mut Self {
+        self.writer(Box::new(crate::net::TcpWriter::new(addr)))
+    }
+
+    /// Sends records to a log collector listening on TCP at `addr`, over TLS.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the platform TLS implementation cannot be initialized.
+    #[cfg(feature = "tls")]
+    pub fn write_to_tls(&mut self, addr: &str) -> std::io::Result<&mut Self> {
+        let writer = crate::net::TcpWriter::tls(addr)?;
+        Ok(self.writer(Box::new(writer)))
+    }
+
+    /// Sends records to a log collector listening on UDP at `addr`, one datagram per record.
+    #[cfg(feature = "net")]
+    pub fn write_to_udp(&mut self, addr: &str) -> &mut Self {
+        self.writer(Box::new(crate::net::UdpWriter::new(addr)))
+    }
+
+    /// Sends records to a local collector listening on the Unix stream socket at `path`.
+    ///
+    /// See the [`net`](crate::net) module for how connection failures are handled.
+    #[cfg(all(unix, feature = "net"))]
+    pub fn write_to_unix<P: AsRef<std::path::Path>>(&mut self, path: P) -> &mut Self {
+        self.writer(Box::new(crate::net::UnixWriter::new(path)))
+    }
+
+    /// Sets the [`Sink`] records are delivered to, instead of the standard error.
+    ///
+    /// Unlike [`writer`](Builder::writer), the sink receives the records themselves and is
+    /// free to render them however its backend expects.
+    pub fn sink(&mut self, sink: Box<dyn Sink>) -> &mut Self {
+        self.destination = Some(Destination::Sink(sink));
+        self
+    }
+
+    /// Sends records whose target starts with `prefix` to a dedicated sink.
+    ///
+    /// Records not matched by any route go to the sink set with [`writer`](Builder::writer) or
+    /// [`sink`](Builder::sink), or to the standard error. When several prefixes match, the
+    /// longest one wins.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// let audit = std::fs::File::create(std::env::temp_dir().join("audit.log")).unwrap();
+    /// Builder::with("info").route("audit::", Box::new(audit)).init();
+    ///
+    /// log::info!(target: "audit::login", "user logged in");
+    /// log::info!("this one goes to stderr");
+    /// ```
+    pub fn route(&mut self, prefix: &str, writer: Box<dyn Write + Send>) -> &mut Self {
+        self.routes
+            .push((prefix.to_string(), Destination::Writer(writer)));
+        self
+    }
+
+    /// Sends records whose target starts with `prefix` to a dedicated [`Sink`].
+    ///
+    /// See [`route`](Builder::route) for how routes are matched.
+    pub fn route_sink(&mut self, prefix: &str, sink: Box<dyn Sink>) -> &mut Self {
+        self.routes
+            .push((prefix.to_string(), Destination::Sink(sink)));
+        self
+    }
+
+    /// Adds a [`Sink`] receiving every record in addition to the main destination.
+    ///
+    /// Additional sinks are not affected by routes, and are free to drop records they are
+    /// not interested in.
+    pub fn add_sink(&mut self, sink: Box<dyn Sink>) -> &mut Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Hands every record over to another logger as well, such as one extracting metrics, in
+    /// addition to the main destination.
+    ///
+    /// The logger only receives the records passing the filters of this one, after layers and
+    /// masks have been applied, and may drop some of them according to its own `enabled`.
+    ///
+    /// ```
+    /// use log::{Log, Metadata, Record};
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// struct ErrorCounter;
+    ///
+    /// impl Log for ErrorCounter {
+    ///     fn enabled(&self, metadata: &Metadata) -> bool {
+    ///         metadata.level() == log::Level::Error
+    ///     }
+    ///
+    ///     fn log(&self, record: &Record) {
+    ///         // metrics::counter!("log_errors", "target" => record.target().to_string()).increment(1);
+    ///     }
+    ///
+    ///     fn flush(&self) {}
+    /// }
+    ///
+    /// Builder::with("info").also_log_to(Box::new(ErrorCounter)).init();
+    /// ```
+    pub fn also_log_to(&mut self, logger: Box<dyn Log>) -> &mut Self {
+        self.sinks.push(Box::new(Forward(logger)));
+        self
+    }
+
+    /// Keeps the most recent records in a [`RingBuffer`], dumped when the program panics.
+    ///
+    /// The panic hook is only installed by [`try_init`](Builder::try_init) and
+    /// [`init`](Builder::init).
+    pub fn dump_on_panic(&mut self, ring: RingBuffer) -> &mut Self {
+        self.ring = Some(ring);
+        self
+    }
+
+    /// Logs panics as error records with the `panic` target, so they reach the configured
+    /// destinations and sinks rather than only stderr.
+    ///
+    /// The record holds the panicking thread, the panic message and its location, and the
+    /// backtrace when `RUST_BACKTRACE` enables them, shortened as with
+    /// [`with_backtraces`](Builder::with_backtraces). The panic hook then runs the one set at
+    /// the time, which by default prints the panic to stderr, and is only installed by
+    /// [`try_init`](Builder::try_init) and [`init`](Builder::init).
+    ///
+    /// ```no_run
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info").log_panics(true).init();
+    ///
+    /// let config: Option<&str> = None;
+    /// config.expect("no config"); // ERROR panic > thread 'main' panicked at src/main.rs:6:8: no config
+    /// ```
+    pub fn log_panics(&mut self, enabled: bool) -> &mut Self {
+        self.log_panics = enabled;
+        self
+    }
+
+    /// Writes the events of the `tracing` crate through the logger too, by installing a
+    /// [`tracing::Subscriber`](crate::tracing::Subscriber) as the global default when the logger
+    /// is initialized, so events from dependencies using `tracing` are not lost.
+    ///
+    /// Nothing is installed if a global `tracing` subscriber was set already.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("info").capture_tracing(true).init();
+    ///
+    /// tracing::info!(user = "ana", "logged in"); // INFO  app > logged in user=ana
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn capture_tracing(&mut self, enabled: bool) -> &mut Self {
+        self.capture_tracing = enabled;
+        self
+    }
+
+    /// Holds back the records left out by the directives in a [`FlightRecorder`], to be written
+    /// when a warning or an error is logged.
+    pub fn flight_recorder(&mut self, recorder: FlightRecorder) -> &mut Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Drops every record once it passed the filters, instead of writing it anywhere.
+    ///
+    /// The logger is still installed and records are still filtered, so benchmarks and tests can
+    /// measure or silence logging without changing call sites. Writers, routes and sinks
+    /// configured on the builder are ignored.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// Builder::with("trace").disabled().init();
+    ///
+    /// log::info!("filtered, then dropped");
+    /// ```
+    pub fn disabled(&mut self) -> &mut Self {
+        self.disabled = true;
+        self
+    }
+
+    /// Writes records to the standard error through `eprint!`, like `env_logger` does in test
+    /// mode, so `cargo test` captures the records logged by each test and only shows those of
+    /// the failing ones.
+    ///
+    /// Only the default destination is affected, and records logged by threads the tests did
+    /// not spawn, such as the [`non_blocking`](Builder::non_blocking) writer, are not captured.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// // At the start of a test:
+    /// let _ = Builder::with("debug").is_test(true).try_init();
+    /// ```
+    pub fn is_test(&mut self, is_test: bool) -> &mut Self {
+        self.is_test = is_test;
+        self
+    }
+
+    /// Whether to print colors, following the same rules as `env_logger`.
+    pub fn write_style(&mut self, write_style: WriteStyle) -> &mut Self {
+        self.write_style = write_style;
+        self
+    }
+
+    /// Tries to initialize the global logger with the configured settings.
+    ///
+    /// # Errors
+    ///
+    /// This function fails to set the global logger if one has already been set.
+    pub fn try_init(&mut self) -> Result<(), SetLoggerError> {
+        self.env_target();
+        let config = self.log_config.then(|| self.describe());
+        let logger = Arc::new(self.build());
+
+        let max_level = logger.filter();
+        let ring = logger.ring.clone();
+        let r = log::set_boxed_logger(Box::new(Installed));
+
+        if r.is_ok() {
+            let _ = INSTALLED.set(RwLock::new(logger.clone()));
+            thread_level::set_max_level(max_level);
+            if let Some(config) = config {
+                logger.write_meta(Level::Info, format_args!("{}", config));
+            }
+            if self.log_panics {
+                crate::panic::install_hook();
+            }
+            // Installed last, so the last records are dumped before the panic is logged.
+            if let Some(ring) = ring {
+                ring.install_panic_hook();
+            }
+            #[cfg(feature = "tracing")]
+            if self.capture_tracing {
+                // Another subscriber may have been installed already, which then gets the events.
+                let _ = tracing_core::dispatcher::set_global_default(tracing_core::Dispatch::new(
+                    crate::tracing::Subscriber::new(),
+                ));
+            }
+            if let Some(banner) = self.banner {
+                log::info!(target: banner.name(), "starting {} {}", banner.name(), banner);
+            }
+        }
+
+        r
+    }
+
+    /// Initializes the global logger with the configured settings, returning a guard which
+    /// shuts it down when dropped, see [`shutdown`](crate::shutdown).
+    ///
+    /// Keeping the guard alive until the end of `main` makes sure the records queued by the
+    /// [`non_blocking`](Builder::non_blocking) writer or gathered with
+    /// [`buffering`](Builder::buffering) are written before the program exits.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::Builder;
+    ///
+    /// let _guard = Builder::with("info").non_blocking(1024).try_init_guarded().unwrap();
+    ///
+    /// log::info!("written by a background thread");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function fails to set the global logger if one has already been set.
+    pub fn try_init_guarded(&mut self) -> Result<LoggerGuard, SetLoggerError> {
+        self.try_init()?;
+        Ok(LoggerGuard { _private: () })
+    }
+
+    /// Initializes the global logger with the configured settings.
+    ///
+    /// # Panics
+    ///
+    /// This function fails to set the global logger if one has already been set.
+    pub fn init(&mut self) {
+        self.try_init()
+            .expect("Builder::init should not be called after logger initialized");
+    }
+
+    /// Builds a [`Logger`] without installing it, so it can be wrapped or composed by the caller.
+    ///
+    /// The custom writers, if any, are moved into the logger.
+    pub fn build(&mut self) -> Logger {
+        let mut format = Formatter::new(self.format, self.timed)
+            .options(self.options)
+            .aliases(self.aliases.clone())
+            .strip_prefixes(self.strip_prefixes.clone())
+            .labels(self.labels.clone())
+            .app_name(self.app_name.clone())
+            .separators(self.separators.clone());
+        if let Some(format_fn) = &self.format_fn {
+            format = format.custom(format_fn.clone());
+        }
+        let format = Arc::new(format);
+        let write_style = self.write_style;
+        let buffering = self.buffering;
+
+        let destination = self.destination.take();
+        let mut routes = std::mem::take(&mut self.routes);
+        let mut sinks = std::mem::take(&mut self.sinks);
+        if self.disabled {
+            routes.clear();
+            sinks.clear();
+        }
+
+        let sink: Box<dyn Sink> = match destination {
+            _ if self.disabled => Box::new(Discard),
+            Some(destination) => destination.into_sink(write_style, buffering, &format),
+            None => default_sink(write_style, buffering, self.is_test, &format),
+        };
+
+        let mut routes: Vec<_> = routes
+            .into_iter()
+            .map(|(prefix, destination)| {
+                let sink = destination.into_sink(write_style, buffering, &format);
+                (prefix, sink)
+            })
+            .collect();
+        routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        let mut rate_limits = std::mem::take(&mut self.rate_limits);
+        rate_limits.sort_by_key(|limit| std::cmp::Reverse(limit.target().len()));
+
+        let outputs = Arc::new(Outputs {
+            sink,
+            routes,
+            sinks,
+            sync_on: self.sync_on,
+        });
+        if let Buffering::Block { max_delay } = buffering {
+            sink::flush_periodically(Arc::downgrade(&outputs), max_delay);
+        }
+        let stats = Arc::new(Counters::default());
+        let loss_report = self
+            .loss_report
+            .map(|interval| Arc::new(LossReport::new(interval)));
+        let worker = self.non_blocking.and_then(|capacity| {
+            let losses = Losses {
+                stats: stats.clone(),
+                report: loss_report.clone(),
+            };
+            Worker::spawn(outputs.clone(), capacity, self.overflow, losses)
+        });
+
+        Logger {
+            filter: self.filter.build(),
+            outputs,
+            worker,
+            ring: self.ring.take().map(|ring| Arc::new(Ring::new(ring))),
+            recorder: self.recorder.take().map(Recorder::new),
+            max_message_len: self.max_message_len,
+            sequence: self.sequence.then(|| AtomicU64::new(1)),
+            backtraces: self.backtraces,
+            layers: std::mem::take(&mut self.layers),
+            filter_fns: std::mem::take(&mut self.filter_fns),
+            quiet: self.quiet,
+            on_error: std::mem::take(&mut self.on_error),
+            masks: self.masks.clone(),
+            rate_limits,
+            dedup: self.dedup.then(Dedup::default),
+            samplers: self.sample_rates.map(|rate| rate.map(Sampler::new)),
+            stats,
+            summary: self.summary,
+            loss_report,
+            summarized: AtomicBool::new(false),
+            #[cfg(feature = "redact")]
+            redactions: std::mem::take(&mut self.redactions),
+        }
+    }
+}
+
+/// A closure deciding whether records are written, on top of the directives.
+type FilterFn = dyn Fn(&Metadata) -> bool + Send + Sync;
+
+/// A callback receiving records as they are written.
+type RecordFn = dyn Fn(&Record) + Send + Sync;
+
+/// Reads a boolean toggle from an environment variable, `None` if unset or not a boolean.
+fn env_flag(name: &str) -> Option<bool> {
+    match std::env::var(name).ok()?.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Warns about the configuration on the standard error, which may be a closed pipe.
+fn warn(args: std::fmt::Arguments) {
+    let _ = writeln!(io::stderr(), "warning: {}", args);
+}
+
+/// The sink used when none was configured: the standard error, or the browser console.
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+fn default_sink(
+    write_style: WriteStyle,
+    buffering: Buffering,
+    is_test: bool,
+    format: &Arc<Formatter>,
+) -> Box<dyn Sink> {
+    use crate::sink::Stream;
+    use crate::writer::Writer;
+
+    let writer = if is_test {
+        Writer::test(write_style)
+    } else {
+        Writer::stderr(write_style)
+    };
+    Box::new(Stream::new(writer, format.clone(), buffering))
+}
+
+/// The sink used when none was configured: the standard error, or the browser console.
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+fn default_sink(_: WriteStyle, _: Buffering, _: bool, _: &Arc<Formatter>) -> Box<dyn Sink> {
+    Box::new(crate::wasm::Console)
+}
+
+/// The logger built by [`Builder`].
+pub struct Logger {
+    filter: filter::Filter,
+    outputs: Arc<Outputs>,
+    worker: Option<Worker>,
+    ring: Option<Arc<Ring>>,
+    recorder: Option<Recorder>,
+    max_message_len: Option<usize>,
+    sequence: Option<AtomicU64>,
+    backtraces: bool,
+    layers: Vec<Box<Layer>>,
+    filter_fns: Vec<Box<FilterFn>>,
+    quiet: bool,
+    on_error: Vec<Box<RecordFn>>,
+    masks: Masks,
+    #[cfg(feature = "redact")]
+    redactions: Redactions,
+    rate_limits: Vec<RateLimit>,
+    dedup: Option<Dedup>,
+    samplers: [Option<Sampler>; 5],
+    stats: Arc<Counters>,
+    summary: bool,
+    summarized: AtomicBool,
+    loss_report: Option<Arc<LossReport>>,
+}
+
+/// The logger installed by [`Builder::try_init`], replaced by [`swap`].
+static INSTALLED: OnceLock<RwLock<Arc<Logger>>> = OnceLock::new();
+
+/// Returns the logger installed by this crate, if any.
+pub(crate) fn installed() -> Option<Arc<Logger>> {
+    let logger = INSTALLED.get()?;
+    Some(logger.read().unwrap_or_else(|e| e.into_inner()).clone())
+}
+
+/// The global logger set by [`Builder::try_init`], handing records over to the logger
+/// currently [`installed`].
+struct Installed;
+
+impl Log for Installed {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        installed().is_some_and(|logger| logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(logger) = installed() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(logger) = installed() {
+            logger.flush();
+        }
+    }
+}
+
+/// Replaces the logger installed by this crate with the one configured by `builder`, or
+/// installs it if there is none yet, so long-running programs can change their filters,
+/// formats and sinks without restarting.
+///
+/// The previous logger is shut down once the records being written through it are, see
+/// [`shutdown`]. The panic hooks and the `tracing` subscriber installed along with the first
+/// logger are kept, as well as the panic hook of its [`RingBuffer`].
+///
+/// ```
+/// use pretty_flexible_env_logger::{Builder, Format};
+///
+/// pretty_flexible_env_logger::init_with("info");
+/// log::debug!("not written");
+///
+/// pretty_flexible_env_logger::swap(Builder::with("debug").format(Format::Json)).unwrap();
+/// log::debug!("written as JSON");
+/// ```
+///
+/// # Errors
+///
+/// This function fails to set the global logger if none was installed by this crate, and
+/// another one has already been set.
+pub fn swap(builder: &mut Builder) -> Result<(), SetLoggerError> {
+    let Some(installed) = INSTALLED.get() else {
+        return builder.try_init();
+    };
+    builder.env_target();
+    let config = builder.log_config.then(|| builder.describe());
+    let logger = Arc::new(builder.build());
+    let max_level = logger.filter();
+    let previous = std::mem::replace(
+        &mut *installed.write().unwrap_or_else(|e| e.into_inner()),
+        logger.clone(),
+    );
+    // Kept raised for the threads filtered on their own.
+    thread_level::set_max_level(max_level);
+    if let Some(config) = config {
+        logger.write_meta(Level::Info, format_args!("{}", config));
+    }
+    // Shut down once the threads writing through it let go of it.
+    drop(previous);
+    Ok(())
+}
+
+/// Flushes the logger installed by this crate, writing the run summary first if enabled with
+/// [`Builder::summary_on_shutdown`], and stops its background writer if
+/// [`non_blocking`](Builder::non_blocking). Records logged afterwards are written directly.
+///
+/// The global logger is never dropped, so programs wanting the summary call this once before
+/// exiting.
+///
+/// ```
+/// pretty_flexible_env_logger::Builder::with("info")
+///     .summary_on_shutdown(true)
+///     .init();
+///
+/// log::warn!("disk almost full");
+///
+/// // Writes `1 warning, 0 errors during this run`.
+/// pretty_flexible_env_logger::shutdown();
+/// ```
+pub fn shutdown() {
+    if let Some(logger) = installed() {
+        logger.shutdown();
+    }
+}
+
+/// Writes out everything the logger installed by this crate holds on to: records queued for
+/// the [`non_blocking`](Builder::non_blocking) writer, blocks gathered with
+/// [`buffering`](Builder::buffering), pending summaries, and the buffers of every sink.
+///
+/// Unlike [`shutdown`], the logger keeps running afterwards, so this can be called before
+/// forking, exec-ing or aborting, to make sure no record is lost.
+///
+/// ```
+/// pretty_flexible_env_logger::init_with("info");
+///
+/// log::error!("unrecoverable state, aborting");
+/// pretty_flexible_env_logger::flush();
+/// ```
+pub fn flush() {
+    if let Some(logger) = installed() {
+        logger.flush();
+    }
+}
+
+/// Flushes and shuts the global logger down when dropped, see [`Builder::try_init_guarded`] and
+/// [`try_init_with_guarded`](crate::try_init_with_guarded).
+#[must_use = "the logger is shut down when the guard is dropped"]
+pub struct LoggerGuard {
+    _private: (),
+}
+
+impl Drop for LoggerGuard {
+    fn drop(&mut self) {
+        shutdown();
+    }
+}
+
+impl Logger {
+    /// Returns the maximum `LevelFilter` that this logger instance is configured to output,
+    /// or to keep in its ring buffer or flight recorder.
+    pub fn filter(&self) -> LevelFilter {
+        let ring = self
+            .ring
+            .as_ref()
+            .map_or(LevelFilter::Off, |ring| ring.level());
+        let recorder = self
+            .recorder
+            .as_ref()
+            .map_or(LevelFilter::Off, |recorder| recorder.level());
+        let mut directives = self.filter.filter();
+        if self.quiet {
+            directives = directives.min(LevelFilter::Warn);
+        }
+        directives.max(ring).max(recorder)
+    }
+
+    /// Writes the run summary if enabled and not written yet, and flushes the sinks.
+    fn shutdown(&self) {
+        if self.summary && !self.summarized.swap(true, Ordering::Relaxed) {
+            self.write_meta(Level::Info, format_args!("{}", self.stats().summary()));
+        }
+        self.flush();
+        if let Some(worker) = &self.worker {
+            worker.join();
+        }
+    }
+
+    /// Returns the number of records which went through this logger so far.
+    pub fn stats(&self) -> LogStats {
+        self.stats.snapshot()
+    }
+
+    /// Checks if this record matches the configured filter.
+    pub fn matches(&self, record: &Record) -> bool {
+        let enabled = thread_level::matches(record).unwrap_or_else(|| self.filter.matches(record));
+        enabled && self.accepts(record.metadata())
+    }
+
+    /// Checks the quiet switch and the filter closures, on top of the directives.
+    fn accepts(&self, metadata: &Metadata) -> bool {
+        (!self.quiet || metadata.level() <= Level::Warn)
+            && self.filter_fns.iter().all(|filter| filter(metadata))
+    }
+
+    /// Returns the message to write instead of the original one, if it has to change.
+    fn rewrite(&self, record: &Record) -> Option<String> {
+        let scope = crate::scope::current();
+        if scope.is_none() && self.max_message_len.is_none() {
+            return None;
+        }
+
+        let mut message = record.args().to_string();
+        if let Some(max_len) = self.max_message_len {
+            if message.len() > max_len {
+                let len = message.len();
+                let mut end = max_len;
+                while !message.is_char_boundary(end) {
+                    end -= 1;
+                }
+                message.truncate(end);
+                message.push_str(&format!("… ({} bytes)", len));
+            }
+        }
+        if let Some(scope) = scope {
+            message = format!("[{}] {}", scope, message);
+        }
+        Some(message)
+    }
+
+    /// Checks whether the record is written despite the rate limits and deduplication.
+    fn admit(&self, record: &Record) -> bool {
+        self.admit_limited(record) && self.admit_repeated(record)
+    }
+
+    /// Checks the record against the rate limit of its target, writing a summary of the records
+    /// suppressed in the previous window when a new one starts.
+    fn admit_limited(&self, record: &Record) -> bool {
+        let Some(limit) = self
+            .rate_limits
+            .iter()
+            .find(|limit| record.target().starts_with(limit.target()))
+        else {
+            return true;
+        };
+        let (admitted, suppressed) = limit.admit();
+        if let Some(suppressed) = suppressed {
+            self.write_suppressed(limit, suppressed);
+        }
+        admitted
+    }
+
+    /// Writes a warning with the number of records of a target suppressed by its rate limit.
+    fn write_suppressed(&self, limit: &RateLimit, suppressed: Suppressed) {
+        self.deliver(
+            &Record::builder()
+                .level(Level::Warn)
+                .target(limit.target())
+                .args(format_args!(
+                    "suppressed {} records of {} over the last {}",
+                    suppressed.count,
+                    limit.target(),
+                    humantime::format_duration(Duration::from_secs(
+                        suppressed.elapsed.as_secs().max(1)
+                    ))
+                ))
+                .build(),
+        );
+    }
+
+    /// Checks whether the record repeats the previous one, writing a summary of the previous
+    /// record when a run of identical records ends.
+    fn admit_repeated(&self, record: &Record) -> bool {
+        let Some(dedup) = &self.dedup else {
+            return true;
+        };
+        let (admitted, repeated) = dedup.admit(record);
+        if let Some(repeated) = repeated {
+            self.write_repeated(repeated);
+        }
+        admitted
+    }
+
+    /// Writes a warning with the number of records discarded since the last one, if the report
+    /// interval elapsed or `force` is set.
+    fn report_losses(&self, force: bool) {
+        let Some(report) = &self.loss_report else {
+            return;
+        };
+        if let Some((sampled, overflowed, elapsed)) = report.due(force) {
+            let cause = match (sampled, overflowed) {
+                (_, 0) => "by sampling".to_string(),
+                (0, _) => "because the writer queue was full".to_string(),
+                _ => format!(
+                    "({} by sampling, {} because the writer queue was full)",
+                    sampled, overflowed
+                ),
+            };
+            self.write_meta(
+                Level::Warn,
+                format_args!(
+                    "dropped {} records {} over the last {}",
+                    sampled + overflowed,
+                    cause,
+                    humantime::format_duration(Duration::from_secs(elapsed.as_secs().max(1)))
+                ),
+            );
+        }
+    }
+
+    /// Writes a record about the logger itself, under the name of the program, whatever the
+    /// filters.
+    fn write_meta(&self, level: Level, args: std::fmt::Arguments) {
+        worker::blocking(|| {
+            self.deliver(
+                &Record::builder()
+                    .level(level)
+                    .target(&app_name())
+                    .args(args)
+                    .build(),
+            )
+        });
+    }
+
+    /// Writes the summary of a run of identical records.
+    fn write_repeated(&self, repeated: Repeated) {
+        worker::blocking(|| {
+            self.deliver(
+                &Record::builder()
+                    .level(repeated.level)
+                    .target(&repeated.target)
+                    .args(format_args!(
+                        "last message repeated {} times",
+                        repeated.count
+                    ))
+                    .build(),
+            )
+        });
+    }
+
+    /// Writes the record, stamped with its sequence number if enabled.
+    fn stamp(&self, record: &Record) {
+        match &self.sequence {
+            Some(next) => {
+                let kvs = Stamped {
+                    key: "seq",
+                    value: next.fetch_add(1, Ordering::Relaxed),
+                    source: record.key_values(),
+                };
+                self.emit(&record.to_builder().key_values(&kvs).build());
+            }
+            None => self.emit(record),
+        }
+    }
+
+    /// Writes a record made up by the logger itself, going through the layers, masks and
+    /// redactions like the logged ones.
+    fn deliver(&self, record: &Record) {
+        self.rewritten(record, |record| self.stamp(record));
+    }
+
+    /// Calls `f` with the record as changed by the layers, masks and redactions.
+    ///
+    /// The diagnostic context is made part of the key-values of the record when it has to be
+    /// masked or redacted, and left out of the record otherwise.
+    fn rewritten(&self, record: &Record, f: impl FnOnce(&Record)) {
+        #[cfg(feature = "redact")]
+        let redacts = !self.redactions.is_empty();
+        #[cfg(not(feature = "redact"))]
+        let redacts = false;
+        if redacts || self.masks.matches_context() {
+            let context = kv::context();
+            if !context.is_empty() {
+                let kvs = kv::WithContext {
+                    context: &context,
+                    source: record.key_values(),
+                };
+                let record = record.to_builder().key_values(&kvs).build();
+                return kv::without_context(|| self.rewrite_view(&record, f));
+            }
+        }
+        self.rewrite_view(record, f)
+    }
+
+    fn rewrite_view(&self, record: &Record, f: impl FnOnce(&Record)) {
+        if !self.rewrites(record) {
+            return f(record);
+        }
+        let mut view = RecordView::new(record);
+        for layer in &self.layers {
+            layer(&mut view);
+        }
+        self.masks.mask(&mut view, record);
+        #[cfg(feature = "redact")]
+        self.redactions.redact(&mut view, record);
+        view.apply(record, f);
+    }
+
+    /// Whether the record goes through a [`RecordView`] before being written.
+    fn rewrites(&self, record: &Record) -> bool {
+        #[cfg(feature = "redact")]
+        if !self.redactions.is_empty() {
+            return true;
+        }
+        !self.layers.is_empty() || self.masks.matches(record)
+    }
+
+    fn emit(&self, record: &Record) {
+        match self.rewrite(record) {
+            Some(message) => self.write(
+                &record
+                    .to_builder()
+                    .args(format_args!("{}", message))
+                    .build(),
+            ),
+            None => self.write(record),
+        }
+    }
+
+    fn write(&self, record: &Record) {
+        match &self.worker {
+            Some(worker) if worker.send(record) => {}
+            _ => {
+                self.stats.emitted(record);
+                self.outputs.write(record);
+            }
+        }
+    }
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if sink::is_inside() {
+            return false;
+        }
+        let enabled =
+            thread_level::enabled(metadata).unwrap_or_else(|| self.filter.enabled(metadata));
+        let recorded = self
+            .recorder
+            .as_ref()
+            .is_some_and(|recorder| recorder.enabled(metadata));
+        ((enabled || recorded) && self.accepts(metadata))
+            || self
+                .ring
+                .as_ref()
+                .is_some_and(|ring| ring.enabled(metadata))
+            || capture::active()
+    }
+
+    fn log(&self, record: &Record) {
+        // Records logged while writing others would feed the sinks with their own records, or
+        // wait for room in the queue they are meant to empty.
+        if sink::is_inside() {
+            return;
+        }
+        // Rewritten first, so secrets reach neither the ring buffer, the captures, the flight
+        // recorder nor the callbacks.
+        self.rewritten(record, |record| self.process(record));
+    }
+
+    fn flush(&self) {
+        self.report_losses(true);
+        for limit in &self.rate_limits {
+            if let Some(suppressed) = limit.take() {
+                self.write_suppressed(limit, suppressed);
+            }
+        }
+        if let Some(repeated) = self.dedup.as_ref().and_then(Dedup::take) {
+            self.write_repeated(repeated);
+        }
+        match &self.worker {
+            Some(worker) if worker.flush() => {}
+            _ => self.outputs.flush(),
+        }
+    }
+}
+
+impl Logger {
+    /// Holds, drops or writes a record changed by the layers, masks and redactions.
+    fn process(&self, record: &Record) {
+        capture::push(record);
+        if let Some(ring) = &self.ring {
+            if ring.enabled(record.metadata()) {
+                ring.push(record);
+            }
+        }
+
+        if !self.matches(record) {
+            match &self.recorder {
+                Some(recorder)
+                    if recorder.enabled(record.metadata()) && self.accepts(record.metadata()) =>
+                {
+                    // Held records are counted once written, or once they make room.
+                    if let Some(level) = recorder.push(record) {
+                        self.stats.filtered(level);
+                    }
+                }
+                _ => self.stats.filtered(record.level()),
+            }
+            return;
+        }
+
+        self.report_losses(false);
+        let sampled = match &self.samplers[record.level() as usize - 1] {
+            Some(sampler) => match sampler.admit() {
+                Some(count) => Some(count),
+                None => {
+                    if let Some(report) = &self.loss_report {
+                        report.sampled();
+                    }
+                    return self.stats.dropped(record.level());
+                }
+            },
+            None => None,
+        };
+        if !self.admit(record) {
+            return self.stats.dropped(record.level());
+        }
+        if let Some(recorder) = &self.recorder {
+            for held in recorder.take(record) {
+                held.replay(|held| self.stamp(held));
+            }
+        }
+        let backtrace =
+            (self.backtraces && record.level() == Level::Error && !backtrace::attached(record))
+                .then(backtrace::capture);
+        let (kvs, with_backtrace);
+        let record = match &backtrace {
+            Some(backtrace) => {
+                kvs = WithBacktrace {
+                    backtrace,
+                    source: record.key_values(),
+                };
+                with_backtrace = record.to_builder().key_values(&kvs).build();
+                &with_backtrace
+            }
+            None => record,
+        };
+        match sampled {
+            Some(count) => {
+                let kvs = Stamped {
+                    key: "sampled",
+                    value: count,
+                    source: record.key_values(),
+                };
+                self.stamp(&record.to_builder().key_values(&kvs).build());
+            }
+            None => self.stamp(record),
+        }
+        if record.level() == Level::Error {
+            for callback in &self.on_error {
+                callback(record);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, OnceLock, Weak};
+
+    use super::*;
+    use crate::fmt::Column;
+
+    /// A writer whose output the tests read back.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+
+        /// The messages of the records written in the logfmt format.
+        fn messages(&self) -> Vec<String> {
+            self.text()
+                .lines()
+                .map(|line| line.split_once(" msg=").unwrap().1.to_string())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn masks_before_callbacks_and_captures() {
+        let out = Shared::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let logger = {
+            let seen = seen.clone();
+            Builder::with("info")
+                .writer(Box::new(out.clone()))
+                .on_error(move |record| seen.lock().unwrap().extend(kv::collect(record)))
+                .build()
+        };
+        let capture = crate::capture();
+
+        let kvs = [("token", "hunter2")];
+        logger.log(
+            &Record::builder()
+                .level(Level::Error)
+                .target("auth")
+                .args(format_args!("login failed"))
+                .key_values(&kvs)
+                .build(),
+        );
+
+        assert!(out.text().contains("token=***"), "{}", out.text());
+        assert_eq!(*seen.lock().unwrap(), [("token".into(), "***".into())]);
+        let records = capture.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].key_values(), [("token".into(), "***".into())]);
+    }
+
+    #[test]
+    fn masks_context_and_key_values_in_every_format() {
+        for format in [Format::Pretty, Format::Json, Format::Logfmt] {
+            let out = Shared::default();
+            let logger = Builder::with("info")
+                .format(format)
+                .writer(Box::new(out.clone()))
+                .build();
+            let _session = crate::mdc::scope("session_token", "s3cr3t");
+            let kvs = [("api_key", "k3y"), ("user", "ana")];
+            logger.log(
+                &Record::builder()
+                    .level(Level::Info)
+                    .target("auth")
+                    .args(format_args!("logged in"))
+                    .key_values(&kvs)
+                    .build(),
+            );
+
+            let text = out.text();
+            assert!(
+                !text.contains("s3cr3t") && !text.contains("k3y"),
+                "{}",
+                text
+            );
+            assert!(
+                text.contains("session_token") && text.contains("ana"),
+                "{}",
+                text
+            );
+        }
+    }
+
+    /// A sink logging a record of its own for each record it writes.
+    struct Echo {
+        logger: Arc<OnceLock<Weak<Logger>>>,
+        out: Shared,
+    }
+
+    impl Sink for Echo {
+        fn write(&self, record: &Record) -> io::Result<()> {
+            writeln!(self.out.clone(), "{}", record.args())?;
+            if let Some(logger) = self.logger.get().and_then(Weak::upgrade) {
+                logger.log(
+                    &Record::builder()
+                        .level(Level::Warn)
+                        .args(format_args!("echo"))
+                        .build(),
+                );
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn drops_records_logged_by_sinks() {
+        let out = Shared::default();
+        let cell = Arc::new(OnceLock::new());
+        let logger = Arc::new(
+            Builder::with("info")
+                .sink(Box::new(Echo {
+                    logger: cell.clone(),
+                    out: out.clone(),
+                }))
+                .non_blocking(1)
+                .overflow(Overflow::Block)
+                .build(),
+        );
+        let _ = cell.set(Arc::downgrade(&logger));
+        for i in 0..3 {
+            logger.log(
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("record {}", i))
+                    .build(),
+            );
+        }
+        logger.flush();
+
+        assert_eq!(out.text(), "record 0\nrecord 1\nrecord 2\n");
+    }
+
+    #[test]
+    fn writes_the_csv_header_to_each_file() {
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("csv-{}-{}.csv", name, std::process::id()));
+        std::fs::write(path("main"), "level,message\nINFO,before\n").unwrap();
+        let open = |name: &str| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path(name))
+                .unwrap()
+        };
+        let logger = Builder::with("info")
+            .format(Format::Csv(&[Column::Level, Column::Message]))
+            .file(open("main"))
+            .route("jobs", Box::new(open("jobs")))
+            .route("db", Box::new(open("db")))
+            .build();
+        for (target, message) in [("db", "query"), ("app", "after"), ("jobs", "run")] {
+            logger.log(
+                &Record::builder()
+                    .level(Level::Info)
+                    .target(target)
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+        logger.flush();
+
+        let read = |name: &str| {
+            let text = std::fs::read_to_string(path(name)).unwrap();
+            std::fs::remove_file(path(name)).unwrap();
+            text
+        };
+        assert_eq!(read("main"), "level,message\nINFO,before\nINFO,after\n");
+        assert_eq!(read("db"), "level,message\nINFO,query\n");
+        assert_eq!(read("jobs"), "level,message\nINFO,run\n");
+    }
+
+    #[test]
+    fn truncates_long_messages_at_char_boundaries() {
+        let out = Shared::default();
+        let logger = Builder::with("info")
+            .format(Format::Logfmt)
+            .writer(Box::new(out.clone()))
+            .max_message_len(4)
+            .build();
+        for message in ["hééh", "hé", "abcdef"] {
+            logger.log(
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+
+        assert_eq!(
+            out.messages(),
+            ["\"hé… (6 bytes)\"", "hé", "\"abcd… (6 bytes)\""]
+        );
+    }
+
+    #[test]
+    fn collapses_repeated_records() {
+        let out = Shared::default();
+        let logger = Builder::with("info")
+            .format(Format::Logfmt)
+            .writer(Box::new(out.clone()))
+            .dedup(true)
+            .build();
+        for message in [
+            "retrying",
+            "retrying",
+            "retrying",
+            "connected",
+            "idle",
+            "idle",
+        ] {
+            logger.log(
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+        logger.flush();
+
+        assert_eq!(
+            out.messages(),
+            [
+                "retrying",
+                "\"last message repeated 2 times\"",
+                "connected",
+                "idle",
+                "\"last message repeated 1 times\"",
+            ]
+        );
+    }
+}
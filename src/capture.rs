@@ -0,0 +1,179 @@
+//! Capturing the records logged by a thread, for tests to assert on.
+
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use log::{Level, LevelFilter, Record};
+
+use crate::kv;
+use crate::thread_level::{self, Raised};
+
+thread_local! {
+    /// The captures started by the current thread.
+    static CAPTURES: RefCell<Vec<Weak<Records>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Captures every record the current thread logs from now on, whatever the directives, until
+/// the returned handle and its clones are dropped, which also lowers the global maximum level
+/// back.
+///
+/// A logger following `RUST_LOG` is installed first if there is none, in
+/// [test mode](crate::Builder::is_test), and records are still written as usual. Tests run on
+/// threads of their own, so each of them only sees its own records; records logged by the
+/// threads they spawn are not captured.
+///
+/// ```
+/// use log::Level;
+///
+/// let logs = pretty_flexible_env_logger::capture();
+///
+/// log::warn!("request timeout after 30s");
+///
+/// assert!(logs.contains(Level::Warn, "timeout"));
+/// assert_eq!(logs.records().len(), 1);
+/// logs.clear();
+/// assert!(logs.records().is_empty());
+/// ```
+pub fn capture() -> Capture {
+    crate::__init_test();
+    let records = Arc::new(Records {
+        records: Mutex::new(Vec::new()),
+        _raised: thread_level::raise(LevelFilter::Trace),
+    });
+    CAPTURES.with(|captures| {
+        let mut captures = captures.borrow_mut();
+        captures.retain(|capture| capture.strong_count() > 0);
+        captures.push(Arc::downgrade(&records));
+    });
+    Capture { records }
+}
+
+/// A handle on the records captured by [`capture`].
+#[derive(Clone)]
+pub struct Capture {
+    records: Arc<Records>,
+}
+
+/// The records of a capture, keeping the global maximum level raised while captured.
+struct Records {
+    records: Mutex<Vec<CapturedRecord>>,
+    _raised: Raised,
+}
+
+impl Capture {
+    /// Returns the records captured so far, oldest first.
+    pub fn records(&self) -> Vec<CapturedRecord> {
+        self.lock().clone()
+    }
+
+    /// Whether a record with this level and a message containing `text` was captured.
+    pub fn contains(&self, level: Level, text: &str) -> bool {
+        self.lock()
+            .iter()
+            .any(|record| record.level == level && record.message.contains(text))
+    }
+
+    /// Forgets the records captured so far.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<CapturedRecord>> {
+        self.records
+            .records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A record captured by [`capture`], as it was logged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedRecord {
+    level: Level,
+    target: String,
+    message: String,
+    key_values: Vec<(String, String)>,
+}
+
+impl CapturedRecord {
+    /// The level of the record.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// The target of the record.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// The message of the record.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The diagnostic context and key-values of the record, rendered as text.
+    pub fn key_values(&self) -> &[(String, String)] {
+        &self.key_values
+    }
+}
+
+/// Whether the current thread captures its records.
+pub(crate) fn active() -> bool {
+    CAPTURES
+        .try_with(|captures| {
+            captures
+                .borrow()
+                .iter()
+                .any(|capture| capture.strong_count() > 0)
+        })
+        .unwrap_or(false)
+}
+
+/// Adds the record to the captures of the current thread, forgetting those whose handles were
+/// dropped.
+pub(crate) fn push(record: &Record) {
+    if !active() {
+        return;
+    }
+    // Rendered first, as rendering the message may log other records.
+    let captured = CapturedRecord {
+        level: record.level(),
+        target: record.target().to_string(),
+        message: record.args().to_string(),
+        key_values: kv::collect(record),
+    };
+    let _ = CAPTURES.try_with(|captures| {
+        let mut captures = captures.borrow_mut();
+        captures.retain(|capture| capture.strong_count() > 0);
+        for capture in captures.iter().filter_map(Weak::upgrade) {
+            capture
+                .records
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(captured.clone());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_when_the_handles_are_dropped() {
+        let logs = capture();
+        let clone = logs.clone();
+        assert!(active());
+        assert_eq!(log::max_level(), LevelFilter::Trace);
+
+        log::trace!(target: "capture", "first");
+        drop(logs);
+        log::trace!(target: "capture", "second");
+        assert!(clone.contains(Level::Trace, "first"));
+        assert!(clone.contains(Level::Trace, "second"));
+        assert_eq!(clone.records()[1].target(), "capture");
+
+        drop(clone);
+        assert!(!active());
+    }
+}
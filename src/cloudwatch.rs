@@ -0,0 +1,416 @@
+//! Pushing records to [AWS CloudWatch Logs][cloudwatch].
+//!
+//! Records are batched on a background thread and sent with `PutLogEvents` calls, signed with
+//! AWS Signature Version 4. Failed batches are retried, so short throttling or network hiccups
+//! do not lose records.
+//!
+//! Credentials are taken from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+//! `AWS_SESSION_TOKEN` environment variables, as set up on Lambda, or from the ECS container
+//! credentials endpoint. The region comes from `AWS_REGION` or `AWS_DEFAULT_REGION`.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::cloudwatch::CloudWatch;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let cloudwatch = CloudWatch::new("/ecs/ingestd", "worker-1");
+//! Builder::with("info").add_sink(Box::new(cloudwatch)).init();
+//! ```
+//!
+//! [cloudwatch]: https://aws.amazon.com/cloudwatch/
+
+use std::fmt::Write as _;
+use std::io;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use log::Record;
+use sha2::{Digest, Sha256};
+
+use crate::batch::{BatchConfig, Batcher};
+use crate::http;
+use crate::json::{find_str, write_str};
+use crate::sink::Sink;
+
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+/// The most events a single `PutLogEvents` call accepts.
+const MAX_BATCH_SIZE: usize = 10_000;
+/// The most bytes a single `PutLogEvents` call accepts, counting the messages and a fixed
+/// overhead for each event.
+const MAX_CALL_BYTES: usize = 1_048_576;
+const EVENT_OVERHEAD: usize = 26;
+/// The longest message an event can hold, in bytes.
+const MAX_MESSAGE_BYTES: usize = 256 * 1024 - EVENT_OVERHEAD;
+/// Container credentials are refreshed well before they expire.
+const CREDENTIALS_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// A [`Sink`] pushing batches of records to CloudWatch Logs.
+pub struct CloudWatch {
+    group: String,
+    stream: String,
+    region: Option<String>,
+    credentials: Option<Credentials>,
+    batch: BatchConfig,
+    batcher: OnceLock<io::Result<Batcher<Event>>>,
+}
+
+#[derive(Clone)]
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+struct Event {
+    timestamp: u128,
+    message: String,
+}
+
+impl CloudWatch {
+    /// Creates a sink pushing to the given log group and stream.
+    ///
+    /// The stream is created on first use if it does not exist yet.
+    pub fn new(group: &str, stream: &str) -> CloudWatch {
+        CloudWatch {
+            group: group.to_string(),
+            stream: stream.to_string(),
+            region: None,
+            credentials: None,
+            batch: BatchConfig {
+                size: 500,
+                wait: Duration::from_secs(2),
+            },
+            batcher: OnceLock::new(),
+        }
+    }
+
+    /// Sets the region, instead of taking it from the environment.
+    pub fn region(mut self, region: &str) -> CloudWatch {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    /// Sets static credentials, instead of looking them up in the environment.
+    pub fn credentials(
+        mut self,
+        access_key_id: &str,
+        secret_access_key: &str,
+        session_token: Option<&str>,
+    ) -> CloudWatch {
+        self.credentials = Some(Credentials {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: session_token.map(str::to_string),
+        });
+        self
+    }
+
+    /// Sets the number of records sent in a single call, 500 by default and 10000 at most.
+    pub fn batch_size(mut self, batch_size: usize) -> CloudWatch {
+        self.batch.size = batch_size.clamp(1, MAX_BATCH_SIZE);
+        self
+    }
+
+    /// Sets for how long records are held before being sent in an incomplete batch, two seconds
+    /// by default.
+    pub fn batch_wait(mut self, batch_wait: Duration) -> CloudWatch {
+        self.batch.wait = batch_wait;
+        self
+    }
+
+    /// Starts the background thread on first use.
+    fn batcher(&self) -> io::Result<&Batcher<Event>> {
+        self.batcher
+            .get_or_init(|| {
+                let region = self
+                    .region
+                    .clone()
+                    .or_else(|| std::env::var("AWS_REGION").ok())
+                    .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no AWS region configured")
+                    })?;
+                let mut client = Client {
+                    endpoint: format!("logs.{}.amazonaws.com", region),
+                    region,
+                    group: self.group.clone(),
+                    stream: self.stream.clone(),
+                    static_credentials: self.credentials.clone(),
+                    cached_credentials: None,
+                    stream_created: false,
+                };
+                Batcher::spawn("cloudwatch", self.batch, move |events| {
+                    client.put_log_events(events)
+                })
+            })
+            .as_ref()
+            .map_err(|e| io::Error::new(e.kind(), e.to_string()))
+    }
+}
+
+impl Sink for CloudWatch {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let timestamp = crate::fmt::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        let mut message = format!("{} {} > {}", record.level(), record.target(), record.args());
+        if message.len() > MAX_MESSAGE_BYTES {
+            let mut end = MAX_MESSAGE_BYTES;
+            while !message.is_char_boundary(end) {
+                end -= 1;
+            }
+            message.truncate(end);
+        }
+        self.batcher()?.send(Event { timestamp, message })
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.batcher()?.flush();
+        Ok(())
+    }
+}
+
+struct Client {
+    endpoint: String,
+    region: String,
+    group: String,
+    stream: String,
+    static_credentials: Option<Credentials>,
+    cached_credentials: Option<(Credentials, Instant)>,
+    stream_created: bool,
+}
+
+impl Client {
+    fn put_log_events(&mut self, events: &[Event]) -> io::Result<()> {
+        let credentials = self.credentials()?;
+
+        if !self.stream_created {
+            let mut body = String::from("{\"logGroupName\":");
+            write_str(&mut body, &self.group);
+            body.push_str(",\"logStreamName\":");
+            write_str(&mut body, &self.stream);
+            body.push('}');
+            match self.call(&credentials, "CreateLogStream", &body) {
+                Ok(()) => self.stream_created = true,
+                Err(e) if e.to_string().contains("ResourceAlreadyExistsException") => {
+                    self.stream_created = true;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Events have to be in chronological order within a call.
+        let mut sorted: Vec<&Event> = events.iter().collect();
+        sorted.sort_by_key(|event| event.timestamp);
+
+        // A batch failing partway is retried whole, repeating the events of the calls that went
+        // through, which is better than losing the others.
+        for events in calls(&sorted) {
+            self.put(&credentials, events)?;
+        }
+        Ok(())
+    }
+
+    fn put(&self, credentials: &Credentials, events: &[&Event]) -> io::Result<()> {
+        let mut body = String::from("{\"logGroupName\":");
+        write_str(&mut body, &self.group);
+        body.push_str(",\"logStreamName\":");
+        write_str(&mut body, &self.stream);
+        body.push_str(",\"logEvents\":[");
+        for (i, event) in events.iter().enumerate() {
+            if i > 0 {
+                body.push(',');
+            }
+            let _ = write!(body, "{{\"timestamp\":{},\"message\":", event.timestamp);
+            write_str(&mut body, &event.message);
+            body.push('}');
+        }
+        body.push_str("]}");
+
+        self.call(credentials, "PutLogEvents", &body)
+    }
+
+    fn credentials(&mut self) -> io::Result<Credentials> {
+        if let Some(credentials) = &self.static_credentials {
+            return Ok(credentials.clone());
+        }
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+        if let Some((credentials, fetched)) = &self.cached_credentials {
+            if fetched.elapsed() < CREDENTIALS_LIFETIME {
+                return Ok(credentials.clone());
+            }
+        }
+        let credentials = container_credentials()?;
+        self.cached_credentials = Some((credentials.clone(), Instant::now()));
+        Ok(credentials)
+    }
+
+    fn call(&self, credentials: &Credentials, action: &str, body: &str) -> io::Result<()> {
+        let target = format!("Logs_20140328.{}", action);
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let amz_date: String = now.chars().filter(|c| *c != '-' && *c != ':').collect();
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/logs/aws4_request", date, self.region);
+
+        let mut signed = vec![
+            ("content-type", CONTENT_TYPE),
+            ("host", &self.endpoint),
+            ("x-amz-date", &amz_date),
+        ];
+        if let Some(token) = &credentials.session_token {
+            signed.push(("x-amz-security-token", token));
+        }
+        signed.push(("x-amz-target", &target));
+
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = signed
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            hex(&Sha256::digest(body.as_bytes()))
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = format!("AWS4{}", credentials.secret_access_key);
+        let key = hmac(key.as_bytes(), date);
+        let key = hmac(&key, &self.region);
+        let key = hmac(&key, "logs");
+        let key = hmac(&key, "aws4_request");
+        let signature = hex(&hmac(&key, &string_to_sign));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        );
+        let mut headers = vec![
+            ("X-Amz-Date", amz_date.as_str()),
+            ("X-Amz-Target", target.as_str()),
+            ("Authorization", authorization.as_str()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("X-Amz-Security-Token", token));
+        }
+
+        http::post(
+            &format!("https://{}/", self.endpoint),
+            CONTENT_TYPE,
+            &headers,
+            body.as_bytes(),
+        )
+    }
+}
+
+/// Splits events into runs small enough for a single `PutLogEvents` call each.
+fn calls<'a>(events: &'a [&'a Event]) -> Vec<&'a [&'a Event]> {
+    let mut calls = Vec::new();
+    let (mut start, mut bytes) = (0, 0);
+    for (i, event) in events.iter().enumerate() {
+        let size = event.message.len() + EVENT_OVERHEAD;
+        if i > start && (i - start == MAX_BATCH_SIZE || bytes + size > MAX_CALL_BYTES) {
+            calls.push(&events[start..i]);
+            (start, bytes) = (i, 0);
+        }
+        bytes += size;
+    }
+    if start < events.len() {
+        calls.push(&events[start..]);
+    }
+    calls
+}
+
+/// Fetches credentials from the ECS container credentials endpoint.
+fn container_credentials() -> io::Result<Credentials> {
+    let url = match (
+        std::env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI"),
+        std::env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI"),
+    ) {
+        (Ok(relative), _) => format!("http://169.254.170.2{}", relative),
+        (_, Ok(full)) => full,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no AWS credentials found",
+            ))
+        }
+    };
+    let token = std::env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN").ok();
+    let headers: Vec<(&str, &str)> = token
+        .as_deref()
+        .map(|token| ("Authorization", token))
+        .into_iter()
+        .collect();
+
+    let body = http::get(&url, &headers)?;
+    let field = |name| {
+        find_str(&body, name).map(str::to_string).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "malformed credentials response")
+        })
+    };
+    Ok(Credentials {
+        access_key_id: field("AccessKeyId")?,
+        secret_access_key: field("SecretAccessKey")?,
+        session_token: field("Token").ok(),
+    })
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(size: usize) -> Event {
+        Event {
+            timestamp: 0,
+            message: "x".repeat(size),
+        }
+    }
+
+    #[test]
+    fn splits_batches_under_the_call_limits() {
+        let large: Vec<Event> = (0..9).map(|_| event(MAX_MESSAGE_BYTES)).collect();
+        let large: Vec<&Event> = large.iter().collect();
+        let sizes: Vec<usize> = calls(&large).iter().map(|call| call.len()).collect();
+        assert_eq!(sizes, [4, 4, 1]);
+
+        let small: Vec<Event> = (0..MAX_BATCH_SIZE + 1).map(|_| event(10)).collect();
+        let small: Vec<&Event> = small.iter().collect();
+        let sizes: Vec<usize> = calls(&small).iter().map(|call| call.len()).collect();
+        assert_eq!(sizes, [MAX_BATCH_SIZE, 1]);
+
+        assert!(calls(&[]).is_empty());
+    }
+}
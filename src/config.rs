@@ -0,0 +1,403 @@
+//! Configuring the logger from a TOML file, with several appenders.
+//!
+//! Deployments can then change where records go and how they look without rebuilding the
+//! program. Each appender writes records to the console, a file or syslog, in its own format,
+//! and keeps those passing its own level and directives:
+//!
+//! ```toml
+//! # The directives of the logger, or the name of the environment variable holding them,
+//! # `RUST_LOG` by default.
+//! directives = "debug"
+//! timed = true
+//!
+//! [appenders.console]
+//! kind = "console"
+//! level = "info"
+//!
+//! [appenders.file]
+//! kind = "file"
+//! path = "/var/log/ingestd.jsonl"
+//! format = "json"
+//! filter = "debug,hyper=warn"
+//!
+//! [appenders.syslog]
+//! kind = "syslog"
+//! facility = "daemon"
+//! level = "warn"
+//! ```
+//!
+//! Records must pass the directives of the logger before reaching an appender, so they should
+//! be as verbose as the most verbose appender. Formats are given by their name, as in the
+//! `RUST_LOG_FORMAT` environment variable, and files are appended to unless `append = false`.
+//! The CSV and CEF formats, which take their columns and device from code, are not available.
+//! Syslog appenders need the `syslog` feature, and send to the local daemon unless a `server`
+//! is given as `host:port`, over UDP.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! Builder::from_config("/etc/ingestd/logging.toml")
+//!     .expect("invalid logging config")
+//!     .init();
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use env_logger::filter::{self, Filter};
+use log::{LevelFilter, Record};
+use serde::Deserialize;
+
+use crate::fmt::Format;
+use crate::output::Output;
+use crate::sink::Sink;
+use crate::Builder;
+
+/// The error returned when a configuration cannot be read or applied.
+#[derive(Debug)]
+pub struct ConfigError {
+    message: String,
+}
+
+impl ConfigError {
+    fn new(message: String) -> ConfigError {
+        ConfigError { message }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid logging config: {}", self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    directives: Option<String>,
+    #[serde(default)]
+    timed: bool,
+    #[serde(default)]
+    appenders: BTreeMap<String, Appender>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Appender {
+    kind: Kind,
+    format: Option<String>,
+    level: Option<String>,
+    filter: Option<String>,
+    timed: Option<bool>,
+    path: Option<PathBuf>,
+    append: Option<bool>,
+    facility: Option<String>,
+    server: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    Console,
+    File,
+    Syslog,
+}
+
+impl Builder {
+    /// Creates a builder from the TOML configuration file at `path`, see the
+    /// [`config`](crate::config) module.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file cannot be read, is not valid, or one of its appenders
+    /// cannot be opened.
+    pub fn from_config<P: AsRef<Path>>(path: P) -> Result<Builder, ConfigError> {
+        let path = path.as_ref();
+        let config = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::new(format!("cannot read {}: {}", path.display(), e)))?;
+        Builder::from_config_str(&config)
+    }
+
+    /// Creates a builder from a TOML configuration, see the [`config`](crate::config) module.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the configuration is not valid, or one of its appenders cannot be
+    /// opened.
+    pub fn from_config_str(config: &str) -> Result<Builder, ConfigError> {
+        let config: Config = toml::from_str(config).map_err(|e| ConfigError::new(e.to_string()))?;
+
+        let mut builder = Builder::with(config.directives.as_deref().unwrap_or("RUST_LOG"));
+        builder.timed(config.timed);
+        for (i, (name, appender)) in config.appenders.into_iter().enumerate() {
+            let sink = appender
+                .build(config.timed)
+                .map_err(|e| ConfigError::new(format!("appender `{}`: {}", name, e)))?;
+            match i {
+                0 => builder.sink(sink),
+                _ => builder.add_sink(sink),
+            };
+        }
+        Ok(builder)
+    }
+}
+
+impl Appender {
+    fn build(self, timed: bool) -> Result<Box<dyn Sink>, String> {
+        let level = match &self.level {
+            Some(level) => level
+                .parse()
+                .map_err(|_| format!("unknown level `{}`", level))?,
+            None => LevelFilter::Trace,
+        };
+        let filter = self
+            .filter
+            .as_deref()
+            .map(|directives| filter::Builder::new().parse(directives).build());
+        let format = match &self.format {
+            Some(name) => Format::from_name(name).ok_or(format!("unknown format `{}`", name))?,
+            None => Format::default(),
+        };
+        let timed = self.timed.unwrap_or(timed);
+
+        let sink: Box<dyn Sink> = match self.kind {
+            Kind::Console => Box::new(Output::stderr().format(format).timed(timed)),
+            Kind::File => {
+                let path = self.path.ok_or("missing `path`")?;
+                let file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(self.append.unwrap_or(true))
+                    .truncate(!self.append.unwrap_or(true))
+                    .open(&path)
+                    .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+                Box::new(Output::file(file).format(format).timed(timed))
+            }
+            Kind::Syslog => syslog(self.facility.as_deref(), self.server.as_deref())
+                .map_err(|e| format!("cannot connect to syslog: {}", e))?,
+        };
+        Ok(Box::new(Filtered {
+            level,
+            filter,
+            sink,
+        }))
+    }
+}
+
+#[cfg(feature = "syslog")]
+fn syslog(facility: Option<&str>, server: Option<&str>) -> io::Result<Box<dyn Sink>> {
+    use crate::syslog::{Facility, Syslog};
+
+    let facility = match facility.unwrap_or("user").to_lowercase().as_str() {
+        "kern" => Facility::Kern,
+        "user" => Facility::User,
+        "mail" => Facility::Mail,
+        "daemon" => Facility::Daemon,
+        "auth" => Facility::Auth,
+        "syslog" => Facility::Syslog,
+        "lpr" => Facility::Lpr,
+        "news" => Facility::News,
+        "uucp" => Facility::Uucp,
+        "cron" => Facility::Cron,
+        "authpriv" => Facility::AuthPriv,
+        "ftp" => Facility::Ftp,
+        "local0" => Facility::Local0,
+        "local1" => Facility::Local1,
+        "local2" => Facility::Local2,
+        "local3" => Facility::Local3,
+        "local4" => Facility::Local4,
+        "local5" => Facility::Local5,
+        "local6" => Facility::Local6,
+        "local7" => Facility::Local7,
+        facility => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown facility `{}`", facility),
+            ))
+        }
+    };
+    Ok(Box::new(match server {
+        Some(server) => Syslog::udp(server, facility)?,
+        #[cfg(unix)]
+        None => Syslog::local(facility)?,
+        #[cfg(not(unix))]
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a `server` is required on this platform",
+            ))
+        }
+    }))
+}
+
+#[cfg(not(feature = "syslog"))]
+fn syslog(_: Option<&str>, _: Option<&str>) -> io::Result<Box<dyn Sink>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the `syslog` feature is not enabled",
+    ))
+}
+
+/// A sink receiving the records passing the level and directives of an appender.
+struct Filtered {
+    level: LevelFilter,
+    filter: Option<Filter>,
+    sink: Box<dyn Sink>,
+}
+
+impl Sink for Filtered {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        if record.level() > self.level || self.filter.as_ref().is_some_and(|f| !f.matches(record)) {
+            return Ok(());
+        }
+        self.sink.write(record)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.sink.flush()
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.sink.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, Log};
+
+    use super::*;
+
+    /// A file in the temporary directory, removed once read.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let path = std::env::temp_dir().join(format!("config-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            TempFile(path)
+        }
+
+        /// The lines of the file, without their timestamp.
+        fn read(self) -> Vec<String> {
+            let text = std::fs::read_to_string(&self.0).unwrap();
+            std::fs::remove_file(&self.0).unwrap();
+            text.lines()
+                .map(|line| line.split_once(' ').unwrap().1.to_string())
+                .collect()
+        }
+    }
+
+    fn error(config: &str) -> String {
+        match Builder::from_config_str(config) {
+            Ok(_) => panic!("accepted {}", config),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn log(builder: Result<Builder, ConfigError>, records: &[(Level, &str, &str)]) {
+        let logger = builder.unwrap().build();
+        for (level, target, message) in records {
+            logger.log(
+                &Record::builder()
+                    .level(*level)
+                    .target(target)
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+        logger.flush();
+    }
+
+    #[test]
+    fn filters_records_by_appender() {
+        let (warnings, db) = (TempFile::new("warnings"), TempFile::new("db"));
+        let config = format!(
+            r#"
+            directives = "debug"
+
+            [appenders.warnings]
+            kind = "file"
+            path = '{}'
+            format = "logfmt"
+            level = "warn"
+
+            [appenders.db]
+            kind = "file"
+            path = '{}'
+            format = "logfmt"
+            filter = "off,db=info"
+            "#,
+            warnings.0.display(),
+            db.0.display()
+        );
+        log(
+            Builder::from_config_str(&config),
+            &[
+                (Level::Info, "db", "connected"),
+                (Level::Warn, "app", "slow"),
+                (Level::Debug, "db", "query"),
+                (Level::Error, "db", "lost"),
+            ],
+        );
+
+        assert_eq!(
+            warnings.read(),
+            [
+                "level=warn target=app msg=slow",
+                "level=error target=db msg=lost"
+            ]
+        );
+        assert_eq!(
+            db.read(),
+            [
+                "level=info target=db msg=connected",
+                "level=error target=db msg=lost"
+            ]
+        );
+    }
+
+    #[test]
+    fn truncates_files_unless_appending() {
+        let file = TempFile::new("truncated");
+        std::fs::write(&file.0, "ts=0 level=info target=app msg=before\n").unwrap();
+        let config = format!(
+            "directives = \"info\"\n[appenders.file]\nkind = \"file\"\npath = '{}'\nformat = \"logfmt\"\nappend = false",
+            file.0.display()
+        );
+        log(
+            Builder::from_config_str(&config),
+            &[(Level::Error, "app", "after")],
+        );
+
+        assert_eq!(file.read(), ["level=error target=app msg=after"]);
+    }
+
+    #[test]
+    fn rejects_invalid_appenders() {
+        let unknown_kind = error("[appenders.a]\nkind = \"socket\"");
+        assert!(
+            unknown_kind.contains("unknown variant `socket`"),
+            "{}",
+            unknown_kind
+        );
+        assert_eq!(
+            error("[appenders.a]\nkind = \"console\"\nformat = \"xml\""),
+            "invalid logging config: appender `a`: unknown format `xml`"
+        );
+        assert_eq!(
+            error("[appenders.a]\nkind = \"console\"\nlevel = \"loud\""),
+            "invalid logging config: appender `a`: unknown level `loud`"
+        );
+        assert_eq!(
+            error("[appenders.a]\nkind = \"file\""),
+            "invalid logging config: appender `a`: missing `path`"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Escaping of control characters in messages, so untrusted data cannot forge records or
+//! corrupt terminals.
+
+use std::fmt;
+
+use log::Record;
+
+/// Returns the message of the record with control characters escaped, or `None` if it has none.
+///
+/// Line feeds and tabs are kept, as multi-line messages are laid out by the formatter. Other
+/// control characters, including the escape character starting ANSI sequences, are written as
+/// `\r`, `\x1b` or `\u{85}`.
+pub(crate) fn escape_message(record: &Record) -> Option<String> {
+    /// Looks for control characters as the message is formatted, without allocating it.
+    struct Scan(bool);
+
+    impl fmt::Write for Scan {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 |= s.chars().any(needs_escape);
+            Ok(())
+        }
+    }
+
+    let mut scan = Scan(false);
+    let _ = fmt::write(&mut scan, *record.args());
+    if !scan.0 {
+        return None;
+    }
+
+    let message = record.args().to_string();
+    let mut out = String::with_capacity(message.len());
+    for c in message.chars() {
+        match c {
+            '\r' => out.push_str("\\r"),
+            c if needs_escape(c) && (c as u32) < 0x80 => {
+                out.push_str(&format!("\\x{:02x}", c as u32))
+            }
+            c if needs_escape(c) => out.push_str(&c.escape_unicode().to_string()),
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+fn needs_escape(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
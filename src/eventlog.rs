@@ -0,0 +1,238 @@
+//! Reporting of records to the Windows Event Log.
+//!
+//! Meant to be added next to the console output with [`Builder::add_sink`], so services
+//! installed as Windows services get their warnings and errors in the Event Viewer:
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::eventlog::EventLog;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let eventlog = EventLog::new("MyService").expect("cannot open the event log");
+//! Builder::with("info").add_sink(Box::new(eventlog)).init();
+//! ```
+//!
+//! [`Builder::add_sink`]: crate::Builder::add_sink
+
+use std::ffi::{c_void, OsStr};
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+
+use log::{Level, Record};
+
+use crate::sink::Sink;
+
+type Handle = *mut c_void;
+type HKey = *mut c_void;
+
+// Predefined keys are sign-extended on 64-bit targets, as in `(HKEY)(LONG)0x80000002`.
+const HKEY_LOCAL_MACHINE: HKey = 0x8000_0002_u32 as i32 as isize as HKey;
+const KEY_SET_VALUE: u32 = 0x0002;
+const REG_EXPAND_SZ: u32 = 2;
+const REG_DWORD: u32 = 4;
+
+const EVENTLOG_ERROR_TYPE: u16 = 0x0001;
+const EVENTLOG_WARNING_TYPE: u16 = 0x0002;
+const EVENTLOG_INFORMATION_TYPE: u16 = 0x0004;
+
+/// `EventCreate.exe` ships a message table whose entries 1 to 1000 simply print their argument.
+const MESSAGE_FILE: &str = "%SystemRoot%\\System32\\EventCreate.exe";
+const EVENT_ID: u32 = 1;
+
+#[link(name = "advapi32")]
+extern "system" {
+    fn RegisterEventSourceW(server_name: *const u16, source_name: *const u16) -> Handle;
+    fn DeregisterEventSource(event_log: Handle) -> i32;
+    fn ReportEventW(
+        event_log: Handle,
+        event_type: u16,
+        category: u16,
+        event_id: u32,
+        user_sid: *mut c_void,
+        num_strings: u16,
+        data_size: u32,
+        strings: *const *const u16,
+        raw_data: *mut c_void,
+    ) -> i32;
+    fn RegCreateKeyExW(
+        key: HKey,
+        sub_key: *const u16,
+        reserved: u32,
+        class: *const u16,
+        options: u32,
+        sam_desired: u32,
+        security_attributes: *mut c_void,
+        result: *mut HKey,
+        disposition: *mut u32,
+    ) -> i32;
+    fn RegSetValueExW(
+        key: HKey,
+        value_name: *const u16,
+        reserved: u32,
+        value_type: u32,
+        data: *const u8,
+        data_size: u32,
+    ) -> i32;
+    fn RegCloseKey(key: HKey) -> i32;
+}
+
+/// A [`Sink`] reporting records to the Windows Event Log.
+///
+/// Only warnings and errors are reported by default, see [`EventLog::min_level`].
+pub struct EventLog {
+    handle: Handle,
+    min_level: Level,
+}
+
+// SAFETY: event log handles can be used from any thread.
+unsafe impl Send for EventLog {}
+// SAFETY: `ReportEventW` is thread-safe.
+unsafe impl Sync for EventLog {}
+
+impl EventLog {
+    /// Opens the event log for the given source.
+    ///
+    /// Sources which were not [registered](EventLog::register) end up in the `Application`
+    /// log, with a note that the event description cannot be found.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the event log cannot be opened.
+    pub fn new(source: &str) -> io::Result<EventLog> {
+        let source = wide(source);
+        // SAFETY: `source` is a valid, null terminated wide string.
+        let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(EventLog {
+            handle,
+            min_level: Level::Warn,
+        })
+    }
+
+    /// Registers the event source in the `Application` log, so the Event Viewer displays
+    /// messages properly.
+    ///
+    /// This is typically done once, by the service installer, as it requires administrator
+    /// privileges.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the registry cannot be written to.
+    pub fn register(source: &str) -> io::Result<()> {
+        let sub_key = wide(&format!(
+            "SYSTEM\\CurrentControlSet\\Services\\EventLog\\Application\\{}",
+            source
+        ));
+        let mut key: HKey = ptr::null_mut();
+        // SAFETY: all pointers are either null or point to valid, live values.
+        let r = unsafe {
+            RegCreateKeyExW(
+                HKEY_LOCAL_MACHINE,
+                sub_key.as_ptr(),
+                0,
+                ptr::null(),
+                0,
+                KEY_SET_VALUE,
+                ptr::null_mut(),
+                &mut key,
+                ptr::null_mut(),
+            )
+        };
+        if r != 0 {
+            return Err(io::Error::from_raw_os_error(r));
+        }
+
+        let message_file = wide(MESSAGE_FILE);
+        let types_supported: u32 =
+            (EVENTLOG_ERROR_TYPE | EVENTLOG_WARNING_TYPE | EVENTLOG_INFORMATION_TYPE).into();
+        let result = set_value(
+            key,
+            "EventMessageFile",
+            REG_EXPAND_SZ,
+            message_file.as_ptr() as *const u8,
+            message_file.len() * 2,
+        )
+        .and_then(|_| {
+            set_value(
+                key,
+                "TypesSupported",
+                REG_DWORD,
+                &types_supported as *const u32 as *const u8,
+                4,
+            )
+        });
+        // SAFETY: the key was successfully opened above.
+        unsafe { RegCloseKey(key) };
+        result
+    }
+
+    /// Sets the minimum level of records reported to the event log.
+    pub fn min_level(mut self, level: Level) -> EventLog {
+        self.min_level = level;
+        self
+    }
+}
+
+impl Sink for EventLog {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        if record.level() > self.min_level {
+            return Ok(());
+        }
+        let event_type = match record.level() {
+            Level::Error => EVENTLOG_ERROR_TYPE,
+            Level::Warn => EVENTLOG_WARNING_TYPE,
+            _ => EVENTLOG_INFORMATION_TYPE,
+        };
+        let message = wide(&format!("{} > {}", record.target(), record.args()));
+        let strings = [message.as_ptr()];
+        // SAFETY: the handle is open for the lifetime of `self`, and `strings` holds one valid,
+        // null terminated wide string.
+        let r = unsafe {
+            ReportEventW(
+                self.handle,
+                event_type,
+                0,
+                EVENT_ID,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                ptr::null_mut(),
+            )
+        };
+        if r == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        // SAFETY: the handle was opened in `EventLog::new` and is not used afterwards.
+        unsafe { DeregisterEventSource(self.handle) };
+    }
+}
+
+fn set_value(
+    key: HKey,
+    name: &str,
+    value_type: u32,
+    data: *const u8,
+    size: usize,
+) -> io::Result<()> {
+    let name = wide(name);
+    // SAFETY: `data` points to `size` readable bytes, and `name` is null terminated.
+    let r = unsafe { RegSetValueExW(key, name.as_ptr(), 0, value_type, data, size as u32) };
+    if r != 0 {
+        return Err(io::Error::from_raw_os_error(r));
+    }
+    Ok(())
+}
+
+/// Converts a string to a null terminated UTF-16 string.
+fn wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+}
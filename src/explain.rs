@@ -0,0 +1,127 @@
+//! Reporting how a configuration value is understood, without installing a logger.
+
+use std::fmt;
+
+use log::LevelFilter;
+
+/// How a value passed to [`init_with`](crate::init_with) or [`Builder::with`](crate::Builder::with)
+/// is resolved and parsed into directives, as returned by [`explain`].
+///
+/// Its `Display` implementation lays the report out over several lines, for `--help` or
+/// `doctor` subcommands.
+#[derive(Clone, Debug)]
+pub struct ConfigReport {
+    source: String,
+    value: String,
+    directives: Vec<(Option<String>, LevelFilter)>,
+    filter: Option<String>,
+    errors: Vec<String>,
+}
+
+impl ConfigReport {
+    /// Where the directives are taken from, in words.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The directives string, as read from the environment variable or given inline.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The parsed directives, as a module, or `None` for all modules, and a level.
+    pub fn directives(&self) -> impl Iterator<Item = (Option<&str>, LevelFilter)> {
+        self.directives
+            .iter()
+            .map(|(module, level)| (module.as_deref(), *level))
+    }
+
+    /// The text messages have to contain to be written, given after a `/`.
+    pub fn filter(&self) -> Option<&str> {
+        self.filter.as_deref()
+    }
+
+    /// The parts of the directives which are ignored because they are invalid.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+}
+
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "source: {}", self.source)?;
+        writeln!(f, "value: `{}`", self.value)?;
+        if self.directives.is_empty() {
+            writeln!(f, "directives: none, only errors are written")?;
+        } else {
+            writeln!(f, "directives:")?;
+            for (module, level) in self.directives() {
+                let level = level.to_string().to_lowercase();
+                writeln!(f, "  {} = {}", module.unwrap_or("(all modules)"), level)?;
+            }
+        }
+        if let Some(filter) = &self.filter {
+            writeln!(f, "message filter: `{}`", filter)?;
+        }
+        for error in &self.errors {
+            writeln!(f, "ignored: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reports how a value passed to [`init_with`](crate::init_with) would be resolved and parsed,
+/// without installing a logger.
+///
+/// ```
+/// let report = pretty_flexible_env_logger::explain("info,hyper=warn");
+/// print!("{}", report);
+/// // source: inline directives
+/// // value: `info,hyper=warn`
+/// // directives:
+/// //   (all modules) = info
+/// //   hyper = warn
+///
+/// assert_eq!(report.directives().count(), 2);
+/// ```
+pub fn explain(environment_or_inline_value: &str) -> ConfigReport {
+    let value = crate::resolve(environment_or_inline_value);
+    let mut report = ConfigReport {
+        source: crate::describe_source(environment_or_inline_value),
+        value: value.clone(),
+        directives: Vec::new(),
+        filter: None,
+        errors: Vec::new(),
+    };
+
+    // Mirrors the parsing done by `env_logger`, which does not expose the parsed directives.
+    let mut parts = value.split('/');
+    let modules = parts.next().unwrap_or("");
+    report.filter = parts.next().map(str::to_string);
+    if parts.next().is_some() {
+        report.filter = None;
+        report.errors.push(format!("`{}`, too many `/`s", value));
+        return report;
+    }
+    for directive in modules.split(',').filter(|directive| !directive.is_empty()) {
+        let mut parts = directive.split('=');
+        let parsed = match (parts.next(), parts.next().map(str::trim), parts.next()) {
+            (Some(part), None, None) => match part.parse() {
+                Ok(level) => Some((None, level)),
+                Err(_) => Some((Some(part), LevelFilter::max())),
+            },
+            (Some(module), Some(""), None) => Some((Some(module), LevelFilter::max())),
+            (Some(module), Some(level), None) => {
+                level.parse().ok().map(|level| (Some(module), level))
+            }
+            _ => None,
+        };
+        match parsed {
+            Some((module, level)) => report.directives.push((module.map(str::to_string), level)),
+            None => report
+                .errors
+                .push(format!("`{}`, not a valid directive", directive)),
+        }
+    }
+    report
+}
@@ -0,0 +1,1280 @@
+//! Formatting of log records written to streams.
+//!
+//! The pretty layout mirrors the one used by [pretty_env_logger][], so output produced through
+//! the [`Builder`](crate::Builder) is indistinguishable from the one produced by the free
+//! `init*` functions.
+//!
+//! [pretty_env_logger]: https://docs.rs/pretty_env_logger
+
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use humantime::Rfc3339Timestamp;
+use log::{Level, Record};
+use termcolor::{Buffer, Color, ColorSpec, WriteColor};
+
+use crate::backtrace;
+use crate::build_info::BuildInfo;
+use crate::escape::escape_message;
+use crate::group;
+use crate::json;
+use crate::kv;
+use crate::process::hostname;
+use crate::report;
+use crate::writer::{self, Writer};
+
+/// The layout of records written to streams.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    /// Colored ` LEVEL target > message` lines, optionally prefixed with a timestamp.
+    ///
+    /// Key-values follow the message as dimmed `key=value` pairs. Errors attached with
+    /// `err:err = e` are followed by their causes, one per indented `caused by:` line.
+    #[default]
+    Pretty,
+    /// The pretty layout without colors, whatever the destination supports.
+    Plain,
+    /// Colored `L target > message` lines, with single-character levels (`E`, `W`, `I`, `D`
+    /// and `T`) and without padding, for space-constrained output.
+    Compact,
+    /// JSON lines with `timestamp`, `level`, `target` and `message` fields, plus the source
+    /// location when available.
+    ///
+    /// Key-values attached to records are added as fields of their own, in this format and the
+    /// Google Cloud one. Keys clashing with the fields of the format are left out.
+    Json,
+    /// JSON lines following Google Cloud's [structured logging][gcp] schema, so logs written
+    /// to the standard error on Cloud Run or GKE are parsed with correct severities.
+    ///
+    /// [gcp]: https://cloud.google.com/logging/docs/structured-logging
+    GoogleCloud,
+    /// JSON lines following Datadog's [standard attributes][datadog], so the agent picks up
+    /// levels and loggers without a custom pipeline. Key-values are nested under `attributes`.
+    ///
+    /// [datadog]: https://docs.datadoghq.com/logs/log_configuration/attributes_naming_convention/
+    Datadog,
+    /// [logfmt][logfmt] lines with `ts`, `level`, `target` and `msg` keys, parsed natively by
+    /// Heroku, Vector or Grafana.
+    ///
+    /// [logfmt]: https://brandur.org/logfmt
+    Logfmt,
+    /// ArcSight [Common Event Format][cef] lines, for feeding SIEM systems directly.
+    ///
+    /// The signature ID is the target and the name is the message, while the extension carries
+    /// the time (`rt`) and the process ID (`dvcpid`), followed by the diagnostic context and the
+    /// key-values of the record.
+    ///
+    /// [cef]: https://www.microfocus.com/documentation/arcsight/arcsight-smartconnectors/pdfdoc/common-event-format-v25/common-event-format-v25.pdf
+    Cef {
+        /// The device vendor, identifying the organization shipping the program.
+        vendor: &'static str,
+        /// The device product, identifying the program itself.
+        product: &'static str,
+        /// The device version, identifying the version of the program.
+        version: &'static str,
+    },
+    /// Comma-separated values with the given columns, preceded by a header row written along
+    /// with the first record unless appending to a file which is not empty, for opening logs
+    /// in spreadsheets or data tools.
+    ///
+    /// ```
+    /// use pretty_flexible_env_logger::{Builder, Column, Format};
+    ///
+    /// let columns = &[Column::Timestamp, Column::Level, Column::Message];
+    /// Builder::with("info").format(Format::Csv(columns)).init();
+    /// ```
+    Csv(&'static [Column]),
+}
+
+impl Format {
+    /// Looks up a layout by its name, as in the `RUST_LOG_FORMAT` environment variable.
+    pub(crate) fn from_name(name: &str) -> Option<Format> {
+        Some(match name.trim().to_lowercase().as_str() {
+            "pretty" => Format::Pretty,
+            "plain" => Format::Plain,
+            "compact" => Format::Compact,
+            "json" => Format::Json,
+            "logfmt" => Format::Logfmt,
+            "google_cloud" | "gcp" => Format::GoogleCloud,
+            "datadog" => Format::Datadog,
+            _ => return None,
+        })
+    }
+}
+
+/// A column of the [`Format::Csv`] format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Column {
+    /// The time the record was written, in RFC 3339 format.
+    Timestamp,
+    /// The level of the record.
+    Level,
+    /// The target of the record.
+    Target,
+    /// The message of the record.
+    Message,
+    /// The module path the record originates from.
+    ModulePath,
+    /// The source file the record originates from.
+    File,
+    /// The line in the source file the record originates from.
+    Line,
+}
+
+impl Column {
+    /// All columns, in declaration order.
+    pub const ALL: &'static [Column] = &[
+        Column::Timestamp,
+        Column::Level,
+        Column::Target,
+        Column::Message,
+        Column::ModulePath,
+        Column::File,
+        Column::Line,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Column::Timestamp => "timestamp",
+            Column::Level => "level",
+            Column::Target => "target",
+            Column::Message => "message",
+            Column::ModulePath => "module_path",
+            Column::File => "file",
+            Column::Line => "line",
+        }
+    }
+}
+
+/// Optional parts of the pretty, plain and compact layouts, and how messages are rendered.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Options {
+    /// Appends the version and profile of the program.
+    pub(crate) build_info: Option<BuildInfo>,
+    /// Appends the source file and line the record originates from.
+    pub(crate) source_location: bool,
+    /// Writes levels in lowercase.
+    pub(crate) lowercase_levels: bool,
+    /// Leaves the level and target of info records out.
+    pub(crate) clean_info: bool,
+    /// Leaves the target out.
+    pub(crate) hide_target: bool,
+    /// Prefixes lines with the name of the current thread.
+    pub(crate) thread: bool,
+    /// Escapes control characters in messages, or only when not writing to a terminal if `None`.
+    pub(crate) escape: Option<bool>,
+    /// Lays out continuation lines of messages.
+    pub(crate) multiline: Multiline,
+    /// Prefixes lines with the name of the machine.
+    pub(crate) hostname: bool,
+    /// Prefixes lines with the process ID.
+    pub(crate) pid: bool,
+    /// Pads the target column.
+    pub(crate) target_width: TargetWidth,
+    /// Wraps long messages at the width of the terminal.
+    pub(crate) wrap: bool,
+}
+
+/// How the target column of the pretty and plain layouts is padded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TargetWidth {
+    /// Targets are padded to the width of the longest one written so far, so the column grows
+    /// as new modules start logging.
+    #[default]
+    Auto,
+    /// Targets are padded to the given width, and longer ones are shortened to their last
+    /// characters, behind an ellipsis.
+    Fixed(usize),
+    /// Targets are padded like with `Auto`, but the column never grows past the given width.
+    /// Longer targets are written whole.
+    Max(usize),
+    /// Targets are not padded.
+    None,
+}
+
+/// How continuation lines of messages spanning several lines are laid out, in the pretty,
+/// plain and compact layouts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Multiline {
+    /// Continuation lines are written as they are, starting at the first column.
+    #[default]
+    Raw,
+    /// Continuation lines are aligned under the first line of the message.
+    Indent,
+    /// Continuation lines are prefixed with the level and target again, as if they were records
+    /// of their own.
+    Restamp,
+}
+
+/// Custom labels of levels, indexed from error to trace.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Labels(pub(crate) [Option<String>; 5]);
+
+/// The strings written between the columns of the pretty and compact layouts.
+#[derive(Clone, Debug)]
+pub(crate) struct Separators {
+    /// Between the timestamp, level, target and other columns.
+    pub(crate) column: String,
+    /// Before the message.
+    pub(crate) message: String,
+}
+
+impl Default for Separators {
+    fn default() -> Self {
+        Separators {
+            column: " ".to_string(),
+            message: " > ".to_string(),
+        }
+    }
+}
+
+/// A closure rendering records, set with [`Builder::format_fn`](crate::Builder::format_fn).
+pub(crate) type FormatFn = dyn Fn(&mut dyn WriteColor, &Record) -> io::Result<()> + Send + Sync;
+
+/// Renders records in the configured [`Format`].
+pub(crate) struct Formatter {
+    format: Format,
+    custom: Option<Arc<FormatFn>>,
+    timed: bool,
+    options: Options,
+    aliases: Vec<(String, String)>,
+    strip_prefixes: Vec<String>,
+    labels: Labels,
+    separators: Separators,
+    app_name: Option<String>,
+    max_target_width: AtomicUsize,
+}
+
+impl Formatter {
+    pub(crate) fn new(format: Format, timed: bool) -> Formatter {
+        Formatter {
+            format,
+            custom: None,
+            timed,
+            options: Options::default(),
+            aliases: Vec::new(),
+            strip_prefixes: Vec::new(),
+            labels: Labels::default(),
+            separators: Separators::default(),
+            app_name: None,
+            max_target_width: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn options(mut self, options: Options) -> Formatter {
+        self.options = options;
+        self
+    }
+
+    /// Shows targets starting with the given modules under shorter names, longest module first.
+    pub(crate) fn aliases(mut self, mut aliases: Vec<(String, String)>) -> Formatter {
+        aliases.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        self.aliases = aliases;
+        self
+    }
+
+    /// Replaces the text shown for some levels.
+    pub(crate) fn labels(mut self, labels: Labels) -> Formatter {
+        self.labels = labels;
+        self
+    }
+
+    pub(crate) fn app_name(mut self, app_name: Option<String>) -> Formatter {
+        self.app_name = app_name;
+        self
+    }
+
+    pub(crate) fn separators(mut self, separators: Separators) -> Formatter {
+        self.separators = separators;
+        self
+    }
+
+    /// Hides the given prefixes of targets, longest prefix first.
+    pub(crate) fn strip_prefixes(mut self, mut prefixes: Vec<String>) -> Formatter {
+        prefixes.sort_by_key(|prefix| std::cmp::Reverse(prefix.len()));
+        self.strip_prefixes = prefixes;
+        self
+    }
+
+    /// Renders a record and writes it out, escaping control characters in the message unless
+    /// configured otherwise or writing to a terminal.
+    pub(crate) fn print(&self, writer: &Writer, record: &Record) -> io::Result<()> {
+        writer.with_buffer(|buf| {
+            self.render_for(writer, buf, record)?;
+            writer.print(buf)
+        })
+    }
+
+    /// Renders a record into `buf` the way [`print`](Formatter::print) writes it to `writer`.
+    pub(crate) fn render_for(
+        &self,
+        writer: &Writer,
+        buf: &mut Buffer,
+        record: &Record,
+    ) -> io::Result<()> {
+        let header = matches!(self.format, Format::Csv(_)) && writer.take_header();
+        let escape = self.options.escape.unwrap_or_else(|| !writer.is_terminal());
+        let width = self.options.wrap.then(|| writer.width()).flatten();
+        match escape.then(|| escape_message(record)).flatten() {
+            Some(message) => self.render(
+                buf,
+                &record
+                    .to_builder()
+                    .args(format_args!("{}", message))
+                    .build(),
+                width,
+                header,
+            ),
+            None => self.render(buf, record, width, header),
+        }
+    }
+
+    /// Renders records with a closure, instead of the configured format.
+    pub(crate) fn custom(mut self, custom: Arc<FormatFn>) -> Formatter {
+        self.custom = Some(custom);
+        self
+    }
+
+    pub(crate) fn write(&self, buf: &mut Buffer, record: &Record) -> io::Result<()> {
+        self.render(buf, record, None, false)
+    }
+
+    /// Writes the record, wrapping messages of the pretty, plain and compact layouts at `width`
+    /// columns if given, and preceded by the header of the CSV format if `header` is set.
+    fn render(
+        &self,
+        buf: &mut Buffer,
+        record: &Record,
+        width: Option<usize>,
+        header: bool,
+    ) -> io::Result<()> {
+        if let Some(custom) = &self.custom {
+            return custom(buf, record);
+        }
+
+        match self.format {
+            Format::Pretty => self.write_pretty(buf, record, width),
+            Format::Plain => writer::with_plain_buffer(|plain| {
+                self.write_pretty(plain, record, width)?;
+                buf.write_all(plain.as_slice())
+            }),
+            Format::Compact => self.write_compact(buf, record, width),
+            Format::Json => write_json(buf, record),
+            Format::GoogleCloud => write_google_cloud(buf, record),
+            Format::Datadog => write_datadog(buf, record),
+            Format::Logfmt => write_logfmt(buf, record),
+            Format::Cef {
+                vendor,
+                product,
+                version,
+            } => write_cef(buf, record, [vendor, product, version]),
+            Format::Csv(columns) => self.write_csv(buf, record, columns, header),
+        }
+    }
+
+    fn write_pretty(
+        &self,
+        buf: &mut Buffer,
+        record: &Record,
+        width: Option<usize>,
+    ) -> io::Result<()> {
+        self.write_lines(buf, record, width, |buf| {
+            if self.is_clean(record) {
+                return self.write_clean_header(buf);
+            }
+            write!(buf, " ")?;
+            self.write_prefix(buf)?;
+
+            let (_, color) = level_style(record.level());
+            buf.set_color(ColorSpec::new().set_fg(Some(color)))?;
+            write!(buf, "{}", self.label(record.level()))?;
+            buf.reset()?;
+            write!(buf, "{}", self.separators.column)?;
+
+            if !self.options.hide_target {
+                let target = self.target(record.target());
+                let (target, width) = self.pad_target(target);
+                buf.set_color(ColorSpec::new().set_bold(true))?;
+                write!(buf, "{: <width$}", target, width = width)?;
+                buf.reset()?;
+                write!(buf, "{}", self.separators.message)?;
+            }
+
+            write!(buf, "{:indent$}", "", indent = 2 * group::depth())
+        })
+    }
+
+    fn write_compact(
+        &self,
+        buf: &mut Buffer,
+        record: &Record,
+        width: Option<usize>,
+    ) -> io::Result<()> {
+        self.write_lines(buf, record, width, |buf| {
+            if self.is_clean(record) {
+                return self.write_clean_header(buf);
+            }
+            self.write_prefix(buf)?;
+
+            let (_, color) = level_style(record.level());
+            let label = self.label(record.level());
+            buf.set_color(ColorSpec::new().set_fg(Some(color)))?;
+            write!(buf, "{}", label.chars().next().unwrap_or(' '))?;
+            buf.reset()?;
+            write!(buf, "{}", self.separators.column)?;
+
+            if !self.options.hide_target {
+                buf.set_color(ColorSpec::new().set_bold(true))?;
+                write!(buf, "{}", self.target(record.target()))?;
+                buf.reset()?;
+                write!(buf, "{}", self.separators.message)?;
+            }
+
+            write!(buf, "{:indent$}", "", indent = 2 * group::depth())
+        })
+    }
+
+    /// Writes the message after `header`, laying out its continuation lines as configured and
+    /// wrapping it at `width` columns if given.
+    fn write_lines<H>(
+        &self,
+        buf: &mut Buffer,
+        record: &Record,
+        width: Option<usize>,
+        header: H,
+    ) -> io::Result<()>
+    where
+        H: Fn(&mut Buffer) -> io::Result<()>,
+    {
+        header(buf)?;
+        let message = match (self.options.multiline, width) {
+            (Multiline::Raw, None) => None,
+            (_, None) => Some(record.args().to_string()).filter(|message| message.contains('\n')),
+            (_, Some(_)) => Some(record.args().to_string()),
+        };
+        let Some(message) = message else {
+            write!(buf, "{}", record.args())?;
+            return self.write_suffix(buf, record);
+        };
+
+        let indent = writer::with_plain_buffer(|plain| {
+            header(plain)?;
+            Ok::<_, io::Error>(String::from_utf8_lossy(plain.as_slice()).chars().count())
+        })?;
+        // Too narrow a column would leave a word or two per line, worse than not wrapping.
+        let width = width.filter(|&width| width >= indent + MIN_WRAP_WIDTH);
+        for (i, line) in message.split('\n').enumerate() {
+            let mut column = indent;
+            if i > 0 {
+                writeln!(buf)?;
+                match self.options.multiline {
+                    Multiline::Raw => column = 0,
+                    Multiline::Indent => write!(buf, "{:indent$}", "", indent = indent)?,
+                    Multiline::Restamp => header(buf)?,
+                }
+            }
+            match width {
+                Some(width) => write_wrapped(buf, line, column, indent, width)?,
+                None => write!(buf, "{}", line)?,
+            }
+        }
+        self.write_suffix(buf, record)
+    }
+
+    /// Returns the label of a level, padded to the width of the longest label.
+    fn label(&self, level: Level) -> Cow<'_, str> {
+        if self.labels.0.iter().all(Option::is_none) {
+            let label = level_style(level).0;
+            return if self.options.lowercase_levels {
+                Cow::Owned(label.to_lowercase())
+            } else {
+                Cow::Borrowed(label)
+            };
+        }
+
+        let label = |level: Level| match &self.labels.0[level as usize - 1] {
+            Some(label) => label.as_str(),
+            None => level.as_str(),
+        };
+        let width = Level::iter()
+            .map(|level| label(level).chars().count())
+            .max()
+            .unwrap_or(0);
+        let label = format!("{: <width$}", label(level), width = width);
+        if self.options.lowercase_levels {
+            Cow::Owned(label.to_lowercase())
+        } else {
+            Cow::Owned(label)
+        }
+    }
+
+    /// Whether the record is written like normal program output, without level and target.
+    fn is_clean(&self, record: &Record) -> bool {
+        self.options.clean_info && record.level() == Level::Info
+    }
+
+    fn write_clean_header(&self, buf: &mut Buffer) -> io::Result<()> {
+        self.write_prefix(buf)?;
+        write!(buf, "{:indent$}", "", indent = 2 * group::depth())
+    }
+
+    /// Starts a line of the pretty or compact layout with the optional parts preceding the level.
+    fn write_prefix(&self, buf: &mut Buffer) -> io::Result<()> {
+        let column = &self.separators.column;
+        if let Some(app_name) = &self.app_name {
+            write!(buf, "{}{}", app_name, column)?;
+        }
+        if self.timed {
+            if let Some(now) = timestamp() {
+                write!(buf, "{}{}", now, column)?;
+            }
+        }
+        if self.options.hostname {
+            write!(buf, "{}{}", hostname(), column)?;
+        }
+        if self.options.pid {
+            write!(buf, "{}{}", std::process::id(), column)?;
+        }
+        if self.options.thread {
+            write!(buf, "[{}]{}", thread_name(), column)?;
+        }
+        Ok(())
+    }
+
+    /// Ends a line of the pretty or compact layout with the optional parts following the message.
+    fn write_suffix(&self, buf: &mut Buffer, record: &Record) -> io::Result<()> {
+        let mut kvs = kv::collect(record);
+        let mut take = |name: &str| {
+            let i = kvs.iter().position(|(key, _)| key == name)?;
+            Some(kvs.remove(i).1)
+        };
+        let causes = take(report::KEY);
+        let backtrace = take(backtrace::KEY);
+        if !kvs.is_empty() {
+            let mut out = String::new();
+            for (key, value) in &kvs {
+                out.push(' ');
+                out.push_str(key);
+                out.push('=');
+                write_logfmt_value(&mut out, value);
+            }
+            buf.set_color(ColorSpec::new().set_dimmed(true))?;
+            write!(buf, "{}", out)?;
+            buf.reset()?;
+        }
+        if let Some(build_info) = self.options.build_info {
+            buf.set_color(ColorSpec::new().set_dimmed(true))?;
+            write!(buf, " {}", build_info)?;
+            buf.reset()?;
+        }
+        if self.options.source_location {
+            if let (Some(file), Some(line)) = (record.file(), record.line()) {
+                buf.set_color(ColorSpec::new().set_dimmed(true))?;
+                write!(buf, " ({}:{})", short_path(file), line)?;
+                buf.reset()?;
+            }
+        }
+        writeln!(buf)?;
+
+        let causes = causes.iter().flat_map(|causes| causes.lines());
+        for cause in causes.map(str::to_string).chain(kv::error_causes(record)) {
+            buf.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
+            write!(buf, "    caused by:")?;
+            buf.reset()?;
+            writeln!(buf, " {}", cause)?;
+        }
+        for frame in backtrace.iter().flat_map(|backtrace| backtrace.lines()) {
+            buf.set_color(ColorSpec::new().set_dimmed(true))?;
+            writeln!(buf, "    at {}", frame)?;
+            buf.reset()?;
+        }
+        Ok(())
+    }
+
+    fn write_csv(
+        &self,
+        buf: &mut Buffer,
+        record: &Record,
+        columns: &[Column],
+        header: bool,
+    ) -> io::Result<()> {
+        let mut out = String::new();
+        if header {
+            let names: Vec<_> = columns.iter().map(|column| column.name()).collect();
+            out.push_str(&names.join(","));
+            out.push('\n');
+        }
+
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let value = match column {
+                Column::Timestamp => timestamp().map(|now| now.to_string()),
+                Column::Level => Some(record.level().to_string()),
+                Column::Target => Some(record.target().to_string()),
+                Column::Message => Some(record.args().to_string()),
+                Column::ModulePath => record.module_path().map(str::to_string),
+                Column::File => record.file().map(str::to_string),
+                Column::Line => record.line().map(|line| line.to_string()),
+            };
+            write_csv_field(&mut out, value.as_deref().unwrap_or(""));
+        }
+        writeln!(buf, "{}", out)
+    }
+
+    /// Returns the name a target is shown under in the pretty and compact layouts.
+    fn target<'a>(&self, target: &'a str) -> Cow<'a, str> {
+        for (module, alias) in &self.aliases {
+            if let Some(rest) = target.strip_prefix(module.as_str()) {
+                if rest.is_empty() || rest.starts_with("::") {
+                    return Cow::Owned(format!("{}{}", alias, rest));
+                }
+            }
+        }
+        for prefix in &self.strip_prefixes {
+            match target.strip_prefix(prefix.as_str()) {
+                Some(rest) if !rest.is_empty() => return Cow::Borrowed(rest),
+                _ => {}
+            }
+        }
+        Cow::Borrowed(target)
+    }
+
+    /// Returns the target to write in the pretty layout and the width to pad it to.
+    fn pad_target<'a>(&self, target: Cow<'a, str>) -> (Cow<'a, str>, usize) {
+        match self.options.target_width {
+            TargetWidth::Auto => {
+                let width = self.max_target_width(&target);
+                (target, width)
+            }
+            TargetWidth::Fixed(width) => {
+                let len = target.chars().count();
+                if len <= width {
+                    return (target, width);
+                }
+                if width == 0 {
+                    return (Cow::Borrowed(""), 0);
+                }
+                let tail: String = target.chars().skip(len + 1 - width).collect();
+                (Cow::Owned(format!("…{}", tail)), width)
+            }
+            TargetWidth::Max(max) => {
+                let width = self.max_target_width(&target).min(max);
+                (target, width)
+            }
+            TargetWidth::None => (target, 0),
+        }
+    }
+
+    fn max_target_width(&self, target: &str) -> usize {
+        self.max_target_width
+            .fetch_max(target.len(), Ordering::Relaxed)
+            .max(target.len())
+    }
+}
+
+/// The narrowest message column wrapped messages are laid out in.
+const MIN_WRAP_WIDTH: usize = 20;
+
+/// Writes a line of a message starting at `column`, breaking it between words so it fits in
+/// `width` columns. Continuation lines are indented by `indent` columns.
+///
+/// Words longer than the available width are written whole.
+fn write_wrapped(
+    buf: &mut Buffer,
+    line: &str,
+    mut column: usize,
+    indent: usize,
+    width: usize,
+) -> io::Result<()> {
+    for (i, word) in line.split(' ').enumerate() {
+        let len = word.chars().count();
+        if i > 0 {
+            if column + 1 + len > width && column > indent {
+                writeln!(buf)?;
+                write!(buf, "{:indent$}", "", indent = indent)?;
+                column = indent;
+            } else {
+                write!(buf, " ")?;
+                column += 1;
+            }
+        }
+        write!(buf, "{}", word)?;
+        column += len;
+    }
+    Ok(())
+}
+
+fn write_json(buf: &mut Buffer, record: &Record) -> io::Result<()> {
+    let mut out = String::from("{");
+    if let Some(now) = timestamp() {
+        out.push_str("\"timestamp\":\"");
+        out.push_str(&now.to_string());
+        out.push_str("\",");
+    }
+    out.push_str("\"level\":\"");
+    out.push_str(record.level().as_str());
+    out.push_str("\",\"target\":");
+    json::write_str(&mut out, record.target());
+    out.push_str(",\"message\":");
+    json::write_str(&mut out, &record.args().to_string());
+    if let Some(file) = record.file() {
+        out.push_str(",\"file\":");
+        json::write_str(&mut out, file);
+    }
+    if let Some(line) = record.line() {
+        out.push_str(&format!(",\"line\":{}", line));
+    }
+    kv::write_json_fields(
+        &mut out,
+        record,
+        &["timestamp", "level", "target", "message", "file", "line"],
+    );
+    out.push('}');
+    writeln!(buf, "{}", out)
+}
+
+fn write_google_cloud(buf: &mut Buffer, record: &Record) -> io::Result<()> {
+    let severity = match record.level() {
+        Level::Trace | Level::Debug => "DEBUG",
+        Level::Info => "INFO",
+        Level::Warn => "WARNING",
+        Level::Error => "ERROR",
+    };
+
+    let mut out = String::from("{\"severity\":\"");
+    out.push_str(severity);
+    out.push('"');
+    if let Some(now) = timestamp() {
+        out.push_str(",\"time\":\"");
+        out.push_str(&now.to_string());
+        out.push('"');
+    }
+    out.push_str(",\"message\":");
+    json::write_str(&mut out, &record.args().to_string());
+    out.push_str(",\"target\":");
+    json::write_str(&mut out, record.target());
+    if let Some(file) = record.file() {
+        out.push_str(",\"logging.googleapis.com/sourceLocation\":{\"file\":");
+        json::write_str(&mut out, file);
+        if let Some(line) = record.line() {
+            out.push_str(",\"line\":");
+            json::write_str(&mut out, &line.to_string());
+        }
+        if let Some(module) = record.module_path() {
+            out.push_str(",\"function\":");
+            json::write_str(&mut out, module);
+        }
+        out.push('}');
+    }
+    kv::write_json_fields(
+        &mut out,
+        record,
+        &[
+            "severity",
+            "time",
+            "message",
+            "target",
+            "logging.googleapis.com/sourceLocation",
+        ],
+    );
+    out.push('}');
+    writeln!(buf, "{}", out)
+}
+
+fn write_datadog(buf: &mut Buffer, record: &Record) -> io::Result<()> {
+    let mut out = String::from("{");
+    if let Some(now) = timestamp() {
+        out.push_str("\"timestamp\":\"");
+        out.push_str(&now.to_string());
+        out.push_str("\",");
+    }
+    out.push_str("\"status\":");
+    json::write_str(&mut out, &record.level().as_str().to_lowercase());
+    out.push_str(",\"message\":");
+    json::write_str(&mut out, &record.args().to_string());
+    out.push_str(",\"logger\":{\"name\":");
+    json::write_str(&mut out, record.target());
+    out.push('}');
+    let mut attributes = String::new();
+    kv::write_json_fields(&mut attributes, record, &[]);
+    if let Some(attributes) = attributes.strip_prefix(',') {
+        out.push_str(",\"attributes\":{");
+        out.push_str(attributes);
+        out.push('}');
+    }
+    out.push('}');
+    writeln!(buf, "{}", out)
+}
+
+fn write_logfmt(buf: &mut Buffer, record: &Record) -> io::Result<()> {
+    let mut out = String::new();
+    if let Some(now) = timestamp() {
+        out.push_str("ts=");
+        out.push_str(&now.to_string());
+        out.push(' ');
+    }
+    out.push_str("level=");
+    out.push_str(&record.level().as_str().to_lowercase());
+    out.push_str(" target=");
+    write_logfmt_value(&mut out, record.target());
+    out.push_str(" msg=");
+    write_logfmt_value(&mut out, &record.args().to_string());
+    for (key, value) in kv::collect(record) {
+        out.push(' ');
+        out.push_str(&key);
+        out.push('=');
+        write_logfmt_value(&mut out, &value);
+    }
+    writeln!(buf, "{}", out)
+}
+
+/// Appends a logfmt value, quoted when it is empty or contains spaces, quotes or `=`.
+fn write_logfmt_value(out: &mut String, value: &str) {
+    let bare = !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c == ' ' || c == '=' || c == '"' || c.is_control());
+    if bare {
+        out.push_str(value);
+        return;
+    }
+
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_cef(buf: &mut Buffer, record: &Record, device: [&str; 3]) -> io::Result<()> {
+    let severity = match record.level() {
+        Level::Error => 8,
+        Level::Warn => 6,
+        Level::Info => 3,
+        Level::Debug => 1,
+        Level::Trace => 0,
+    };
+
+    let mut out = String::from("CEF:0|");
+    for field in device
+        .iter()
+        .copied()
+        .chain([record.target(), &record.args().to_string()])
+    {
+        write_cef_header(&mut out, field);
+        out.push('|');
+    }
+    out.push_str(&format!("{}|", severity));
+
+    let millis = now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    out.push_str(&format!("rt={} dvcpid={}", millis, std::process::id()));
+    for (key, value) in kv::collect(record) {
+        out.push(' ');
+        // Keys can't be escaped, characters other than letters, digits and `_` are replaced.
+        out.extend(key.chars().map(|c| match c {
+            c if c.is_alphanumeric() || c == '_' => c,
+            _ => '_',
+        }));
+        out.push('=');
+        write_cef_value(&mut out, &value);
+    }
+    writeln!(buf, "{}", out)
+}
+
+/// Appends a CEF header field, where pipes are escaped and line breaks are not allowed.
+fn write_cef_header(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '|' => out.push_str("\\|"),
+            '\\' => out.push_str("\\\\"),
+            '\r' | '\n' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Appends a CEF extension value, where `\` and `=` are escaped with a backslash and line
+/// breaks are written as `\n` and `\r`.
+fn write_cef_value(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '=' => out.push_str("\\="),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Appends a CSV field, quoted as described in RFC 4180 when it contains separators or quotes.
+fn write_csv_field(out: &mut String, value: &str) {
+    if value.contains([',', '"', '\r', '\n']) {
+        out.push('"');
+        out.push_str(&value.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(value);
+    }
+}
+
+/// Shortens a source path to `crate-name/src/...` for dependencies, and makes it relative to
+/// the working directory for the rest of the workspace.
+pub(crate) fn short_path(file: &str) -> Cow<'_, str> {
+    static ROOT: OnceLock<Option<String>> = OnceLock::new();
+
+    let path = file.replace('\\', "/");
+    let short = if let Some((_, rest)) = path.split_once("/.cargo/registry/src/") {
+        // Skips the registry directory, then the version of the crate.
+        let rest = rest.split_once('/').map_or(rest, |(_, rest)| rest);
+        match rest.split_once('/') {
+            Some((krate, rest)) => format!("{}/{}", strip_version(krate), rest),
+            None => rest.to_string(),
+        }
+    } else if let Some((_, rest)) = path.split_once("/.cargo/git/checkouts/") {
+        // Checkouts are stored as `name-hash/revision/...`.
+        let mut parts = rest.splitn(3, '/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(checkout), Some(_), Some(rest)) => {
+                let name = checkout.rsplit_once('-').map_or(checkout, |(name, _)| name);
+                format!("{}/{}", name, rest)
+            }
+            _ => rest.to_string(),
+        }
+    } else if let Some(rest) = path.strip_prefix("/rustc/") {
+        // Paths into the standard library are `/rustc/hash/library/std/src/...`.
+        rest.split_once("/library/")
+            .map_or(path.clone(), |(_, rest)| rest.to_string())
+    } else {
+        let root = ROOT.get_or_init(|| {
+            std::env::current_dir()
+                .ok()
+                .map(|dir| format!("{}/", dir.to_string_lossy().replace('\\', "/")))
+        });
+        match root.as_deref().and_then(|root| path.strip_prefix(root)) {
+            Some(rest) => rest.to_string(),
+            None => return Cow::Borrowed(file),
+        }
+    };
+    Cow::Owned(short)
+}
+
+/// Strips the version from a `name-1.2.3` directory name.
+fn strip_version(krate: &str) -> &str {
+    krate
+        .match_indices('-')
+        .map(|(i, _)| i)
+        .find(|&i| {
+            let version = &krate[i + 1..];
+            version.starts_with(|c: char| c.is_ascii_digit()) && version.split('.').count() >= 3
+        })
+        .map_or(krate, |i| &krate[..i])
+}
+
+/// Returns the name of the current thread, or its ID if it is unnamed.
+pub(crate) fn thread_name() -> String {
+    if let Some(name) = ORIGIN_THREAD.with(|origin| origin.borrow().clone()) {
+        return name;
+    }
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        // Thread IDs can only be formatted as `ThreadId(N)`.
+        None => {
+            let id = format!("{:?}", thread.id());
+            let digits = id.trim_start_matches("ThreadId(").trim_end_matches(')');
+            format!("#{}", digits)
+        }
+    }
+}
+
+thread_local! {
+    /// The thread records written by the current thread were logged from, if another one.
+    static ORIGIN_THREAD: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// The time records written by the current thread were logged at, if earlier.
+    static ORIGIN_TIME: Cell<Option<SystemTime>> = const { Cell::new(None) };
+}
+
+/// Sets the name of the thread records written by the current thread were logged from,
+/// returning the previous one.
+pub(crate) fn set_origin_thread(name: Option<String>) -> Option<String> {
+    ORIGIN_THREAD.with(|origin| origin.replace(name))
+}
+
+/// Sets the time records written by the current thread were logged at, returning the previous
+/// one.
+pub(crate) fn set_origin_time(time: Option<SystemTime>) -> Option<SystemTime> {
+    ORIGIN_TIME.with(|origin| origin.replace(time))
+}
+
+/// Returns the time the record being written was logged at, which is now unless it is written
+/// on behalf of another thread or later on.
+pub(crate) fn now() -> SystemTime {
+    ORIGIN_TIME
+        .try_with(Cell::get)
+        .ok()
+        .flatten()
+        .unwrap_or_else(SystemTime::now)
+}
+
+/// Returns the time the record being written was logged at, or `None` if it cannot be
+/// formatted.
+///
+/// Clocks on some targets, WASI in particular, may report times before the epoch, which would
+/// make `humantime` panic.
+pub(crate) fn timestamp() -> Option<Rfc3339Timestamp> {
+    /// The last second of year 9999, the latest time `humantime` can format.
+    const MAX_SECS: u64 = 253_402_300_799;
+
+    let now = now();
+    match now.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) if since_epoch.as_secs() <= MAX_SECS => {
+            Some(humantime::format_rfc3339_millis(now))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn level_style(level: Level) -> (&'static str, Color) {
+    match level {
+        Level::Trace => ("TRACE", Color::Magenta),
+        Level::Debug => ("DEBUG", Color::Blue),
+        Level::Info => ("INFO ", Color::Green),
+        Level::Warn => ("WARN ", Color::Yellow),
+        Level::Error => ("ERROR", Color::Red),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: Format, record: &Record) -> String {
+        let mut buf = Buffer::no_color();
+        Formatter::new(format, false)
+            .write(&mut buf, record)
+            .unwrap();
+        String::from_utf8(buf.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn datadog_nests_key_values_under_attributes() {
+        let kvs = [("user", "ana"), ("status", "shadowed")];
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("billing")
+            .args(format_args!("card declined"))
+            .key_values(&kvs)
+            .build();
+        let line = render(Format::Datadog, &record);
+        let json = line.split_once("\"status\"").unwrap().1;
+        assert_eq!(
+            json.trim_end(),
+            ":\"warn\",\"message\":\"card declined\",\"logger\":{\"name\":\"billing\"},\
+             \"attributes\":{\"user\":\"ana\",\"status\":\"shadowed\"}}"
+        );
+
+        let record = Record::builder().args(format_args!("plain")).build();
+        assert!(!render(Format::Datadog, &record).contains("attributes"));
+    }
+
+    fn logfmt_value(value: &str) -> String {
+        let mut out = String::new();
+        write_logfmt_value(&mut out, value);
+        out
+    }
+
+    #[test]
+    fn logfmt_quotes_values_only_when_needed() {
+        assert_eq!(logfmt_value("ana"), "ana");
+        assert_eq!(logfmt_value("C:\\temp"), "C:\\temp");
+        assert_eq!(logfmt_value(""), "\"\"");
+        assert_eq!(logfmt_value("two words"), "\"two words\"");
+        assert_eq!(logfmt_value("a=b"), "\"a=b\"");
+        assert_eq!(
+            logfmt_value("say \"hi\"\tC:\\ \r\n"),
+            "\"say \\\"hi\\\"\\tC:\\\\ \\r\\n\""
+        );
+    }
+
+    #[test]
+    fn logfmt_writes_a_line_per_record() {
+        let kvs = [("user", "ana"), ("reason", "bad pin")];
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("billing")
+            .args(format_args!("card declined"))
+            .key_values(&kvs)
+            .build();
+        let line = render(Format::Logfmt, &record);
+        let line = line.split_once(' ').unwrap().1;
+        assert_eq!(
+            line,
+            "level=warn target=billing msg=\"card declined\" user=ana reason=\"bad pin\"\n"
+        );
+    }
+
+    #[test]
+    fn cef_escapes_header_fields() {
+        let mut out = String::new();
+        write_cef_header(&mut out, "a|b\\c\r\nd=e");
+        assert_eq!(out, "a\\|b\\\\c  d=e");
+
+        let kvs = [("user name", "ana=admin"), ("path", "C:\\tmp\nx")];
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("auth")
+            .args(format_args!("login failed | user=ana\nretrying"))
+            .key_values(&kvs)
+            .build();
+        let format = Format::Cef {
+            vendor: "Acme",
+            product: "ingestd",
+            version: "1.0",
+        };
+        let line = render(format, &record);
+        let (header, extension) = line.rsplit_once('|').unwrap();
+        assert_eq!(
+            header,
+            "CEF:0|Acme|ingestd|1.0|auth|login failed \\| user=ana retrying|6"
+        );
+        assert!(extension.starts_with("rt="), "{}", extension);
+        assert!(extension.trim_end().ends_with(&format!(
+            "dvcpid={} user_name=ana\\=admin path=C:\\\\tmp\\nx",
+            std::process::id()
+        )));
+    }
+
+    #[test]
+    fn csv_quotes_fields_and_writes_the_header_once() {
+        let mut out = String::new();
+        for field in ["plain", "a,b", "say \"hi\"", "two\nlines"] {
+            write_csv_field(&mut out, field);
+            out.push('|');
+        }
+        assert_eq!(out, "plain|\"a,b\"|\"say \"\"hi\"\"\"|\"two\nlines\"|");
+
+        let formatter = Formatter::new(
+            Format::Csv(&[Column::Level, Column::Target, Column::Message, Column::Line]),
+            false,
+        );
+        let writer = Writer::pipe(Box::new(io::sink()), env_logger::WriteStyle::Never);
+        let mut buf = Buffer::no_color();
+        for message in ["started", "failed, retrying"] {
+            formatter
+                .render_for(
+                    &writer,
+                    &mut buf,
+                    &Record::builder()
+                        .level(Level::Info)
+                        .target("jobs")
+                        .args(format_args!("{}", message))
+                        .build(),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(buf.into_inner()).unwrap(),
+            "level,target,message,line\nINFO,jobs,started,\nINFO,jobs,\"failed, retrying\",\n"
+        );
+    }
+
+    #[test]
+    fn shortens_source_paths() {
+        assert_eq!(strip_version("serde-1.0.197"), "serde");
+        assert_eq!(strip_version("tokio-util-0.7.10"), "tokio-util");
+        assert_eq!(strip_version("h2-0.4.0-alpha.1"), "h2");
+        assert_eq!(
+            strip_version("winapi-x86_64-pc-windows-gnu"),
+            "winapi-x86_64-pc-windows-gnu"
+        );
+        assert_eq!(strip_version("ring-2"), "ring-2");
+
+        assert_eq!(
+            short_path(
+                "/home/ana/.cargo/registry/src/index.crates.io-6f17d22bba15001f/\
+                 tokio-util-0.7.10/src/codec/framed.rs"
+            ),
+            "tokio-util/src/codec/framed.rs"
+        );
+        assert_eq!(
+            short_path("C:\\Users\\ana\\.cargo\\registry\\src\\index-1\\serde-1.0.197\\src\\de.rs"),
+            "serde/src/de.rs"
+        );
+        assert_eq!(
+            short_path("/home/ana/.cargo/git/checkouts/hyper-1a2b3c/0f1e2d3/src/client.rs"),
+            "hyper/src/client.rs"
+        );
+        assert_eq!(
+            short_path("/rustc/0123abc/library/std/src/panicking.rs"),
+            "std/src/panicking.rs"
+        );
+        let local = std::env::current_dir().unwrap().join("src/main.rs");
+        assert_eq!(short_path(&local.to_string_lossy()), "src/main.rs");
+        assert_eq!(short_path("src/main.rs"), "src/main.rs");
+    }
+
+    fn wrapped(line: &str) -> String {
+        let mut buf = Buffer::no_color();
+        write_wrapped(&mut buf, line, 5, 5, 20).unwrap();
+        String::from_utf8(buf.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn wraps_between_words() {
+        assert_eq!(
+            wrapped("the quick brown fox jumps over the lazy dog"),
+            "the quick brown\n     fox jumps over\n     the lazy dog"
+        );
+        assert_eq!(
+            wrapped("a supercalifragilisticexpialidocious b"),
+            "a\n     supercalifragilisticexpialidocious\n     b"
+        );
+        assert_eq!(wrapped("ça déjà été"), "ça déjà été");
+    }
+
+    #[test]
+    fn csv_skips_the_header_when_appending() {
+        let path = std::env::temp_dir().join(format!("csv-header-{}.csv", std::process::id()));
+        std::fs::write(&path, "level,message\nINFO,before\n").unwrap();
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        let writer = Writer::file(file, env_logger::WriteStyle::Never);
+        let formatter = Formatter::new(Format::Csv(&[Column::Level, Column::Message]), false);
+        formatter
+            .print(
+                &writer,
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("after"))
+                    .build(),
+            )
+            .unwrap();
+        writer.flush().unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text, "level,message\nINFO,before\nINFO,after\n");
+    }
+}
@@ -0,0 +1,293 @@
+//! Sending records to [Graylog][graylog] in the GELF format.
+//!
+//! Each record becomes a GELF 1.1 message, with the level mapped to its syslog severity, and
+//! the target, source location, diagnostic context and key-values sent as additional fields. Over UDP, messages too large
+//! for a single datagram are chunked; over TCP, they are delimited with a null byte.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::gelf::Gelf;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let gelf = Gelf::udp("graylog.internal:12201")
+//!     .expect("cannot resolve Graylog")
+//!     .field("env", "production");
+//! Builder::with("info").sink(Box::new(gelf)).init();
+//! ```
+//!
+//! [graylog]: https://graylog.org/
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::Record;
+
+use crate::json::write_str;
+use crate::kv;
+use crate::net::{self, TcpWriter};
+use crate::process::hostname;
+use crate::sink::{syslog_severity, Sink};
+
+/// Datagrams larger than this are split into chunks.
+const MAX_CHUNK_SIZE: usize = 8192;
+/// Size of the chunk header: magic bytes, message id, sequence number and count.
+const CHUNK_HEADER_SIZE: usize = 12;
+/// The maximum number of chunks a message may be split into.
+const MAX_CHUNKS: usize = 128;
+
+enum Transport {
+    Udp(UdpSocket),
+    Tcp(Mutex<TcpWriter>),
+}
+
+/// A [`Sink`] sending GELF messages to Graylog.
+pub struct Gelf {
+    transport: Transport,
+    host: String,
+    fields: Vec<(String, String)>,
+    message_id: AtomicU64,
+}
+
+impl Gelf {
+    /// Sends messages as UDP datagrams to `addr`, given as `host:port`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the address cannot be resolved or a local socket cannot be bound.
+    pub fn udp<A: ToSocketAddrs>(addr: A) -> io::Result<Gelf> {
+        Ok(Gelf::new(Transport::Udp(net::udp_socket(addr)?)))
+    }
+
+    /// Sends messages over TCP to `addr`, given as `host:port`, reconnecting as needed.
+    pub fn tcp(addr: &str) -> Gelf {
+        Gelf::new(Transport::Tcp(Mutex::new(TcpWriter::new(addr))))
+    }
+
+    fn new(transport: Transport) -> Gelf {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Gelf {
+            transport,
+            host: hostname().to_string(),
+            fields: Vec::new(),
+            message_id: AtomicU64::new(seed ^ u64::from(std::process::id()) << 32),
+        }
+    }
+
+    /// Adds a field attached to every message. The leading underscore is added when missing.
+    pub fn field(mut self, name: &str, value: &str) -> Gelf {
+        let name = if name.starts_with('_') {
+            name.to_string()
+        } else {
+            format!("_{}", name)
+        };
+        self.fields.push((name, value.to_string()));
+        self
+    }
+
+    /// Overrides the `host` field, the machine hostname by default.
+    pub fn host(mut self, host: &str) -> Gelf {
+        self.host = host.to_string();
+        self
+    }
+
+    fn render(&self, record: &Record) -> String {
+        let timestamp = crate::fmt::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |d| d.as_secs_f64());
+
+        let mut out = String::from("{\"version\":\"1.1\",\"host\":");
+        write_str(&mut out, &self.host);
+        out.push_str(",\"short_message\":");
+        write_str(&mut out, &record.args().to_string());
+        let _ = write!(
+            out,
+            ",\"timestamp\":{:.3},\"level\":{}",
+            timestamp,
+            syslog_severity(record.level())
+        );
+        out.push_str(",\"_target\":");
+        write_str(&mut out, record.target());
+        if let Some(file) = record.file() {
+            out.push_str(",\"_file\":");
+            write_str(&mut out, file);
+        }
+        if let Some(line) = record.line() {
+            let _ = write!(out, ",\"_line\":{}", line);
+        }
+        for (name, value) in &self.fields {
+            out.push(',');
+            write_str(&mut out, name);
+            out.push(':');
+            write_str(&mut out, value);
+        }
+        for (key, value) in kv::collect(record) {
+            let name = field_name(&key);
+            // Graylog rejects messages setting the id it assigns.
+            if name == "_id" {
+                continue;
+            }
+            out.push(',');
+            write_str(&mut out, &name);
+            out.push(':');
+            write_str(&mut out, &value);
+        }
+        out.push('}');
+        out
+    }
+
+    fn send_chunked(&self, socket: &UdpSocket, message: &[u8]) -> io::Result<()> {
+        if message.len() <= MAX_CHUNK_SIZE {
+            return socket.send(message).map(|_| ());
+        }
+
+        let chunk_size = MAX_CHUNK_SIZE - CHUNK_HEADER_SIZE;
+        let count = message.len().div_ceil(chunk_size);
+        if count > MAX_CHUNKS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "message too large for GELF chunking",
+            ));
+        }
+
+        let id = self
+            .message_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_be_bytes();
+        let mut datagram = Vec::with_capacity(MAX_CHUNK_SIZE);
+        for (seq, chunk) in message.chunks(chunk_size).enumerate() {
+            datagram.clear();
+            datagram.extend_from_slice(&[0x1e, 0x0f]);
+            datagram.extend_from_slice(&id);
+            datagram.push(seq as u8);
+            datagram.push(count as u8);
+            datagram.extend_from_slice(chunk);
+            socket.send(&datagram)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Gelf {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let message = self.render(record);
+        match &self.transport {
+            Transport::Udp(socket) => self.send_chunked(socket, message.as_bytes()),
+            Transport::Tcp(writer) => {
+                let mut frame = message.into_bytes();
+                frame.push(0);
+                let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+                writer.write_all(&frame)
+            }
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        match &self.transport {
+            Transport::Udp(_) => Ok(()),
+            Transport::Tcp(writer) => writer.lock().unwrap_or_else(|e| e.into_inner()).flush(),
+        }
+    }
+}
+
+/// Turns a key into the name of an additional field, made of letters, digits, `_`, `.` and `-`
+/// and starting with an underscore.
+fn field_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len() + 1);
+    name.push('_');
+    name.extend(key.chars().map(|c| match c {
+        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | '-' => c,
+        _ => '_',
+    }));
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use super::*;
+
+    fn record<'a>(args: std::fmt::Arguments<'a>) -> Record<'a> {
+        Record::builder()
+            .level(Level::Warn)
+            .target("gelf")
+            .args(args)
+            .build()
+    }
+
+    #[test]
+    fn sends_key_values_as_additional_fields() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gelf = Gelf::udp(server.local_addr().unwrap())
+            .unwrap()
+            .host("web-1")
+            .field("env", "prod");
+        let kvs = [("user id", "42"), ("id", "shadowed"), ("req.path", "/")];
+        let message = gelf.render(
+            &record(format_args!("say \"hi\""))
+                .to_builder()
+                .key_values(&kvs)
+                .build(),
+        );
+
+        assert!(
+            message.starts_with(
+                "{\"version\":\"1.1\",\"host\":\"web-1\",\"short_message\":\"say \\\"hi\\\"\",\"timestamp\":"
+            ),
+            "{}",
+            message
+        );
+        assert!(
+            message.ends_with(
+                ",\"level\":4,\"_target\":\"gelf\",\"_env\":\"prod\",\"_user_id\":\"42\",\"_req.path\":\"/\"}"
+            ),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn chunks_large_datagrams() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gelf = Gelf::udp(server.local_addr().unwrap()).unwrap();
+        let text = "x".repeat(20_000);
+        gelf.write(&record(format_args!("{}", text))).unwrap();
+
+        let mut chunks = Vec::new();
+        let mut buf = [0; MAX_CHUNK_SIZE];
+        for _ in 0..3 {
+            let len = server.recv(&mut buf).unwrap();
+            assert!(len <= MAX_CHUNK_SIZE);
+            chunks.push(buf[..len].to_vec());
+        }
+        let mut message = Vec::new();
+        for (seq, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk[..2], [0x1e, 0x0f]);
+            assert_eq!(chunk[2..10], chunks[0][2..10]);
+            assert_eq!(chunk[10..12], [seq as u8, 3]);
+            message.extend_from_slice(&chunk[CHUNK_HEADER_SIZE..]);
+        }
+        let message = String::from_utf8(message).unwrap();
+        assert!(message.contains(&format!("\"short_message\":\"{}\"", text)));
+        assert!(message.ends_with('}'));
+    }
+
+    #[test]
+    fn sends_to_ipv6_servers() {
+        // Hosts without IPv6, such as some containers, can't run this test.
+        let Ok(server) = UdpSocket::bind("[::1]:0") else {
+            return;
+        };
+        let gelf = Gelf::udp(server.local_addr().unwrap()).unwrap();
+        gelf.write(&record(format_args!("over udp"))).unwrap();
+
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).contains("\"short_message\":\"over udp\""));
+    }
+}
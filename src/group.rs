@@ -0,0 +1,59 @@
+//! Indented groups of records, rendering nested operations as a tree in the terminal.
+
+use std::cell::Cell;
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Logs an info record, then indents the records logged on the current thread in the pretty,
+/// plain and compact layouts until the returned [`GroupGuard`] is dropped.
+///
+/// The arguments are the same as those of [`log::info!`].
+///
+/// ```
+/// use pretty_flexible_env_logger::group;
+///
+/// pretty_flexible_env_logger::init_with("info");
+///
+/// let _group = group!("loading config");
+/// log::info!("reading config.toml"); // ... >   reading config.toml
+/// ```
+#[macro_export]
+macro_rules! group {
+    ($($arg:tt)+) => {{
+        $crate::__log::info!($($arg)+);
+        $crate::group::enter()
+    }};
+}
+
+/// Indents the records logged on the current thread until the returned guard is dropped.
+///
+/// This is what [`group!`](crate::group!) does after logging the title of the group.
+pub fn enter() -> GroupGuard {
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    GroupGuard { _private: () }
+}
+
+/// Closes a group when dropped, see [`enter`].
+#[must_use = "the group is closed when the guard is dropped"]
+pub struct GroupGuard {
+    _private: (),
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+    }
+}
+
+/// Sets how many groups are open on the current thread, for records written on behalf of
+/// another thread.
+pub(crate) fn set_depth(depth: usize) {
+    let _ = DEPTH.try_with(|current| current.set(depth));
+}
+
+/// Returns how many groups are open on the current thread.
+pub(crate) fn depth() -> usize {
+    DEPTH.try_with(Cell::get).unwrap_or(0)
+}
@@ -0,0 +1,115 @@
+//! A minimal HTTP/1.1 client, enough to push batches of records to collectors.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends a `POST` request, failing unless the response status is a success.
+///
+/// `https` URLs are only supported with the `tls` feature.
+pub(crate) fn post(
+    url: &str,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<()> {
+    let mut all_headers = vec![("Content-Type", content_type)];
+    all_headers.extend_from_slice(headers);
+    request("POST", url, &all_headers, body).map(|_| ())
+}
+
+/// Sends a `GET` request, returning the response body if the status is a success.
+#[cfg_attr(not(feature = "cloudwatch"), allow(dead_code))]
+pub(crate) fn get(url: &str, headers: &[(&str, &str)]) -> io::Result<String> {
+    let body = request("GET", url, headers, &[])?;
+    String::from_utf8(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn request(method: &str, url: &str, headers: &[(&str, &str)], body: &[u8]) -> io::Result<Vec<u8>> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or_else(|| invalid("missing URL scheme"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let default_port = match scheme {
+        "http" => 80,
+        "https" => 443,
+        _ => return Err(invalid("unsupported URL scheme")),
+    };
+    let (host_name, port) = match authority.rsplit_once(':') {
+        Some((name, port)) => (name, port.parse().map_err(|_| invalid("invalid port"))?),
+        None => (authority, default_port),
+    };
+
+    let stream = TcpStream::connect((host_name, port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        method,
+        path,
+        authority,
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    match scheme {
+        #[cfg(feature = "tls")]
+        "https" => {
+            let connector = native_tls::TlsConnector::new().map_err(io::Error::other)?;
+            let stream = connector
+                .connect(host_name, stream)
+                .map_err(io::Error::other)?;
+            exchange(stream, head.as_bytes(), body)
+        }
+        "http" => exchange(stream, head.as_bytes(), body),
+        _ => Err(invalid("https requires the tls feature")),
+    }
+}
+
+fn exchange<S: Read + Write>(mut stream: S, head: &[u8], body: &[u8]) -> io::Result<Vec<u8>> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let head_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map_or(response.len(), |i| i + 4);
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    let status_line = head.lines().next().unwrap_or_default();
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("malformed HTTP response"))?;
+
+    let body = &response[head_end..];
+    if (200..300).contains(&status) {
+        Ok(body.to_vec())
+    } else if body.is_empty() {
+        Err(io::Error::other(format!("request failed: {}", status_line)))
+    } else {
+        // Collectors tell why in the body, which callers may look into.
+        Err(io::Error::other(format!(
+            "request failed: {}: {}",
+            status_line,
+            String::from_utf8_lossy(body).trim()
+        )))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
@@ -0,0 +1,308 @@
+//! Native logging to the systemd journal.
+//!
+//! Records are sent through the journal's [native protocol][protocol], so instead of a
+//! pre-formatted colored line, the journal receives the message along with structured fields:
+//!
+//! * `PRIORITY` - the syslog severity derived from the record level,
+//! * `TARGET` - the record target,
+//! * `CODE_FILE`, `CODE_LINE` and `CODE_MODULE` - the source location, when available,
+//! * `SYSLOG_IDENTIFIER` - the executable name,
+//! * the diagnostic context and key-values of the record, named in upper case with characters
+//!   other than letters and digits replaced by underscores, `user.id` as `USER_ID`.
+//!
+//! Messages too large for a datagram are handed to the journal in a sealed memory file, as the
+//! protocol describes.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::journald::Journald;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let journald = Journald::new().expect("journald is not running");
+//! Builder::with("info").sink(Box::new(journald)).init();
+//! ```
+//!
+//! [protocol]: https://systemd.io/JOURNAL_NATIVE_PROTOCOL/
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+use log::Record;
+
+use crate::kv;
+use crate::process::app_name;
+use crate::sink::{syslog_severity, Sink};
+
+/// The socket journald listens on for native protocol messages.
+const JOURNALD_PATH: &str = "/run/systemd/journal/socket";
+/// The fields written by the sink, which key-values are kept from overriding.
+const FIELDS: [&str; 7] = [
+    "MESSAGE",
+    "PRIORITY",
+    "TARGET",
+    "CODE_FILE",
+    "CODE_LINE",
+    "CODE_MODULE",
+    "SYSLOG_IDENTIFIER",
+];
+/// The longest field name the journal accepts.
+const MAX_NAME_LEN: usize = 64;
+
+/// A [`Sink`] sending records to the systemd journal.
+pub struct Journald {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+impl Journald {
+    /// Connects to the journal on its well-known socket.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if journald is not running.
+    pub fn new() -> io::Result<Journald> {
+        Journald::with_path(JOURNALD_PATH)
+    }
+
+    /// Connects to a journal listening on a custom socket.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the socket cannot be connected to.
+    pub fn with_path<P: AsRef<Path>>(path: P) -> io::Result<Journald> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Journald {
+            socket,
+            identifier: app_name(),
+        })
+    }
+
+    /// Sets the `SYSLOG_IDENTIFIER` field, the executable name by default.
+    pub fn identifier(mut self, identifier: &str) -> Journald {
+        self.identifier = identifier.to_string();
+        self
+    }
+}
+
+impl Sink for Journald {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let buf = self.render(record);
+        match self.socket.send(&buf) {
+            Ok(_) => Ok(()),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EMSGSIZE | libc::ENOBUFS)) => {
+                send_large(&self.socket, &buf).map_err(|_| e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Journald {
+    /// Renders the record as a native protocol message.
+    fn render(&self, record: &Record) -> Vec<u8> {
+        let mut buf = Vec::new();
+        add_field(&mut buf, "MESSAGE", &record.args().to_string());
+        add_field(
+            &mut buf,
+            "PRIORITY",
+            &syslog_severity(record.level()).to_string(),
+        );
+        add_field(&mut buf, "TARGET", record.target());
+        if let Some(file) = record.file() {
+            add_field(&mut buf, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            add_field(&mut buf, "CODE_LINE", &line.to_string());
+        }
+        if let Some(module) = record.module_path() {
+            add_field(&mut buf, "CODE_MODULE", module);
+        }
+        add_field(&mut buf, "SYSLOG_IDENTIFIER", &self.identifier);
+        for (key, value) in kv::collect(record) {
+            add_field(&mut buf, &field_name(&key), &value);
+        }
+        buf
+    }
+}
+
+/// Turns a key into a valid field name, made of upper case letters, digits and underscores and
+/// starting with a letter.
+fn field_name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_uppercase()) || FIELDS.contains(&name.as_str()) {
+        name.insert_str(0, "F_");
+    }
+    name.truncate(MAX_NAME_LEN);
+    name
+}
+
+/// Sends a message too large for a datagram through a sealed memory file, passed along with an
+/// empty datagram.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_large(socket: &UnixDatagram, buf: &[u8]) -> io::Result<()> {
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+    // SAFETY: the name is a C string.
+    let fd = unsafe {
+        libc::memfd_create(
+            c"journal-message".as_ptr(),
+            libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor was just created, and is owned by the file from now on.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(buf)?;
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    // SAFETY: the descriptor is valid for as long as the file lives.
+    if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Aligned for `cmsghdr`, and large enough for a single descriptor.
+    let mut control = [0u64; 4];
+    // SAFETY: `msghdr` is plain old data, and the control message is written within the
+    // buffer, whose size `CMSG_SPACE` gives.
+    let r = unsafe {
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) as _;
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), file.as_raw_fd());
+        libc::sendmsg(socket.as_raw_fd(), &msg, libc::MSG_NOSIGNAL)
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn send_large(_: &UnixDatagram, _: &[u8]) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Appends a field, using the length-prefixed form for values spanning multiple lines.
+fn add_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use super::*;
+
+    fn journal() -> (Journald, UnixDatagram) {
+        let (socket, journal) = UnixDatagram::pair().unwrap();
+        let journald = Journald {
+            socket,
+            identifier: "app".to_string(),
+        };
+        (journald, journal)
+    }
+
+    #[test]
+    fn adds_multiline_fields_with_their_length() {
+        let mut buf = Vec::new();
+        add_field(&mut buf, "MESSAGE", "one line");
+        add_field(&mut buf, "MESSAGE", "two\nlines");
+        let mut expected = b"MESSAGE=one line\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\n");
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn maps_context_and_key_values_to_fields() {
+        assert_eq!(field_name("user.id"), "USER_ID");
+        assert_eq!(field_name("2fa"), "F_2FA");
+        assert_eq!(field_name("_secret"), "F__SECRET");
+        assert_eq!(field_name("message"), "F_MESSAGE");
+        assert_eq!(field_name(&"k".repeat(100)).len(), MAX_NAME_LEN);
+
+        let (journald, _) = journal();
+        let _request = crate::mdc::scope("request-id", "r-42");
+        let kvs = [("user.id", "7")];
+        let buf = journald.render(
+            &Record::builder()
+                .level(Level::Warn)
+                .target("auth")
+                .args(format_args!("login failed"))
+                .key_values(&kvs)
+                .build(),
+        );
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "MESSAGE=login failed\nPRIORITY=4\nTARGET=auth\nSYSLOG_IDENTIFIER=app\n\
+             REQUEST_ID=r-42\nUSER_ID=7\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sends_large_messages_through_a_memory_file() {
+        use std::fs::File;
+        use std::io::{Read, Seek, SeekFrom};
+        use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+        let (journald, journal) = journal();
+        let message = "x".repeat(1 << 20);
+        journald
+            .write(
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("{}", message))
+                    .build(),
+            )
+            .unwrap();
+
+        let mut data = [0u8; 16];
+        let mut control = [0u64; 4];
+        // SAFETY: the buffers outlive the call, and the descriptor received is owned by the file.
+        let mut file = unsafe {
+            let mut iov = libc::iovec {
+                iov_base: data.as_mut_ptr().cast(),
+                iov_len: data.len(),
+            };
+            let mut msg: libc::msghdr = std::mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr().cast();
+            msg.msg_controllen = std::mem::size_of_val(&control) as _;
+            assert_eq!(libc::recvmsg(journal.as_raw_fd(), &mut msg, 0), 0);
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            assert_eq!((*cmsg).cmsg_type, libc::SCM_RIGHTS);
+            File::from_raw_fd(std::ptr::read_unaligned(
+                libc::CMSG_DATA(cmsg).cast::<RawFd>(),
+            ))
+        };
+        // The journal maps the file, whatever its offset.
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut received = String::new();
+        file.read_to_string(&mut received).unwrap();
+        assert!(received.starts_with(&format!("MESSAGE={}\nPRIORITY=6\n", message)));
+    }
+}
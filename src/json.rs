@@ -0,0 +1,32 @@
+//! Just enough JSON to render records for collectors.
+
+use std::fmt::Write;
+
+/// Appends `s` as a quoted JSON string.
+pub(crate) fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Finds the value of a top-level string field, assuming it contains no escape sequences.
+#[cfg(feature = "cloudwatch")]
+pub(crate) fn find_str<'a>(json: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("\"{}\"", name);
+    let rest = &json[json.find(&key)? + key.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    rest.find('"').map(|end| &rest[..end])
+}
@@ -0,0 +1,158 @@
+//! Publishing records to an [Apache Kafka][kafka] topic.
+//!
+//! Records are rendered as JSON lines (see [`Format::Json`](crate::Format::Json)) and
+//! published in batches from a background thread. The partition key can be derived from the
+//! record, so that for instance all records of a given target land in the same partition and
+//! keep their relative order.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::kafka::{Kafka, PartitionKey};
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let kafka = Kafka::new(&["kafka-1.internal:9092"], "logs").partition_key(PartitionKey::Target);
+//! Builder::with("info").add_sink(Box::new(kafka)).init();
+//! ```
+//!
+//! [kafka]: https://kafka.apache.org/
+
+use std::io;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use kafka::client::RequiredAcks;
+use kafka::producer::{Producer, Record as KafkaRecord};
+use log::Record;
+
+use crate::batch::{BatchConfig, Batcher};
+use crate::fmt::{Format, Formatter};
+use crate::sink::Sink;
+use crate::writer;
+
+/// What the partition key of published messages is derived from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionKey {
+    /// No key, messages are spread over partitions in a round-robin fashion.
+    None,
+    /// The record target.
+    Target,
+    /// The record level.
+    Level,
+}
+
+/// A [`Sink`] publishing records to a Kafka topic.
+pub struct Kafka {
+    hosts: Vec<String>,
+    topic: String,
+    partition_key: PartitionKey,
+    required_acks: RequiredAcks,
+    formatter: Formatter,
+    batch: BatchConfig,
+    batcher: OnceLock<io::Result<Batcher<Message>>>,
+}
+
+struct Message {
+    key: String,
+    value: String,
+}
+
+impl Kafka {
+    /// Creates a sink publishing to `topic` on the cluster reachable through `hosts`.
+    pub fn new(hosts: &[&str], topic: &str) -> Kafka {
+        Kafka {
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            topic: topic.to_string(),
+            partition_key: PartitionKey::None,
+            required_acks: RequiredAcks::One,
+            formatter: Formatter::new(Format::Json, true),
+            batch: BatchConfig {
+                size: 100,
+                wait: Duration::from_millis(500),
+            },
+            batcher: OnceLock::new(),
+        }
+    }
+
+    /// Sets what the partition key is derived from, none by default.
+    pub fn partition_key(mut self, partition_key: PartitionKey) -> Kafka {
+        self.partition_key = partition_key;
+        self
+    }
+
+    /// Sets the acknowledgement required from brokers, one by default.
+    pub fn required_acks(mut self, required_acks: RequiredAcks) -> Kafka {
+        self.required_acks = required_acks;
+        self
+    }
+
+    /// Sets the number of records published at once, 100 by default.
+    pub fn batch_size(mut self, batch_size: usize) -> Kafka {
+        self.batch.size = batch_size.max(1);
+        self
+    }
+
+    /// Sets for how long records are held before an incomplete batch is published.
+    pub fn batch_wait(mut self, batch_wait: Duration) -> Kafka {
+        self.batch.wait = batch_wait;
+        self
+    }
+
+    /// Starts the background thread on first use.
+    fn batcher(&self) -> io::Result<&Batcher<Message>> {
+        self.batcher
+            .get_or_init(|| {
+                let hosts = self.hosts.clone();
+                let topic = self.topic.clone();
+                let required_acks = self.required_acks;
+                let mut producer: Option<Producer> = None;
+                Batcher::spawn("kafka", self.batch, move |messages: &[Message]| {
+                    let producer = match &mut producer {
+                        Some(producer) => producer,
+                        None => producer.insert(
+                            Producer::from_hosts(hosts.clone())
+                                .with_required_acks(required_acks)
+                                .create()
+                                .map_err(io::Error::other)?,
+                        ),
+                    };
+                    let records: Vec<_> = messages
+                        .iter()
+                        .map(|m| {
+                            KafkaRecord::from_key_value(
+                                &topic,
+                                m.key.as_bytes(),
+                                m.value.as_bytes(),
+                            )
+                        })
+                        .collect();
+                    producer
+                        .send_all(&records)
+                        .map(|_| ())
+                        .map_err(io::Error::other)
+                })
+            })
+            .as_ref()
+            .map_err(|e| io::Error::new(e.kind(), e.to_string()))
+    }
+}
+
+impl Sink for Kafka {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let mut value = writer::with_plain_buffer(|buf| {
+            self.formatter.write(buf, record)?;
+            Ok::<_, io::Error>(String::from_utf8_lossy(buf.as_slice()).into_owned())
+        })?;
+        value.truncate(value.trim_end().len());
+
+        let key = match self.partition_key {
+            PartitionKey::None => String::new(),
+            PartitionKey::Target => record.target().to_string(),
+            PartitionKey::Level => record.level().to_string(),
+        };
+        self.batcher()?.send(Message { key, value })
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.batcher()?.flush();
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! Access to the structured key-values attached to records.
+
+use std::cell::Cell;
+
+use log::kv::{Error, Key, Source, Value, VisitSource};
+use log::Record;
+
+use crate::json;
+use crate::mdc;
+
+/// Collects the diagnostic context and the key-values of a record, rendered as strings, in the
+/// order they were attached.
+pub(crate) fn collect(record: &Record) -> Vec<(String, String)> {
+    struct Collect(Vec<(String, String)>);
+
+    impl<'kvs> VisitSource<'kvs> for Collect {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+            self.0.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    let mut visitor = Collect(Vec::new());
+    for_each_context(|key, value| visitor.0.push((key.to_string(), value.to_string())));
+    let _ = record.key_values().visit(&mut visitor);
+    visitor.0
+}
+
+/// The key-values of a record, preceded by a counter stamped by the logger, such as the
+/// sequence number.
+pub(crate) struct Stamped<'a> {
+    pub(crate) key: &'static str,
+    pub(crate) value: u64,
+    pub(crate) source: &'a dyn Source,
+}
+
+impl Source for Stamped<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        visitor.visit_pair(Key::from_str(self.key), Value::from(self.value))?;
+        self.source.visit(visitor)
+    }
+}
+
+/// The key-values of a record, preceded by the diagnostic context it was logged in, for records
+/// written with the context left out, see [`without_context`].
+pub(crate) struct WithContext<'a> {
+    pub(crate) context: &'a [(String, String)],
+    pub(crate) source: &'a dyn Source,
+}
+
+impl Source for WithContext<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        for (key, value) in self.context {
+            visitor.visit_pair(Key::from_str(key), Value::from(value.as_str()))?;
+        }
+        self.source.visit(visitor)
+    }
+}
+
+thread_local! {
+    /// Whether the diagnostic context is left out, while writing records which captured their
+    /// own.
+    static CONTEXT_HIDDEN: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` with the diagnostic context of the current thread and task left out of records.
+pub(crate) fn without_context<R>(f: impl FnOnce() -> R) -> R {
+    let previous = CONTEXT_HIDDEN.with(|hidden| hidden.replace(true));
+    let result = f();
+    CONTEXT_HIDDEN.with(|hidden| hidden.set(previous));
+    result
+}
+
+/// Calls `f` with each pair of the thread-local and task-local diagnostic contexts.
+pub(crate) fn for_each_context(mut f: impl FnMut(&str, &str)) {
+    if CONTEXT_HIDDEN.try_with(Cell::get).unwrap_or(false) {
+        return;
+    }
+    mdc::for_each(&mut f);
+    #[cfg(feature = "tokio")]
+    crate::task::for_each(&mut f);
+}
+
+/// Collects the pairs of the diagnostic contexts, see [`for_each_context`].
+pub(crate) fn context() -> Vec<(String, String)> {
+    let mut context = Vec::new();
+    for_each_context(|key, value| context.push((key.to_string(), value.to_string())));
+    context
+}
+
+/// Collects the causes of the errors attached as key-values, following their `source()` chain.
+pub(crate) fn error_causes(record: &Record) -> Vec<String> {
+    struct Causes(Vec<String>);
+
+    impl<'kvs> VisitSource<'kvs> for Causes {
+        fn visit_pair(&mut self, _: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+            let mut source = value.to_borrowed_error().and_then(|e| e.source());
+            while let Some(cause) = source {
+                self.0.push(cause.to_string());
+                source = cause.source();
+            }
+            Ok(())
+        }
+    }
+
+    let mut visitor = Causes(Vec::new());
+    let _ = record.key_values().visit(&mut visitor);
+    visitor.0
+}
+
+/// Renders the key-values of a record as a JSON object, or `None` if there are none.
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub(crate) fn to_json(record: &Record) -> Option<String> {
+    let mut out = String::new();
+    write_json_fields(&mut out, record, &[]);
+    if out.is_empty() {
+        return None;
+    }
+    out.replace_range(..1, "{");
+    out.push('}');
+    Some(out)
+}
+
+/// Appends the diagnostic context and the key-values of a record as `,"key":value` JSON fields,
+/// skipping the keys already used by the surrounding object.
+pub(crate) fn write_json_fields(out: &mut String, record: &Record, reserved: &[&str]) {
+    struct Fields<'a> {
+        out: &'a mut String,
+        reserved: &'a [&'a str],
+    }
+
+    impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+            if !self.reserved.contains(&key.as_str()) {
+                self.out.push(',');
+                json::write_str(self.out, key.as_str());
+                self.out.push(':');
+                write_json_value(self.out, &value);
+            }
+            Ok(())
+        }
+    }
+
+    for_each_context(|key, value| {
+        if !reserved.contains(&key) {
+            out.push(',');
+            json::write_str(out, key);
+            out.push(':');
+            json::write_str(out, value);
+        }
+    });
+    let _ = record.key_values().visit(&mut Fields { out, reserved });
+}
+
+/// Appends a value serialized through serde, so structures become nested objects and arrays.
+#[cfg(feature = "serde")]
+fn write_json_value(out: &mut String, value: &Value) {
+    match serde_json::to_string(value) {
+        Ok(json) => out.push_str(&json),
+        Err(_) => json::write_str(out, &value.to_string()),
+    }
+}
+
+/// Appends a value as a JSON boolean, number or string.
+#[cfg(not(feature = "serde"))]
+fn write_json_value(out: &mut String, value: &Value) {
+    if let Some(b) = value.to_bool() {
+        out.push_str(if b { "true" } else { "false" });
+    } else if let Some(n) = value.to_i64() {
+        out.push_str(&n.to_string());
+    } else if let Some(n) = value.to_u64() {
+        out.push_str(&n.to_string());
+    } else if let Some(n) = value.to_f64().filter(|n| n.is_finite()) {
+        out.push_str(&n.to_string());
+    } else {
+        json::write_str(out, &value.to_string());
+    }
+}
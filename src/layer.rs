@@ -0,0 +1,160 @@
+//! Hooks enriching records before they are formatted.
+
+use log::kv::{Error, Key, Source, Value, VisitSource};
+use log::{Level, Record};
+
+/// A hook run on every record reaching the logger, before it is held, captured or formatted.
+pub(crate) type Layer = dyn Fn(&mut RecordView) + Send + Sync;
+
+/// A record as seen by the hooks added with [`Builder::layer`](crate::Builder::layer).
+///
+/// Hooks can rewrite the message and attach key-values, which every format and sink then
+/// receives after the key-values of the original record.
+///
+/// ```
+/// use pretty_flexible_env_logger::Builder;
+///
+/// Builder::with("info")
+///     .layer(|record| {
+///         if record.target().starts_with("billing") {
+///             record.insert("team", "payments");
+///         }
+///     })
+///     .init();
+/// ```
+#[derive(Debug)]
+pub struct RecordView {
+    level: Level,
+    target: String,
+    message: String,
+    fields: Vec<(String, String)>,
+    /// Replacements of the values of the original key-values, by position.
+    overrides: Vec<(usize, String)>,
+}
+
+impl RecordView {
+    pub(crate) fn new(record: &Record) -> RecordView {
+        RecordView {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            fields: Vec::new(),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Replaces the values of the original key-values of `record` for which `f` returns a new
+    /// value, and the ones attached by hooks.
+    pub(crate) fn map_values(&mut self, record: &Record, f: impl Fn(&str, &str) -> Option<String>) {
+        struct Map<'a, F> {
+            f: F,
+            index: usize,
+            overrides: &'a mut Vec<(usize, String)>,
+        }
+
+        impl<'kvs, F: Fn(&str, &str) -> Option<String>> VisitSource<'kvs> for Map<'_, F> {
+            fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+                if let Some(value) = (self.f)(key.as_str(), &value.to_string()) {
+                    self.overrides.retain(|(index, _)| *index != self.index);
+                    self.overrides.push((self.index, value));
+                }
+                self.index += 1;
+                Ok(())
+            }
+        }
+
+        let _ = record.key_values().visit(&mut Map {
+            f: &f,
+            index: 0,
+            overrides: &mut self.overrides,
+        });
+        for (key, value) in &mut self.fields {
+            if let Some(new) = f(key, value) {
+                *value = new;
+            }
+        }
+    }
+
+    /// Writes the record with the changes made by the hooks through `f`.
+    pub(crate) fn apply(&self, record: &Record, f: impl FnOnce(&Record)) {
+        let kvs = Extended {
+            source: record.key_values(),
+            fields: &self.fields,
+            overrides: &self.overrides,
+        };
+        f(&record
+            .to_builder()
+            .args(format_args!("{}", self.message))
+            .key_values(&kvs)
+            .build())
+    }
+
+    /// The level of the record.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// The target of the record.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// The message of the record, as rewritten by the previous hooks.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Replaces the message of the record.
+    pub fn set_message(&mut self, message: impl Into<String>) {
+        self.message = message.into();
+    }
+
+    /// Attaches a key-value to the record.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl ToString) {
+        self.fields.push((key.into(), value.to_string()));
+    }
+
+    /// The key-values attached by the hooks so far.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// The key-values of a record, with some of their values replaced, followed by the ones
+/// attached by hooks.
+struct Extended<'a> {
+    source: &'a dyn Source,
+    fields: &'a [(String, String)],
+    overrides: &'a [(usize, String)],
+}
+
+impl Source for Extended<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        struct Override<'a, 'kvs> {
+            visitor: &'a mut dyn VisitSource<'kvs>,
+            overrides: &'kvs [(usize, String)],
+            index: usize,
+        }
+
+        impl<'kvs> VisitSource<'kvs> for Override<'_, 'kvs> {
+            fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+                let value = match self.overrides.iter().find(|(i, _)| *i == self.index) {
+                    Some((_, value)) => Value::from(value.as_str()),
+                    None => value,
+                };
+                self.index += 1;
+                self.visitor.visit_pair(key, value)
+            }
+        }
+
+        self.source.visit(&mut Override {
+            visitor,
+            overrides: self.overrides,
+            index: 0,
+        })?;
+        for (key, value) in self.fields {
+            visitor.visit_pair(Key::from_str(key), Value::from(value.as_str()))?;
+        }
+        Ok(())
+    }
+}
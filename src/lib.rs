@@ -32,15 +32,72 @@
 //! The defaults can be setup by calling [init()][init] or [try_init()][try_init] at the start
 //! of the program.
 //!
+//! ## Builder
+//!
+//! For more control over where and how records are written, use the [Builder][Builder]:
+//! it resolves the directives the same way as [init_with()][init_with], and can send the
+//! output to any `Write` implementation.
+//!
 //! ## Enable logging
 //!
 //! This crate uses [pretty_env_logger][] internally, so the same ways of enabling
 //! logs through an environment variable are supported.
 //!
+//! ## Optional features
+//!
+//! Additional sinks are available behind cargo features:
+//!
+//! * `syslog` - a local or remote syslog daemon,
+//! * `journald` - the systemd journal, with structured fields,
+//! * `eventlog` - the Windows Event Log,
+//! * `oslog` - Apple's unified logging system,
+//! * `wasm` - the browser console, used by default on `wasm32` targets,
+//! * `net` - a log collector listening on TCP, UDP or a Unix socket,
+//! * `tls` - a log collector listening on TCP, over TLS,
+//! * `loki` - Grafana Loki, through its HTTP push API,
+//! * `gelf` - Graylog, over UDP or TCP,
+//! * `cloudwatch` - AWS CloudWatch Logs,
+//! * `otel` - an OpenTelemetry collector, over OTLP,
+//! * `kafka` - an Apache Kafka topic,
+//! * `mqtt` - an MQTT topic, through the application's own client,
+//! * `sqlite` - a local SQLite database.
+//!
+//! With the `tokio` feature, a diagnostic context can be attached to async tasks, see the
+//! `task` module.
+//!
+//! With the `tracing` feature, events of the `tracing` crate can be written through the logger,
+//! see the `tracing` module.
+//!
+//! With the `slog` feature, records of the `slog` crate can be written through the logger, see
+//! the `slog` module.
+//!
+//! With the `sentry` feature, errors can be reported to Sentry, with the records leading to them
+//! as breadcrumbs, see the `sentry` module.
+//!
+//! With the `anyhow` feature, [`log_error!`] writes the backtraces captured by `anyhow` errors.
+//!
+//! With the `indicatif` feature, records can be written above progress bars without garbling
+//! them, see `Builder::progress_bars`.
+//!
+//! With the `redact` feature, secrets can be scrubbed out of records with regular expressions,
+//! see [`Builder::redact`].
+//!
+//! With the `config` feature, the logger can be configured from a TOML file describing
+//! several appenders, each with its own destination, format and filters, see the `config`
+//! module.
+//!
+//! With the `macros` feature, `#[pretty_log_test]` turns functions into tests initializing the
+//! logger first, in [test mode](Builder::is_test).
+//!
+//! With the `serde` feature, key-values captured with `:serde` in the `log` macros are written
+//! to the JSON formats as nested objects and arrays.
+//!
 //! [env_logger]: https://docs.rs/env_logger
 //! [pretty_env_logger]: https://docs.rs/pretty_env_logger
 //! [init]: [pretty_flexible_env_logger::init]
 //! [try_init]: [pretty_flexible_env_logger::try_init]
+//! [init_with]: [pretty_flexible_env_logger::init_with]
+//! [Builder]: [pretty_flexible_env_logger::Builder]
 
 #[doc(hidden)]
 pub use pretty_env_logger;
@@ -48,8 +105,109 @@ pub use pretty_env_logger;
 #[doc(hidden)]
 pub use pretty_env_logger::env_logger;
 
+#[doc(hidden)]
+pub use log as __log;
+
+mod backtrace;
+#[cfg(any(
+    feature = "loki",
+    feature = "cloudwatch",
+    feature = "kafka",
+    feature = "otel"
+))]
+mod batch;
+mod build_info;
+mod builder;
+mod capture;
+#[cfg(feature = "cloudwatch")]
+pub mod cloudwatch;
+#[cfg(feature = "config")]
+pub mod config;
+mod escape;
+#[cfg(all(windows, feature = "eventlog"))]
+pub mod eventlog;
+mod explain;
+mod fmt;
+#[cfg(feature = "gelf")]
+pub mod gelf;
+pub mod group;
+#[cfg(any(feature = "loki", feature = "cloudwatch", feature = "otel"))]
+mod http;
+#[cfg(all(unix, feature = "journald"))]
+pub mod journald;
+mod json;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+mod kv;
+mod layer;
+#[cfg(feature = "loki")]
+pub mod loki;
+mod mask;
+pub mod mdc;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(all(target_vendor = "apple", feature = "oslog"))]
+pub mod oslog;
+#[cfg(feature = "otel")]
+pub mod otel;
+mod output;
+mod owned;
+mod panic;
+mod process;
+mod recorder;
+#[cfg(feature = "redact")]
+mod redact;
+#[doc(hidden)]
+pub mod report;
+mod ring;
+mod scope;
+#[cfg(feature = "sentry")]
+pub mod sentry;
+mod sink;
+#[cfg(feature = "slog")]
+pub mod slog;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+mod stats;
+#[cfg(feature = "syslog")]
+pub mod syslog;
+#[cfg(feature = "tokio")]
+pub mod task;
+pub mod template;
+mod thread_level;
+mod throttle;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+pub mod wasm;
+mod worker;
+mod writer;
+
+pub use build_info::BuildInfo;
+pub use builder::{flush, shutdown, swap, Builder, Logger, LoggerGuard};
+pub use capture::{capture, Capture, CapturedRecord};
+#[cfg(feature = "config")]
+pub use config::ConfigError;
+pub use env_logger::WriteStyle;
+pub use explain::{explain, ConfigReport};
+pub use fmt::{Column, Format, Multiline, TargetWidth};
+pub use layer::RecordView;
+pub use output::Output;
+pub use recorder::FlightRecorder;
+pub use ring::RingBuffer;
+pub use scope::with_scope;
+pub use sink::{Buffering, Sink};
+pub use stats::{stats, LogStats};
+pub use template::TemplateError;
+pub use thread_level::{reset_thread_level, scoped, set_thread_level, ScopedGuard};
+pub use worker::Overflow;
+
+#[cfg(feature = "macros")]
+pub use pretty_flexible_env_logger_macros::pretty_log_test;
+
 use log::SetLoggerError;
-use pretty_env_logger::{formatted_builder, formatted_timed_builder};
 
 /// Initializes default global logger.
 ///
@@ -94,6 +252,22 @@ pub fn try_init() -> Result<(), SetLoggerError> {
     try_init_with("RUST_LOG")
 }
 
+/// Tries to initialize default global logger, returning a guard which flushes and shuts it down
+/// when dropped.
+///
+/// This should be called early in the execution of a Rust program, and the
+/// global logger may only be initialized once. Future initialization attempts
+/// will return an error.
+///
+/// It defaults to using settings from `RUST_LOG` environment variable
+///
+/// # Errors
+///
+/// This function fails to set the global logger if one has already been set.
+pub fn try_init_guarded() -> Result<LoggerGuard, SetLoggerError> {
+    try_init_with_guarded("RUST_LOG")
+}
+
 /// Tries to initialize default global logger with timed entries.
 ///
 /// This should be called early in the execution of a Rust program, and the
@@ -116,7 +290,7 @@ pub fn try_init_timed() -> Result<(), SetLoggerError> {
 /// # Arguments
 ///
 /// * `environment_or_inline_value` - A string slice that holds the name of environment variable, or
-///    the directives string in the same form as the `RUST_LOG` environment variable.
+///   the directives string in the same form as the `RUST_LOG` environment variable.
 ///
 /// # Panics
 ///
@@ -134,7 +308,7 @@ pub fn init_with(environment_or_inline_value: &str) {
 /// # Arguments
 ///
 /// * `environment_or_inline_value` - A string slice that holds the name of environment variable, or
-///    the directives string in the same form as the `RUST_LOG` environment variable.
+///   the directives string in the same form as the `RUST_LOG` environment variable.
 ///
 /// # Errors
 ///
@@ -152,19 +326,130 @@ pub fn init_timed_with(environment_or_inline_value: &str) {
 /// # Arguments
 ///
 /// * `environment_or_inline_value` - A string slice that holds the name of environment variable, or
-///    the directives string in the same form as the `RUST_LOG` environment variable.
+///   the directives string in the same form as the `RUST_LOG` environment variable.
 ///
 /// # Errors
 ///
 /// This function fails to set the global logger if one has already been set.
 pub fn try_init_with(environment_or_inline_value: &str) -> Result<(), SetLoggerError> {
-    let value = match ::std::env::var(environment_or_inline_value) {
-        Ok(s) => Some(s),
-        Err(_) => Some(environment_or_inline_value.to_string()),
-    };
+    let value = Some(resolve(environment_or_inline_value));
     try_init_custom_string(value)
 }
 
+/// Tries to initialize the global logger with a custom configuration, letting `customize`
+/// adjust the underlying `env_logger` builder first.
+///
+/// The directives are resolved as with [`try_init_with`], and parsed into the builder of
+/// [pretty_env_logger][] before it is handed to `customize`, which can then change any of its
+/// options, such as the format, the target or further filters. The logger is then the one of
+/// `env_logger`: the options of [`Builder`] and its sinks do not apply to it, and the functions
+/// of this crate acting on the logger it installs have no effect:
+///
+/// - [`flush`], [`shutdown`] and dropping a [`LoggerGuard`] do nothing, and [`swap`] fails as
+///   a logger is set already;
+/// - [`capture`] captures no record, and the filters of [`set_thread_level`] and [`scoped`] are
+///   not applied, although raising the level of a thread still raises the global maximum;
+/// - [`stats`] returns empty counters;
+/// - the [`mdc`] context, the [`group`](mod@group) indentation and the scopes of
+///   [`with_scope`] are not written.
+///
+/// ```
+/// use log::LevelFilter;
+/// use pretty_flexible_env_logger::env_logger::Target;
+///
+/// pretty_flexible_env_logger::try_init_with_customizer("info", |builder| {
+///     builder
+///         .filter_module("hyper", LevelFilter::Warn)
+///         .target(Target::Stdout);
+/// })
+/// .unwrap();
+/// ```
+///
+/// # Arguments
+///
+/// * `environment_or_inline_value` - A string slice that holds the name of environment variable, or
+///   the directives string in the same form as the `RUST_LOG` environment variable.
+/// * `customize` - A function adjusting the `env_logger` builder before the logger is set.
+///
+/// # Errors
+///
+/// This function fails to set the global logger if one has already been set.
+pub fn try_init_with_customizer<F>(
+    environment_or_inline_value: &str,
+    customize: F,
+) -> Result<(), SetLoggerError>
+where
+    F: FnOnce(&mut env_logger::Builder),
+{
+    let mut builder = pretty_env_logger::formatted_builder();
+    builder.parse_filters(&resolve(environment_or_inline_value));
+    customize(&mut builder);
+    builder.try_init()
+}
+
+/// Tries to initialize the global logger from an `env_logger` builder the caller configured,
+/// adding the directives resolved from `environment_or_inline_value` to its filters.
+///
+/// The directives are resolved as with [`try_init_with`], and take precedence over the filters
+/// already set for the same modules. As with [`try_init_with_customizer`], the logger is then
+/// the one of `env_logger`, writing in the format of the builder, and the functions of this
+/// crate listed there, from [`flush`] and [`swap`] to [`capture`] and [`stats`], have no effect
+/// on it.
+///
+/// ```
+/// use pretty_flexible_env_logger::env_logger::Builder;
+///
+/// let mut builder = Builder::new();
+/// builder.format_timestamp(None).format_module_path(false);
+/// pretty_flexible_env_logger::try_init_from_builder(builder, "info").unwrap();
+/// ```
+///
+/// # Arguments
+///
+/// * `builder` - The `env_logger` builder the logger is built from.
+/// * `environment_or_inline_value` - A string slice that holds the name of environment variable, or
+///   the directives string in the same form as the `RUST_LOG` environment variable.
+///
+/// # Errors
+///
+/// This function fails to set the global logger if one has already been set.
+pub fn try_init_from_builder(
+    mut builder: env_logger::Builder,
+    environment_or_inline_value: &str,
+) -> Result<(), SetLoggerError> {
+    builder.parse_filters(&resolve(environment_or_inline_value));
+    builder.try_init()
+}
+
+/// Tries to initialize the global logger with a custom configuration, returning a guard which
+/// flushes and shuts it down when dropped.
+///
+/// Keeping the guard alive until the end of `main` makes sure no record is left behind in a
+/// buffer or queue when the program exits.
+///
+/// ```
+/// let _guard = pretty_flexible_env_logger::try_init_with_guarded("info").unwrap();
+///
+/// log::info!("written before the program exits");
+/// ```
+///
+/// # Arguments
+///
+/// * `environment_or_inline_value` - A string slice that holds the name of environment variable, or
+///   the directives string in the same form as the `RUST_LOG` environment variable.
+///
+/// # Errors
+///
+/// This function fails to set the global logger if one has already been set.
+pub fn try_init_with_guarded(
+    environment_or_inline_value: &str,
+) -> Result<LoggerGuard, SetLoggerError> {
+    let value = resolve(environment_or_inline_value);
+    let mut builder = Builder::empty();
+    builder.parse_filters(&value);
+    builder.try_init_guarded()
+}
+
 /// Tries to initialize the timed global logger with a custom configuration.
 ///
 /// This should be called early in the execution of a Rust program, and the
@@ -174,19 +459,53 @@ pub fn try_init_with(environment_or_inline_value: &str) -> Result<(), SetLoggerE
 /// # Arguments
 ///
 /// * `environment_or_inline_value` - A string slice that holds the name of environment variable, or
-///    the directives string in the same form as the `RUST_LOG` environment variable.
+///   the directives string in the same form as the `RUST_LOG` environment variable.
 ///
 /// # Errors
 ///
 /// This function fails to set the global logger if one has already been set.
 pub fn try_init_timed_with(environment_or_inline_value: &str) -> Result<(), log::SetLoggerError> {
-    let value = match ::std::env::var(environment_or_inline_value) {
-        Ok(s) => Some(s),
-        Err(_) => Some(environment_or_inline_value.to_string()),
-    };
+    let value = Some(resolve(environment_or_inline_value));
     try_init_timed_custom_string(value)
 }
 
+/// Looks up the value as an environment variable, falling back to treating it as inline directives.
+fn resolve(environment_or_inline_value: &str) -> String {
+    match ::std::env::var(environment_or_inline_value) {
+        Ok(s) => s,
+        Err(_) => environment_or_inline_value.to_string(),
+    }
+}
+
+/// Tells where [`resolve`] takes the directives from, in words.
+fn describe_source(environment_or_inline_value: &str) -> String {
+    let value = environment_or_inline_value;
+    if ::std::env::var(value).is_ok() {
+        return format!("the `{}` environment variable", value);
+    }
+    let looks_like_variable = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    if looks_like_variable {
+        format!(
+            "inline directives, as the `{}` environment variable is not set",
+            value
+        )
+    } else {
+        "inline directives".to_string()
+    }
+}
+
+/// Installs a logger following `RUST_LOG` in test mode unless there is one already, for
+/// `#[pretty_log_test]`.
+#[doc(hidden)]
+pub fn __init_test() {
+    if builder::installed().is_none() {
+        let _ = Builder::new().is_test(true).try_init();
+    }
+}
+
 /// Tries to initialize the global logger with custom filtering directives.
 ///
 /// This should be called early in the execution of a Rust program, and the
@@ -201,7 +520,7 @@ pub fn try_init_timed_with(environment_or_inline_value: &str) -> Result<(), log:
 ///
 /// This function fails to set the global logger if one has already been set.
 pub fn try_init_custom_string(filters: Option<String>) -> Result<(), SetLoggerError> {
-    let mut builder = formatted_builder();
+    let mut builder = Builder::empty();
 
     if let Some(s) = filters {
         builder.parse_filters(&s);
@@ -224,7 +543,8 @@ pub fn try_init_custom_string(filters: Option<String>) -> Result<(), SetLoggerEr
 ///
 /// This function fails to set the global logger if one has already been set.
 pub fn try_init_timed_custom_string(filters: Option<String>) -> Result<(), SetLoggerError> {
-    let mut builder = formatted_timed_builder();
+    let mut builder = Builder::empty();
+    builder.timed(true);
 
     if let Some(s) = filters {
         builder.parse_filters(&s);
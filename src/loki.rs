@@ -0,0 +1,183 @@
+//! Pushing records to [Grafana Loki][loki].
+//!
+//! Records are batched on a background thread and sent to Loki's push API, either when the
+//! batch is full or when the oldest record in it waited long enough. Each batch is split into
+//! streams by level, so records carry a `level` label in addition to the configured ones.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::loki::Loki;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let loki = Loki::new("http://loki.internal:3100")
+//!     .label("service", "ingestd")
+//!     .label("env", "production");
+//! Builder::with("info").add_sink(Box::new(loki)).init();
+//! ```
+//!
+//! [loki]: https://grafana.com/oss/loki/
+
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::OnceLock;
+use std::time::{Duration, UNIX_EPOCH};
+
+use log::{Level, Record};
+
+use crate::batch::{BatchConfig, Batcher};
+use crate::http;
+use crate::json::write_str;
+use crate::sink::Sink;
+
+const PUSH_PATH: &str = "/loki/api/v1/push";
+
+/// A [`Sink`] pushing batches of records to Loki.
+pub struct Loki {
+    url: String,
+    labels: Vec<(String, String)>,
+    batch: BatchConfig,
+    batcher: OnceLock<io::Result<Batcher<Entry>>>,
+}
+
+struct Entry {
+    level: Level,
+    nanos: u128,
+    line: String,
+}
+
+impl Loki {
+    /// Creates a sink pushing to the Loki instance at `url`, e.g. `http://localhost:3100`.
+    pub fn new(url: &str) -> Loki {
+        Loki {
+            url: format!("{}{}", url.trim_end_matches('/'), PUSH_PATH),
+            labels: Vec::new(),
+            batch: BatchConfig {
+                size: 100,
+                wait: Duration::from_secs(1),
+            },
+            batcher: OnceLock::new(),
+        }
+    }
+
+    /// Adds a label attached to every stream.
+    pub fn label(mut self, name: &str, value: &str) -> Loki {
+        self.labels.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the number of records sent in a single request, 100 by default.
+    pub fn batch_size(mut self, batch_size: usize) -> Loki {
+        self.batch.size = batch_size.max(1);
+        self
+    }
+
+    /// Sets for how long records are held before being sent in an incomplete batch, one second
+    /// by default.
+    pub fn batch_wait(mut self, batch_wait: Duration) -> Loki {
+        self.batch.wait = batch_wait;
+        self
+    }
+
+    /// Starts the background thread on first use.
+    fn batcher(&self) -> io::Result<&Batcher<Entry>> {
+        self.batcher
+            .get_or_init(|| {
+                let url = self.url.clone();
+                let labels = self.labels.clone();
+                Batcher::spawn("loki", self.batch, move |entries| {
+                    let body = render(&labels, entries);
+                    http::post(&url, "application/json", &[], body.as_bytes())
+                })
+            })
+            .as_ref()
+            .map_err(|e| io::Error::new(e.kind(), e.to_string()))
+    }
+}
+
+impl Sink for Loki {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let nanos = crate::fmt::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        let entry = Entry {
+            level: record.level(),
+            nanos,
+            line: format!("{} > {}", record.target(), record.args()),
+        };
+        self.batcher()?.send(entry)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.batcher()?.flush();
+        Ok(())
+    }
+}
+
+/// Renders the push request body, with one stream per level.
+fn render(labels: &[(String, String)], entries: &[Entry]) -> String {
+    let mut streams: BTreeMap<Level, Vec<&Entry>> = BTreeMap::new();
+    for entry in entries {
+        streams.entry(entry.level).or_default().push(entry);
+    }
+
+    let mut body = String::from("{\"streams\":[");
+    for (i, (level, entries)) in streams.iter().enumerate() {
+        if i > 0 {
+            body.push(',');
+        }
+        body.push_str("{\"stream\":{");
+        for (name, value) in labels {
+            write_str(&mut body, name);
+            body.push(':');
+            write_str(&mut body, value);
+            body.push(',');
+        }
+        body.push_str("\"level\":");
+        write_str(&mut body, &level.as_str().to_lowercase());
+        body.push_str("},\"values\":[");
+        for (j, entry) in entries.iter().enumerate() {
+            if j > 0 {
+                body.push(',');
+            }
+            body.push('[');
+            write_str(&mut body, &entry.nanos.to_string());
+            body.push(',');
+            write_str(&mut body, &entry.line);
+            body.push(']');
+        }
+        body.push_str("]}");
+    }
+    body.push_str("]}");
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: Level, nanos: u128, line: &str) -> Entry {
+        Entry {
+            level,
+            nanos,
+            line: line.to_string(),
+        }
+    }
+
+    #[test]
+    fn renders_a_stream_per_level() {
+        let labels = [("service".to_string(), "ingestd".to_string())];
+        let entries = [
+            entry(Level::Info, 1, "app > started"),
+            entry(Level::Warn, 2, "app > \"slow\" disk"),
+            entry(Level::Info, 3, "app > ready"),
+        ];
+        assert_eq!(
+            render(&labels, &entries),
+            "{\"streams\":[\
+             {\"stream\":{\"service\":\"ingestd\",\"level\":\"warn\"},\
+             \"values\":[[\"2\",\"app > \\\"slow\\\" disk\"]]},\
+             {\"stream\":{\"service\":\"ingestd\",\"level\":\"info\"},\
+             \"values\":[[\"1\",\"app > started\"],[\"3\",\"app > ready\"]]}]}"
+        );
+        assert_eq!(render(&[], &[]), "{\"streams\":[]}");
+    }
+}
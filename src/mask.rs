@@ -0,0 +1,84 @@
+//! Hiding the values of sensitive key-values.
+
+use log::kv::{Error, Key, Value, VisitSource};
+use log::Record;
+
+use crate::kv;
+use crate::layer::RecordView;
+
+/// Keys masked unless configured otherwise.
+const DEFAULT_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "authorization",
+    "cookie",
+];
+
+/// What masked values are replaced with.
+const MASK: &str = "***";
+
+/// Parts of keys whose values are masked, matched case-insensitively anywhere in the key.
+#[derive(Clone, Debug)]
+pub(crate) struct Masks(Vec<String>);
+
+impl Default for Masks {
+    fn default() -> Self {
+        Masks::new(DEFAULT_KEYS)
+    }
+}
+
+impl Masks {
+    pub(crate) fn new(keys: &[&str]) -> Masks {
+        Masks(keys.iter().map(|key| key.to_ascii_lowercase()).collect())
+    }
+
+    fn is_sensitive(&self, key: &str) -> bool {
+        self.0.iter().any(|part| {
+            key.as_bytes()
+                .windows(part.len())
+                .any(|window| window.eq_ignore_ascii_case(part.as_bytes()))
+        })
+    }
+
+    /// Whether any key-value of the record has to be masked.
+    pub(crate) fn matches(&self, record: &Record) -> bool {
+        struct Find<'a>(&'a Masks, bool);
+
+        impl<'kvs> VisitSource<'kvs> for Find<'_> {
+            fn visit_pair(&mut self, key: Key<'kvs>, _: Value<'kvs>) -> Result<(), Error> {
+                self.1 |= self.0.is_sensitive(key.as_str());
+                Ok(())
+            }
+        }
+
+        if self.0.is_empty() {
+            return false;
+        }
+        let mut find = Find(self, false);
+        let _ = record.key_values().visit(&mut find);
+        find.1
+    }
+
+    /// Whether any pair of the diagnostic context has to be masked.
+    pub(crate) fn matches_context(&self) -> bool {
+        let mut found = false;
+        if !self.0.is_empty() {
+            kv::for_each_context(|key, _| found |= self.is_sensitive(key));
+        }
+        found
+    }
+
+    /// Masks the sensitive key-values of the record, and the ones attached by hooks.
+    pub(crate) fn mask(&self, view: &mut RecordView, record: &Record) {
+        if self.0.is_empty() {
+            return;
+        }
+        view.map_values(record, |key, _| {
+            self.is_sensitive(key).then(|| MASK.to_string())
+        });
+    }
+}
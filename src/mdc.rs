@@ -0,0 +1,84 @@
+//! A thread-local diagnostic context, attached to every record logged on the thread.
+//!
+//! Pairs inserted in the context are written along with the key-values of each record, which
+//! makes correlating the records of a single request trivial in threaded servers.
+//!
+//! ```
+//! use pretty_flexible_env_logger::mdc;
+//!
+//! pretty_flexible_env_logger::init_with("info");
+//!
+//! let _guard = mdc::scope("request_id", 42);
+//! log::info!("handling request"); // ... request_id=42
+//! ```
+
+use std::cell::RefCell;
+
+thread_local! {
+    static CONTEXT: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Inserts a pair in the context of the current thread, replacing any previous value.
+pub fn insert<V: ToString>(key: &str, value: V) {
+    replace(key, Some(value.to_string()));
+}
+
+/// Removes a pair from the context of the current thread.
+pub fn remove(key: &str) {
+    replace(key, None);
+}
+
+/// Removes all pairs from the context of the current thread.
+pub fn clear() {
+    CONTEXT.with(|context| context.borrow_mut().clear());
+}
+
+/// Inserts a pair in the context of the current thread until the returned guard is dropped,
+/// when the previous value, if any, is restored.
+#[must_use = "the pair is removed when the guard is dropped"]
+pub fn scope<V: ToString>(key: &str, value: V) -> MdcGuard {
+    let previous = replace(key, Some(value.to_string()));
+    MdcGuard {
+        key: key.to_string(),
+        previous,
+    }
+}
+
+/// Restores the context of the current thread when dropped, see [`scope`].
+pub struct MdcGuard {
+    key: String,
+    previous: Option<String>,
+}
+
+impl Drop for MdcGuard {
+    fn drop(&mut self) {
+        replace(&self.key, self.previous.take());
+    }
+}
+
+/// Sets or removes a pair, returning its previous value.
+fn replace(key: &str, value: Option<String>) -> Option<String> {
+    CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        let position = context.iter().position(|(k, _)| k == key);
+        match (position, value) {
+            (Some(i), Some(value)) => Some(std::mem::replace(&mut context[i].1, value)),
+            (Some(i), None) => Some(context.remove(i).1),
+            (None, Some(value)) => {
+                context.push((key.to_string(), value));
+                None
+            }
+            (None, None) => None,
+        }
+    })
+}
+
+/// Calls `f` with each pair in the context of the current thread, in insertion order.
+pub(crate) fn for_each(mut f: impl FnMut(&str, &str)) {
+    // The context may already be destroyed when logging from another thread-local destructor.
+    let _ = CONTEXT.try_with(|context| {
+        for (key, value) in context.borrow().iter() {
+            f(key, value);
+        }
+    });
+}
@@ -0,0 +1,87 @@
+//! Publishing records over an MQTT connection.
+//!
+//! Devices usually already hold a connection to their broker, so instead of opening another
+//! one, this sink hands each record to a callback publishing it through the existing client.
+//! Records are rendered as JSON (see [`Format::Json`](crate::Format::Json)), and the topic may
+//! contain `{level}` and `{target}` placeholders, so subscribers can pick what they listen to.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::mqtt::{Mqtt, QoS};
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! # fn publish(_: &str, _: &[u8], _: u8) -> std::io::Result<()> { Ok(()) }
+//! let mqtt = Mqtt::new("devices/42/logs/{level}", |topic, payload, qos| {
+//!     // e.g. `client.publish(topic, qos.into(), false, payload)` with your MQTT client
+//!     publish(topic, payload, qos as u8)
+//! })
+//! .qos(QoS::AtLeastOnce);
+//! Builder::with("info").add_sink(Box::new(mqtt)).init();
+//! ```
+
+use std::io;
+
+use log::Record;
+
+use crate::fmt::{Format, Formatter};
+use crate::sink::Sink;
+use crate::writer;
+
+/// The MQTT quality of service records are published with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QoS {
+    /// Fire and forget.
+    AtMostOnce = 0,
+    /// Acknowledged delivery, possibly duplicated.
+    AtLeastOnce = 1,
+    /// Assured delivery, exactly once.
+    ExactlyOnce = 2,
+}
+
+type Publish = dyn Fn(&str, &[u8], QoS) -> io::Result<()> + Send + Sync;
+
+/// A [`Sink`] publishing records through a user supplied MQTT client.
+pub struct Mqtt {
+    topic: String,
+    qos: QoS,
+    formatter: Formatter,
+    publish: Box<Publish>,
+}
+
+impl Mqtt {
+    /// Creates a sink publishing to `topic` through the `publish` callback, which receives the
+    /// resolved topic, the payload, and the quality of service.
+    pub fn new<F>(topic: &str, publish: F) -> Mqtt
+    where
+        F: Fn(&str, &[u8], QoS) -> io::Result<()> + Send + Sync + 'static,
+    {
+        Mqtt {
+            topic: topic.to_string(),
+            qos: QoS::AtMostOnce,
+            formatter: Formatter::new(Format::Json, true),
+            publish: Box::new(publish),
+        }
+    }
+
+    /// Sets the quality of service, at most once by default.
+    pub fn qos(mut self, qos: QoS) -> Mqtt {
+        self.qos = qos;
+        self
+    }
+}
+
+impl Sink for Mqtt {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let topic = self
+            .topic
+            .replace("{level}", &record.level().as_str().to_lowercase())
+            .replace("{target}", &record.target().replace("::", "/"));
+
+        writer::with_plain_buffer(|buf| {
+            self.formatter.write(buf, record)?;
+            let payload = buf.as_slice();
+            let payload = payload.strip_suffix(b"\n").unwrap_or(payload);
+
+            (self.publish)(&topic, payload, self.qos)
+        })
+    }
+}
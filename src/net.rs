@@ -0,0 +1,414 @@
+//! Shipping records to a log collector over the network.
+//!
+//! All writers connect lazily, on the first record, and render records in the pretty format
+//! without colors. Stream writers connect on a thread of their own, holding up to 64 KiB of
+//! records meanwhile, and give up on writes blocked for more than five seconds. When the
+//! collector goes away, records are dropped while reconnection is attempted with an exponential
+//! backoff, so a missing collector never stalls the application for long.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! Builder::with("info").write_to_tcp("logs.internal:6514").init();
+//! ```
+
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// The most bytes held while connecting, beyond which records are dropped.
+const MAX_HELD: usize = 64 * 1024;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A `Write` implementation sending records over TCP, reconnecting when the connection drops.
+pub struct TcpWriter {
+    addr: String,
+    #[cfg(feature = "tls")]
+    tls: Option<native_tls::TlsConnector>,
+    conn: Reconnecting,
+}
+
+impl TcpWriter {
+    /// Creates a writer connecting to `addr`, given as `host:port`.
+    pub fn new(addr: &str) -> TcpWriter {
+        TcpWriter {
+            addr: addr.to_string(),
+            #[cfg(feature = "tls")]
+            tls: None,
+            conn: Reconnecting::new(),
+        }
+    }
+
+    /// Creates a writer connecting to `addr` over TLS, verifying the certificate against the
+    /// host name.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the platform TLS implementation cannot be initialized.
+    #[cfg(feature = "tls")]
+    pub fn tls(addr: &str) -> io::Result<TcpWriter> {
+        let connector = native_tls::TlsConnector::new().map_err(io::Error::other)?;
+        let mut writer = TcpWriter::new(addr);
+        writer.tls = Some(connector);
+        Ok(writer)
+    }
+}
+
+impl Write for TcpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let addr = self.addr.clone();
+        #[cfg(feature = "tls")]
+        let tls = self.tls.clone();
+        self.conn.write(buf, move || {
+            let stream = connect(&addr)?;
+            #[cfg(feature = "tls")]
+            if let Some(connector) = tls {
+                let host = addr.rsplit_once(':').map_or(&*addr, |(host, _)| host);
+                let stream = connector.connect(host, stream).map_err(io::Error::other)?;
+                return Ok(Box::new(stream));
+            }
+            Ok(Box::new(stream))
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.flush()
+    }
+}
+
+/// A `Write` implementation sending newline terminated records to a Unix stream socket,
+/// reconnecting when the connection drops.
+///
+/// This suits sidecar and agent architectures, where a local collector listens on a socket.
+#[cfg(unix)]
+pub struct UnixWriter {
+    path: PathBuf,
+    conn: Reconnecting,
+}
+
+#[cfg(unix)]
+impl UnixWriter {
+    /// Creates a writer connecting to the socket at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> UnixWriter {
+        UnixWriter {
+            path: path.as_ref().to_path_buf(),
+            conn: Reconnecting::new(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Write for UnixWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let path = self.path.clone();
+        self.conn.write(buf, move || {
+            let stream = UnixStream::connect(path)?;
+            stream.set_read_timeout(Some(IO_TIMEOUT))?;
+            stream.set_write_timeout(Some(IO_TIMEOUT))?;
+            Ok(Box::new(stream))
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.flush()
+    }
+}
+
+/// A `Write` implementation sending each record as a single UDP datagram.
+pub struct UdpWriter {
+    addr: String,
+    socket: Option<UdpSocket>,
+    backoff: Backoff,
+}
+
+impl UdpWriter {
+    /// Creates a writer sending datagrams to `addr`, given as `host:port`.
+    pub fn new(addr: &str) -> UdpWriter {
+        UdpWriter {
+            addr: addr.to_string(),
+            socket: None,
+            backoff: Backoff::new(),
+        }
+    }
+}
+
+impl Write for UdpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.socket.is_none() {
+            self.backoff.check()?;
+            match udp_socket(&*self.addr) {
+                Ok(socket) => {
+                    self.backoff.reset();
+                    self.socket = Some(socket);
+                }
+                Err(e) => {
+                    self.backoff.fail();
+                    return Err(e);
+                }
+            }
+        }
+        let socket = self.socket.as_ref().expect("opened above");
+        socket.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+type Stream = Box<dyn Write + Send>;
+
+/// A lazily opened stream, opened on a thread of its own and reopened with a backoff after
+/// failures.
+struct Reconnecting {
+    stream: Option<Stream>,
+    /// The stream being opened, if any.
+    connecting: Option<Receiver<io::Result<Stream>>>,
+    /// The bytes written while connecting.
+    held: Vec<u8>,
+    backoff: Backoff,
+}
+
+impl Reconnecting {
+    fn new() -> Reconnecting {
+        Reconnecting {
+            stream: None,
+            connecting: None,
+            held: Vec::new(),
+            backoff: Backoff::new(),
+        }
+    }
+
+    fn write<F>(&mut self, buf: &[u8], open: F) -> io::Result<usize>
+    where
+        F: FnOnce() -> io::Result<Stream> + Send + 'static,
+    {
+        if self.stream.is_none() {
+            if self.connecting.is_none() {
+                self.backoff.check()?;
+                let (sender, receiver) = mpsc::sync_channel(1);
+                let spawned = thread::Builder::new()
+                    .name("log-connect".to_string())
+                    .spawn(move || {
+                        let _ = sender.send(crate::sink::inside(open));
+                    });
+                if let Err(e) = spawned {
+                    self.backoff.fail();
+                    return Err(e);
+                }
+                self.connecting = Some(receiver);
+            }
+            if !self.poll(None)? {
+                if self.held.len() + buf.len() > MAX_HELD {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "still connecting",
+                    ));
+                }
+                self.held.extend_from_slice(buf);
+                return Ok(buf.len());
+            }
+        }
+
+        let stream = self.stream.as_mut().expect("connected above");
+        let r = stream.write(buf);
+        if r.is_err() {
+            self.stream = None;
+            self.backoff.fail();
+        }
+        r
+    }
+
+    /// Checks whether the stream being opened is ready, waiting up to `wait` for it, and writes
+    /// the bytes held meanwhile once it is.
+    fn poll(&mut self, wait: Option<Duration>) -> io::Result<bool> {
+        let Some(connecting) = &self.connecting else {
+            return Ok(self.stream.is_some());
+        };
+        let opened = match wait {
+            Some(wait) => connecting.recv_timeout(wait).map_err(|e| match e {
+                RecvTimeoutError::Timeout => TryRecvError::Empty,
+                RecvTimeoutError::Disconnected => TryRecvError::Disconnected,
+            }),
+            None => connecting.try_recv(),
+        };
+        let opened = match opened {
+            Ok(opened) => opened,
+            Err(TryRecvError::Empty) => return Ok(false),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the connection attempt was abandoned",
+            )),
+        };
+        self.connecting = None;
+        let held = std::mem::take(&mut self.held);
+        let mut stream = match opened {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.backoff.fail();
+                return Err(e);
+            }
+        };
+        if let Err(e) = stream.write_all(&held) {
+            self.backoff.fail();
+            return Err(e);
+        }
+        self.backoff.reset();
+        self.stream = Some(stream);
+        Ok(true)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // The records held would be lost if the program exits next.
+        self.poll(Some(CONNECT_TIMEOUT + IO_TIMEOUT))?;
+        match &mut self.stream {
+            Some(stream) => stream.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Exponential backoff between connection attempts.
+struct Backoff {
+    delay: Duration,
+    next_attempt: Option<Instant>,
+}
+
+impl Backoff {
+    fn new() -> Backoff {
+        Backoff {
+            delay: INITIAL_BACKOFF,
+            next_attempt: None,
+        }
+    }
+
+    /// Fails if the previous attempt was too recent to try again.
+    fn check(&self) -> io::Result<()> {
+        match self.next_attempt {
+            Some(next) if Instant::now() < next => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "waiting to reconnect",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn fail(&mut self) {
+        self.next_attempt = Some(Instant::now() + self.delay);
+        self.delay = (self.delay * 2).min(MAX_BACKOFF);
+    }
+
+    fn reset(&mut self) {
+        *self = Backoff::new();
+    }
+}
+
+pub(crate) fn resolve<A: ToSocketAddrs>(addr: A) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address resolved to nothing"))
+}
+
+/// Opens a UDP socket sending to `addr`, bound to the unspecified address of its family.
+pub(crate) fn udp_socket<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSocket> {
+    let addr = resolve(addr)?;
+    let local: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(addr)?;
+    Ok(socket)
+}
+
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "address resolved to nothing");
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(IO_TIMEOUT))?;
+                stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn holds_records_while_connecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut writer = TcpWriter::new(&listener.local_addr().unwrap().to_string());
+
+        assert_eq!(writer.write(b"first\n").unwrap(), 6);
+        writer.flush().unwrap();
+        writer.write_all(b"second\n").unwrap();
+        drop(writer);
+
+        let mut received = String::new();
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "first\nsecond\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reconnects_once_the_peer_closed_the_connection() {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::net::UnixListener;
+
+        use crate::writer::Writer;
+
+        let path = std::env::temp_dir().join(format!("pfel-reconnect-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let writer = Writer::pipe(
+            Box::new(UnixWriter::new(&path)),
+            env_logger::WriteStyle::Never,
+        );
+        let print = |line: &str| {
+            let mut buf = writer.buffer();
+            buf.write_all(line.as_bytes()).unwrap();
+            let _ = writer.print(&buf);
+            let _ = writer.flush();
+        };
+
+        print("first\n");
+        let (stream, _) = listener.accept().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "first");
+        drop(lines);
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let _ = sender.send(line.unwrap());
+            }
+        });
+        let received = (0..100).find_map(|_| {
+            print("again\n");
+            receiver.recv_timeout(Duration::from_millis(50)).ok()
+        });
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(received.as_deref(), Some("again"));
+    }
+}
@@ -0,0 +1,134 @@
+//! Forwarding of records to Apple's unified logging system.
+//!
+//! Each record is logged to the configured subsystem, with its target used as the category,
+//! so it can be filtered with `log stream --predicate 'subsystem == "com.example.daemon"'` or in
+//! Console.app. Levels are mapped to the closest `os_log` type:
+//!
+//! | Level            | `os_log` type |
+//! |------------------|---------------|
+//! | `Error`          | error         |
+//! | `Warn`           | default       |
+//! | `Info`           | info          |
+//! | `Debug`, `Trace` | debug         |
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::oslog::OsLog;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! Builder::with("info")
+//!     .sink(Box::new(OsLog::new("com.example.daemon")))
+//!     .init();
+//! ```
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CString};
+use std::io;
+use std::sync::Mutex;
+
+use log::{Level, Record};
+
+use crate::sink::Sink;
+
+type OsLogT = *mut c_void;
+
+const OS_LOG_TYPE_DEFAULT: u8 = 0x00;
+const OS_LOG_TYPE_INFO: u8 = 0x01;
+const OS_LOG_TYPE_DEBUG: u8 = 0x02;
+const OS_LOG_TYPE_ERROR: u8 = 0x10;
+
+/// The format string has to live in the section `os_log` reads formats from.
+#[link_section = "__TEXT,__oslogstring,cstring_literals"]
+static FORMAT: [u8; 11] = *b"%{public}s\0";
+
+extern "C" {
+    static __dso_handle: c_void;
+    fn os_log_create(subsystem: *const c_char, category: *const c_char) -> OsLogT;
+    fn os_log_type_enabled(log: OsLogT, log_type: u8) -> bool;
+    fn _os_log_impl(
+        dso: *const c_void,
+        log: OsLogT,
+        log_type: u8,
+        format: *const c_char,
+        buf: *const u8,
+        size: u32,
+    );
+}
+
+struct Handle(OsLogT);
+
+// SAFETY: `os_log_t` objects are thread-safe and never released.
+unsafe impl Send for Handle {}
+
+/// A [`Sink`] forwarding records to the unified logging system.
+pub struct OsLog {
+    subsystem: CString,
+    categories: Mutex<HashMap<String, Handle>>,
+}
+
+impl OsLog {
+    /// Creates a sink logging to the given subsystem, usually a reverse DNS identifier.
+    pub fn new(subsystem: &str) -> OsLog {
+        OsLog {
+            subsystem: c_string(subsystem),
+            categories: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn log_for(&self, category: &str) -> OsLogT {
+        let mut categories = self.categories.lock().unwrap_or_else(|e| e.into_inner());
+        categories
+            .entry(category.to_string())
+            .or_insert_with(|| {
+                let category = c_string(category);
+                // SAFETY: both strings are valid and null terminated.
+                Handle(unsafe { os_log_create(self.subsystem.as_ptr(), category.as_ptr()) })
+            })
+            .0
+    }
+}
+
+impl Sink for OsLog {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let log = self.log_for(record.target());
+        let log_type = match record.level() {
+            Level::Error => OS_LOG_TYPE_ERROR,
+            Level::Warn => OS_LOG_TYPE_DEFAULT,
+            Level::Info => OS_LOG_TYPE_INFO,
+            Level::Debug | Level::Trace => OS_LOG_TYPE_DEBUG,
+        };
+
+        // SAFETY: `log` was returned by `os_log_create`.
+        if !unsafe { os_log_type_enabled(log, log_type) } {
+            return Ok(());
+        }
+
+        let message = c_string(&record.args().to_string());
+        // The argument buffer, as laid out by the `os_log` macro: a summary byte, the number of
+        // arguments, and for each of them a descriptor byte, its size, and its value.
+        let mut buf = [0u8; 12];
+        buf[0] = 0x02; // has non-scalar arguments
+        buf[1] = 1;
+        buf[2] = 0x22; // public string
+        buf[3] = 8;
+        buf[4..].copy_from_slice(&(message.as_ptr() as u64).to_ne_bytes());
+
+        // SAFETY: the format matches the single public string argument described in `buf`,
+        // which points to `message`, alive until the call returns.
+        unsafe {
+            _os_log_impl(
+                &__dso_handle,
+                log,
+                log_type,
+                FORMAT.as_ptr() as *const c_char,
+                buf.as_ptr(),
+                buf.len() as u32,
+            )
+        };
+        Ok(())
+    }
+}
+
+/// Converts to a C string, dropping interior null bytes.
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
@@ -0,0 +1,241 @@
+//! Exporting records to an [OpenTelemetry][otel] collector, over OTLP.
+//!
+//! Records are batched on a background thread and sent as OpenTelemetry log records to the
+//! collector's OTLP/HTTP endpoint, encoded as JSON. Their target becomes the instrumentation
+//! scope, their level the severity, and their source location and key-values the attributes.
+//! Added with [`add_sink`](crate::Builder::add_sink), the exporter leaves the console output
+//! as it is.
+//!
+//! The `service.name` resource attribute is taken from the `OTEL_SERVICE_NAME` environment
+//! variable when set, and from the name of the executable otherwise.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::otel::Otlp;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let otlp = Otlp::new("http://otel-collector.internal:4318")
+//!     .resource("service.name", "ingestd")
+//!     .resource("deployment.environment", "production");
+//! Builder::with("info").add_sink(Box::new(otlp)).init();
+//! ```
+//!
+//! [otel]: https://opentelemetry.io/docs/specs/otel/logs/
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{Level, Record};
+
+use crate::batch::{BatchConfig, Batcher};
+use crate::http;
+use crate::json::write_str;
+use crate::kv;
+use crate::process::app_name;
+use crate::sink::Sink;
+
+const LOGS_PATH: &str = "/v1/logs";
+
+/// A [`Sink`] exporting batches of records to an OpenTelemetry collector.
+pub struct Otlp {
+    url: String,
+    resource: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    batch: BatchConfig,
+    batcher: OnceLock<io::Result<Batcher<LogRecord>>>,
+}
+
+struct LogRecord {
+    scope: String,
+    time: u128,
+    observed: u128,
+    level: Level,
+    body: String,
+    attributes: Vec<(String, Attribute)>,
+}
+
+enum Attribute {
+    Str(String),
+    Int(u32),
+}
+
+impl Otlp {
+    /// Creates a sink exporting to the collector at `endpoint`, e.g. `http://localhost:4318`.
+    pub fn new(endpoint: &str) -> Otlp {
+        let service = std::env::var("OTEL_SERVICE_NAME")
+            .unwrap_or_else(|_| format!("unknown_service:{}", app_name()));
+        Otlp {
+            url: format!("{}{}", endpoint.trim_end_matches('/'), LOGS_PATH),
+            resource: vec![("service.name".to_string(), service)],
+            headers: Vec::new(),
+            batch: BatchConfig {
+                size: 512,
+                wait: Duration::from_secs(1),
+            },
+            batcher: OnceLock::new(),
+        }
+    }
+
+    /// Sets an attribute of the resource the records come from, replacing any previous value.
+    pub fn resource(mut self, key: &str, value: &str) -> Otlp {
+        self.resource.retain(|(k, _)| k != key);
+        self.resource.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds a header sent with every request, e.g. for authentication.
+    pub fn header(mut self, name: &str, value: &str) -> Otlp {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the number of records sent in a single request, 512 by default.
+    pub fn batch_size(mut self, batch_size: usize) -> Otlp {
+        self.batch.size = batch_size.max(1);
+        self
+    }
+
+    /// Sets for how long records are held before being sent in an incomplete batch, one second
+    /// by default.
+    pub fn batch_wait(mut self, batch_wait: Duration) -> Otlp {
+        self.batch.wait = batch_wait;
+        self
+    }
+
+    /// Starts the background thread on first use.
+    fn batcher(&self) -> io::Result<&Batcher<LogRecord>> {
+        self.batcher
+            .get_or_init(|| {
+                let url = self.url.clone();
+                let resource = self.resource.clone();
+                let headers = self.headers.clone();
+                Batcher::spawn("otlp", self.batch, move |records| {
+                    let body = render(&resource, records);
+                    let headers: Vec<_> = headers
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value.as_str()))
+                        .collect();
+                    http::post(&url, "application/json", &headers, body.as_bytes())
+                })
+            })
+            .as_ref()
+            .map_err(|e| io::Error::new(e.kind(), e.to_string()))
+    }
+}
+
+impl Sink for Otlp {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let nanos = |time: SystemTime| time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        let mut attributes = Vec::new();
+        if let Some(file) = record.file() {
+            attributes.push((
+                "code.filepath".to_string(),
+                Attribute::Str(file.to_string()),
+            ));
+        }
+        if let Some(line) = record.line() {
+            attributes.push(("code.lineno".to_string(), Attribute::Int(line)));
+        }
+        if let Some(module) = record.module_path() {
+            attributes.push((
+                "code.namespace".to_string(),
+                Attribute::Str(module.to_string()),
+            ));
+        }
+        for (key, value) in kv::collect(record) {
+            attributes.push((key, Attribute::Str(value)));
+        }
+        let entry = LogRecord {
+            scope: record.target().to_string(),
+            time: nanos(crate::fmt::now()),
+            observed: nanos(SystemTime::now()),
+            level: record.level(),
+            body: record.args().to_string(),
+            attributes,
+        };
+        self.batcher()?.send(entry)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.batcher()?.flush();
+        Ok(())
+    }
+}
+
+/// The severity number and text of a level, from the ranges defined by OpenTelemetry.
+fn severity(level: Level) -> (u8, &'static str) {
+    match level {
+        Level::Error => (17, "ERROR"),
+        Level::Warn => (13, "WARN"),
+        Level::Info => (9, "INFO"),
+        Level::Debug => (5, "DEBUG"),
+        Level::Trace => (1, "TRACE"),
+    }
+}
+
+/// Renders the export request body, with one scope per target.
+fn render(resource: &[(String, String)], records: &[LogRecord]) -> String {
+    let mut scopes: BTreeMap<&str, Vec<&LogRecord>> = BTreeMap::new();
+    for record in records {
+        scopes.entry(&record.scope).or_default().push(record);
+    }
+
+    let mut body = String::from("{\"resourceLogs\":[{\"resource\":{\"attributes\":[");
+    for (i, (key, value)) in resource.iter().enumerate() {
+        if i > 0 {
+            body.push(',');
+        }
+        write_attribute(&mut body, key, &Attribute::Str(value.clone()));
+    }
+    body.push_str("]},\"scopeLogs\":[");
+    for (i, (scope, records)) in scopes.iter().enumerate() {
+        if i > 0 {
+            body.push(',');
+        }
+        body.push_str("{\"scope\":{\"name\":");
+        write_str(&mut body, scope);
+        body.push_str("},\"logRecords\":[");
+        for (j, record) in records.iter().enumerate() {
+            if j > 0 {
+                body.push(',');
+            }
+            let (number, text) = severity(record.level);
+            let _ = write!(
+                body,
+                "{{\"timeUnixNano\":\"{}\",\"observedTimeUnixNano\":\"{}\",\
+                 \"severityNumber\":{},\"severityText\":\"{}\",\"body\":{{\"stringValue\":",
+                record.time, record.observed, number, text
+            );
+            write_str(&mut body, &record.body);
+            body.push_str("},\"attributes\":[");
+            for (k, (key, value)) in record.attributes.iter().enumerate() {
+                if k > 0 {
+                    body.push(',');
+                }
+                write_attribute(&mut body, key, value);
+            }
+            body.push_str("]}");
+        }
+        body.push_str("]}");
+    }
+    body.push_str("]}]}");
+    body
+}
+
+fn write_attribute(body: &mut String, key: &str, value: &Attribute) {
+    body.push_str("{\"key\":");
+    write_str(body, key);
+    match value {
+        Attribute::Str(value) => {
+            body.push_str(",\"value\":{\"stringValue\":");
+            write_str(body, value);
+        }
+        // 64-bit integers are encoded as strings in OTLP/JSON.
+        Attribute::Int(value) => {
+            let _ = write!(body, ",\"value\":{{\"intValue\":\"{}\"", value);
+        }
+    }
+    body.push_str("}}");
+}
@@ -0,0 +1,109 @@
+//! Outputs carrying their own format and level.
+
+use std::fs::File;
+use std::io::{self, Write};
+
+use env_logger::WriteStyle;
+use log::{LevelFilter, Record};
+
+use crate::fmt::{Format, Formatter};
+use crate::sink::Sink;
+use crate::writer::Writer;
+
+/// A [`Sink`] writing records to a stream in its own [`Format`], up to its own level.
+///
+/// Outputs allow a single logger to write pretty colored text to the terminal while writing the
+/// same records as JSON lines to a file. Records must pass the directives of the logger before
+/// reaching an output, so the directives should be as verbose as the most verbose output.
+///
+/// ```
+/// use log::LevelFilter;
+/// use pretty_flexible_env_logger::{Builder, Format, Output};
+///
+/// let file = std::fs::File::create(std::env::temp_dir().join("app.jsonl")).unwrap();
+/// Builder::with("debug")
+///     .sink(Box::new(Output::stderr().level(LevelFilter::Info)))
+///     .add_sink(Box::new(Output::new(Box::new(file)).format(Format::Json)))
+///     .init();
+///
+/// log::info!("on the terminal and in the file");
+/// log::debug!("only in the file");
+/// ```
+pub struct Output {
+    writer: Writer,
+    format: Format,
+    timed: bool,
+    formatter: Formatter,
+    level: LevelFilter,
+}
+
+impl Output {
+    /// Creates an output writing to the standard error.
+    pub fn stderr() -> Output {
+        Output::with_writer(Writer::stderr(WriteStyle::Auto))
+    }
+
+    /// Creates an output writing to any `Write` implementation, without colors by default.
+    pub fn new(writer: Box<dyn Write + Send>) -> Output {
+        Output::with_writer(Writer::pipe(writer, WriteStyle::Auto))
+    }
+
+    /// Creates an output writing to a file, without colors by default, which
+    /// [`Builder::sync_on`](crate::Builder::sync_on) syncs to disk.
+    pub fn file(file: File) -> Output {
+        Output::with_writer(Writer::file(file, WriteStyle::Auto))
+    }
+
+    fn with_writer(writer: Writer) -> Output {
+        Output {
+            writer,
+            format: Format::default(),
+            timed: false,
+            formatter: Formatter::new(Format::default(), false),
+            level: LevelFilter::Trace,
+        }
+    }
+
+    /// Sets the layout of records, [`Format::Pretty`] by default.
+    pub fn format(mut self, format: Format) -> Output {
+        self.format = format;
+        self.formatter = Formatter::new(self.format, self.timed);
+        self
+    }
+
+    /// Whether to prefix each record with a timestamp.
+    pub fn timed(mut self, timed: bool) -> Output {
+        self.timed = timed;
+        self.formatter = Formatter::new(self.format, self.timed);
+        self
+    }
+
+    /// Sets the most verbose level of records written, trace by default.
+    pub fn level(mut self, level: LevelFilter) -> Output {
+        self.level = level;
+        self
+    }
+
+    /// Whether to print colors, following the same rules as `env_logger`.
+    pub fn write_style(mut self, write_style: WriteStyle) -> Output {
+        self.writer = self.writer.with_style(write_style);
+        self
+    }
+}
+
+impl Sink for Output {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        if record.level() > self.level {
+            return Ok(());
+        }
+        self.formatter.print(&self.writer, record)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.writer.sync()
+    }
+}
@@ -0,0 +1,121 @@
+//! Records captured to be written later or from another thread.
+
+use std::time::SystemTime;
+
+use log::kv::{Error, Key, Source, Value, VisitSource};
+use log::{Level, Record};
+
+use crate::fmt;
+use crate::group;
+use crate::kv;
+
+/// A record with everything its rendering depends on captured from the thread which logged it,
+/// when it was logged.
+pub(crate) struct OwnedRecord {
+    level: Level,
+    target: String,
+    message: String,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    kvs: OwnedKvs,
+    thread: String,
+    depth: usize,
+    time: SystemTime,
+}
+
+/// The diagnostic context, followed by the key-values of the record.
+struct OwnedKvs(Vec<(String, OwnedValue)>);
+
+enum OwnedValue {
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Str(String),
+}
+
+impl OwnedRecord {
+    pub(crate) fn new(record: &Record) -> OwnedRecord {
+        let mut kvs = OwnedKvs(Vec::new());
+        kv::for_each_context(|key, value| {
+            kvs.0
+                .push((key.to_string(), OwnedValue::Str(value.to_string())))
+        });
+        let _ = record.key_values().visit(&mut kvs);
+        OwnedRecord {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            module_path: record.module_path().map(str::to_string),
+            file: record.file().map(str::to_string),
+            line: record.line(),
+            kvs,
+            thread: fmt::thread_name(),
+            depth: group::depth(),
+            time: fmt::now(),
+        }
+    }
+
+    pub(crate) fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Calls `f` with the record, to be written as if from the thread which logged it, at the
+    /// time it was logged.
+    pub(crate) fn replay(&self, f: impl FnOnce(&Record)) {
+        let thread = fmt::set_origin_thread(Some(self.thread.clone()));
+        let time = fmt::set_origin_time(Some(self.time));
+        let depth = group::depth();
+        group::set_depth(self.depth);
+        // The context of the thread which logged the record is part of its key-values.
+        kv::without_context(|| {
+            f(&Record::builder()
+                .level(self.level)
+                .target(&self.target)
+                .args(format_args!("{}", self.message))
+                .module_path(self.module_path.as_deref())
+                .file(self.file.as_deref())
+                .line(self.line)
+                .key_values(&self.kvs)
+                .build())
+        });
+        fmt::set_origin_thread(thread);
+        fmt::set_origin_time(time);
+        group::set_depth(depth);
+    }
+}
+
+impl<'kvs> VisitSource<'kvs> for OwnedKvs {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        let value = if let Some(b) = value.to_bool() {
+            OwnedValue::Bool(b)
+        } else if let Some(n) = value.to_u64() {
+            OwnedValue::U64(n)
+        } else if let Some(n) = value.to_i64() {
+            OwnedValue::I64(n)
+        } else if let Some(n) = value.to_f64() {
+            OwnedValue::F64(n)
+        } else {
+            OwnedValue::Str(value.to_string())
+        };
+        self.0.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+impl Source for OwnedKvs {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        for (key, value) in &self.0 {
+            let value = match value {
+                OwnedValue::Bool(b) => Value::from(*b),
+                OwnedValue::U64(n) => Value::from(*n),
+                OwnedValue::I64(n) => Value::from(*n),
+                OwnedValue::F64(n) => Value::from(*n),
+                OwnedValue::Str(s) => Value::from(s.as_str()),
+            };
+            visitor.visit_pair(Key::from_str(key), value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! Logging panics as error records.
+
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::panic;
+use std::thread;
+
+use log::{Level, Record};
+
+use crate::backtrace;
+
+/// Logs each panic as an error record with the `panic` target, with its backtrace attached when
+/// `RUST_BACKTRACE` enables them, before running the previous panic hook.
+pub(crate) fn install_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::capture();
+        let backtrace = match backtrace.status() {
+            BacktraceStatus::Captured => Some(backtrace::render(&backtrace)),
+            _ => None,
+        };
+        let kvs = backtrace
+            .as_deref()
+            .map(|backtrace| (backtrace::KEY, backtrace));
+        let thread = thread::current();
+        let location = info.location();
+        let at = match location {
+            Some(location) => location.to_string(),
+            None => "an unknown location".to_string(),
+        };
+        log::logger().log(
+            &Record::builder()
+                .level(Level::Error)
+                .target("panic")
+                .file(location.map(|location| location.file()))
+                .line(location.map(|location| location.line()))
+                .args(format_args!(
+                    "thread '{}' panicked at {}: {}",
+                    thread.name().unwrap_or("<unnamed>"),
+                    at,
+                    payload(info.payload())
+                ))
+                .key_values(&kvs)
+                .build(),
+        );
+        // The process may be about to abort, or the panicking thread to be the main one.
+        log::logger().flush();
+        previous(info);
+    }));
+}
+
+fn payload(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message,
+            None => "Box<dyn Any>",
+        },
+    }
+}
@@ -0,0 +1,107 @@
+//! Holding verbose records back, to be written only when something goes wrong.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Metadata, Record};
+
+use crate::owned::OwnedRecord;
+
+/// A bounded buffer of the most recent records left out by the directives, written out when a
+/// warning or an error is logged.
+///
+/// The steady-state output stays as quiet as the directives make it, while failures come with
+/// the debug and trace records which led to them. Held records are written with the time they
+/// were logged at, before the record which set them off, and go through the same hooks and
+/// sinks as any other record.
+///
+/// ```
+/// use log::LevelFilter;
+/// use pretty_flexible_env_logger::{Builder, FlightRecorder};
+///
+/// Builder::with("info")
+///     .flight_recorder(FlightRecorder::new(500).level(LevelFilter::Debug))
+///     .init();
+///
+/// log::debug!("connecting to db.internal:5432"); // held back
+/// log::error!("connection refused"); // written after the debug record
+/// ```
+pub struct FlightRecorder {
+    capacity: usize,
+    level: LevelFilter,
+    trigger: Level,
+}
+
+impl FlightRecorder {
+    /// Creates a recorder holding up to `capacity` records, of any level, written out by
+    /// warnings and errors.
+    pub fn new(capacity: usize) -> FlightRecorder {
+        FlightRecorder {
+            capacity: capacity.max(1),
+            level: LevelFilter::Trace,
+            trigger: Level::Warn,
+        }
+    }
+
+    /// Sets the most verbose level of records held, trace by default.
+    pub fn level(mut self, level: LevelFilter) -> FlightRecorder {
+        self.level = level;
+        self
+    }
+
+    /// Sets the least severe level of the records which write out the held ones, warn by
+    /// default.
+    pub fn trigger(mut self, level: Level) -> FlightRecorder {
+        self.trigger = level;
+        self
+    }
+}
+
+/// The records held back by a logger.
+pub(crate) struct Recorder {
+    records: Mutex<VecDeque<OwnedRecord>>,
+    capacity: usize,
+    level: LevelFilter,
+    trigger: Level,
+}
+
+impl Recorder {
+    pub(crate) fn new(config: FlightRecorder) -> Recorder {
+        Recorder {
+            records: Mutex::new(VecDeque::new()),
+            capacity: config.capacity,
+            level: config.level,
+            trigger: config.trigger,
+        }
+    }
+
+    pub(crate) fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    pub(crate) fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    /// Holds the record back, returning the level of the oldest one if it had to make room.
+    pub(crate) fn push(&self, record: &Record) -> Option<Level> {
+        let record = OwnedRecord::new(record);
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let evicted = if records.len() >= self.capacity {
+            records.pop_front().map(|oldest| oldest.level())
+        } else {
+            None
+        };
+        records.push_back(record);
+        evicted
+    }
+
+    /// Takes the records held back, oldest first, if `record` is severe enough to write them out.
+    pub(crate) fn take(&self, record: &Record) -> Vec<OwnedRecord> {
+        if record.level() > self.trigger {
+            return Vec::new();
+        }
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.drain(..).collect()
+    }
+}
@@ -0,0 +1,43 @@
+//! Scrubbing secrets out of records before they are written.
+
+use std::borrow::Cow;
+
+use log::Record;
+use regex::Regex;
+
+use crate::layer::RecordView;
+
+/// Patterns replaced in the messages and key-values of records, in the order they were added.
+#[derive(Default)]
+pub(crate) struct Redactions(Vec<(Regex, String)>);
+
+impl Redactions {
+    pub(crate) fn push(&mut self, pattern: Regex, replacement: &str) {
+        self.0.push((pattern, replacement.to_string()));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Replaces the matches in the message and the key-values of the record.
+    pub(crate) fn redact(&self, view: &mut RecordView, record: &Record) {
+        if let Cow::Owned(message) = self.apply(view.message()) {
+            view.set_message(message);
+        }
+        view.map_values(record, |_, value| match self.apply(value) {
+            Cow::Owned(value) => Some(value),
+            Cow::Borrowed(_) => None,
+        });
+    }
+
+    fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for (pattern, replacement) in &self.0 {
+            if let Cow::Owned(replaced) = pattern.replace_all(&text, replacement.as_str()) {
+                text = Cow::Owned(replaced);
+            }
+        }
+        text
+    }
+}
@@ -0,0 +1,170 @@
+//! Logging errors with their causes, see [`log_error!`](crate::log_error!).
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error;
+use std::fmt;
+
+use log::{Level, Record};
+
+use crate::backtrace;
+
+/// The key the causes of an error are attached to records under, one per line.
+pub(crate) const KEY: &str = "caused_by";
+
+/// Logs an error record for `error`, its message followed by the causes of the error, one per
+/// indented `caused by:` line, and by the backtrace it captured if any.
+///
+/// The error may be any type implementing [`Error`], or a report such as `anyhow::Error` or
+/// `eyre::Report`. With the `anyhow` feature, the backtrace captured by `anyhow` is written as
+/// well, shortened as with [`Builder::with_backtraces`](crate::Builder::with_backtraces).
+///
+/// The message is the error itself, or the one given after it, the error then being the
+/// first cause.
+///
+/// ```
+/// use pretty_flexible_env_logger::log_error;
+///
+/// pretty_flexible_env_logger::init_with("info");
+///
+/// let error = std::fs::read("/nonexistent/config.toml").unwrap_err();
+/// // ERROR app > cannot load the config
+/// //     caused by: No such file or directory (os error 2)
+/// log_error!(error, "cannot load the config");
+/// ```
+#[macro_export]
+macro_rules! log_error {
+    ($error:expr) => {
+        $crate::log_error!(@log $error, ::std::option::Option::None)
+    };
+    ($error:expr, $($arg:tt)+) => {
+        $crate::log_error!(@log $error, ::std::option::Option::Some(format_args!($($arg)+)))
+    };
+    (@log $error:expr, $context:expr) => {
+        match &&&$crate::report::Wrap(&$error) {
+            wrap => {
+                #[allow(unused_imports)]
+                use $crate::report::{FromAnyhow as _, FromError as _, FromReport as _};
+                $crate::report::log(
+                    module_path!(),
+                    file!(),
+                    line!(),
+                    wrap.report(),
+                    $context,
+                );
+            }
+        }
+    };
+}
+
+/// An error borrowed by [`log_error!`](crate::log_error!).
+#[doc(hidden)]
+pub struct Report<'a> {
+    error: &'a (dyn Error + 'static),
+    backtrace: Option<&'a Backtrace>,
+}
+
+/// The error given to [`log_error!`](crate::log_error!), whose type picks the way it is turned
+/// into a [`Report`].
+#[doc(hidden)]
+pub struct Wrap<'a, T: ?Sized>(pub &'a T);
+
+/// Errors themselves.
+#[doc(hidden)]
+pub trait FromError {
+    fn report(&self) -> Report<'_>;
+}
+
+impl<E: Error + 'static> FromError for Wrap<'_, E> {
+    fn report(&self) -> Report<'_> {
+        Report {
+            error: self.0,
+            backtrace: None,
+        }
+    }
+}
+
+/// Reports wrapping an error, such as `anyhow::Error` or `eyre::Report`, which do not implement
+/// [`Error`] themselves.
+#[doc(hidden)]
+pub trait FromReport {
+    fn report(&self) -> Report<'_>;
+}
+
+impl<T: AsRef<dyn Error + Send + Sync + 'static> + ?Sized> FromReport for &Wrap<'_, T> {
+    fn report(&self) -> Report<'_> {
+        Report {
+            error: self.0.as_ref(),
+            backtrace: None,
+        }
+    }
+}
+
+/// `anyhow` errors, with their backtrace.
+#[doc(hidden)]
+pub trait FromAnyhow {
+    fn report(&self) -> Report<'_>;
+}
+
+#[cfg(feature = "anyhow")]
+impl FromAnyhow for &&Wrap<'_, anyhow::Error> {
+    fn report(&self) -> Report<'_> {
+        Report {
+            error: self.0.as_ref(),
+            backtrace: Some(self.0.backtrace()),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn log(
+    target: &'static str,
+    file: &'static str,
+    line: u32,
+    report: Report,
+    context: Option<fmt::Arguments>,
+) {
+    if Level::Error > log::max_level() {
+        return;
+    }
+    let mut cause = match context {
+        Some(_) => Some(report.error),
+        None => report.error.source(),
+    };
+    let mut causes = String::new();
+    while let Some(error) = cause {
+        if !causes.is_empty() {
+            causes.push('\n');
+        }
+        causes.push_str(&error.to_string());
+        cause = error.source();
+    }
+    let backtrace = report
+        .backtrace
+        .filter(|backtrace| backtrace.status() == BacktraceStatus::Captured)
+        .map(backtrace::render);
+
+    let mut kvs = Vec::new();
+    if !causes.is_empty() {
+        kvs.push((KEY, causes.as_str()));
+    }
+    if let Some(backtrace) = &backtrace {
+        kvs.push((backtrace::KEY, backtrace.as_str()));
+    }
+    let write = |message: fmt::Arguments| {
+        log::logger().log(
+            &Record::builder()
+                .level(Level::Error)
+                .target(target)
+                .module_path_static(Some(target))
+                .file_static(Some(file))
+                .line(Some(line))
+                .args(message)
+                .key_values(&kvs)
+                .build(),
+        )
+    };
+    match context {
+        Some(context) => write(context),
+        None => write(format_args!("{}", report.error)),
+    }
+}
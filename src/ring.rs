@@ -0,0 +1,148 @@
+//! Keeping recent records in memory, to be dumped when the program panics.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use log::{LevelFilter, Metadata, Record};
+use termcolor::Buffer;
+
+use crate::fmt::{Format, Formatter};
+
+/// A bounded buffer of the most recent records, dumped when a panic occurs.
+///
+/// The buffer captures records up to its own level, regardless of the directives, so the
+/// steady-state output can stay quiet while debug and trace records are still around to explain
+/// what led to a crash. Records are written to the buffer without taking any lock.
+///
+/// ```
+/// use log::LevelFilter;
+/// use pretty_flexible_env_logger::{Builder, RingBuffer};
+///
+/// let crash_file = std::env::temp_dir().join("crash.log");
+/// Builder::with("warn")
+///     .dump_on_panic(RingBuffer::new(1000).level(LevelFilter::Debug).dump_to(crash_file))
+///     .init();
+/// ```
+pub struct RingBuffer {
+    capacity: usize,
+    level: LevelFilter,
+    dump_to: Option<PathBuf>,
+}
+
+impl RingBuffer {
+    /// Creates a buffer holding up to `capacity` records, of any level.
+    pub fn new(capacity: usize) -> RingBuffer {
+        RingBuffer {
+            capacity: capacity.max(1),
+            level: LevelFilter::Trace,
+            dump_to: None,
+        }
+    }
+
+    /// Sets the most verbose level of records kept in the buffer, trace by default.
+    pub fn level(mut self, level: LevelFilter) -> RingBuffer {
+        self.level = level;
+        self
+    }
+
+    /// Appends the dump to a crash file, instead of writing it to the standard error.
+    pub fn dump_to<P: AsRef<Path>>(mut self, path: P) -> RingBuffer {
+        self.dump_to = Some(path.as_ref().to_path_buf());
+        self
+    }
+}
+
+/// The buffer itself, shared between the logger and the panic hook.
+pub(crate) struct Ring {
+    slots: Box<[AtomicPtr<Vec<u8>>]>,
+    head: AtomicUsize,
+    level: LevelFilter,
+    dump_to: Option<PathBuf>,
+    formatter: Formatter,
+}
+
+impl Ring {
+    pub(crate) fn new(config: RingBuffer) -> Ring {
+        Ring {
+            slots: (0..config.capacity)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            head: AtomicUsize::new(0),
+            level: config.level,
+            dump_to: config.dump_to,
+            formatter: Formatter::new(Format::Pretty, true),
+        }
+    }
+
+    pub(crate) fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    pub(crate) fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    pub(crate) fn push(&self, record: &Record) {
+        let mut buf = Buffer::no_color();
+        if self.formatter.write(&mut buf, record).is_err() {
+            return;
+        }
+        let line = Box::into_raw(Box::new(buf.into_inner()));
+        let i = self.head.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        let old = self.slots[i].swap(line, Ordering::AcqRel);
+        if !old.is_null() {
+            // SAFETY: non-null slots hold pointers created by `Box::into_raw` above, and the
+            // swap made this thread their only owner.
+            drop(unsafe { Box::from_raw(old) });
+        }
+    }
+
+    /// Writes out and empties the buffer, oldest records first.
+    pub(crate) fn dump(&self) -> io::Result<()> {
+        let mut out: Box<dyn Write> = match &self.dump_to {
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(io::stderr().lock()),
+        };
+
+        let head = self.head.load(Ordering::Relaxed);
+        let len = self.slots.len();
+        writeln!(out, "---- last records before the panic ----")?;
+        for i in 0..len {
+            let line = self.slots[(head + i) % len].swap(ptr::null_mut(), Ordering::AcqRel);
+            if !line.is_null() {
+                // SAFETY: see `push`.
+                let line = unsafe { Box::from_raw(line) };
+                out.write_all(&line)?;
+            }
+        }
+        writeln!(out, "---- end of records ----")?;
+        out.flush()
+    }
+
+    /// Dumps the buffer when a panic occurs, before running the previous panic hook.
+    pub(crate) fn install_panic_hook(self: &Arc<Ring>) {
+        let ring = Arc::clone(self);
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = ring.dump();
+            previous(info);
+        }));
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        for slot in self.slots.iter_mut() {
+            let line = *slot.get_mut();
+            if !line.is_null() {
+                // SAFETY: see `push`.
+                drop(unsafe { Box::from_raw(line) });
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Named scopes prefixing the messages of records logged within them.
+
+use std::cell::RefCell;
+
+thread_local! {
+    static SCOPES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` within a named scope, prefixing the message of every record it logs with the name.
+///
+/// Scopes nest, so records logged from nested scopes are prefixed with the whole path, as in
+/// `[db/pool] connection acquired`. See the `task` module for the async variant.
+///
+/// ```
+/// use pretty_flexible_env_logger::with_scope;
+///
+/// pretty_flexible_env_logger::init_with("info");
+///
+/// with_scope("db", || {
+///     log::info!("migrating"); // ... > [db] migrating
+///     with_scope("pool", || {
+///         log::info!("connection acquired"); // ... > [db/pool] connection acquired
+///     });
+/// });
+/// ```
+pub fn with_scope<R>(name: &str, f: impl FnOnce() -> R) -> R {
+    struct Pop;
+
+    impl Drop for Pop {
+        fn drop(&mut self) {
+            SCOPES.with(|scopes| scopes.borrow_mut().pop());
+        }
+    }
+
+    SCOPES.with(|scopes| scopes.borrow_mut().push(name.to_string()));
+    let _pop = Pop;
+    f()
+}
+
+/// Returns the path of the current scopes, or `None` outside of any scope.
+pub(crate) fn current() -> Option<String> {
+    let mut path = Vec::new();
+    #[cfg(feature = "tokio")]
+    crate::task::for_each_scope(|name| path.push(name.to_string()));
+    let _ = SCOPES.try_with(|scopes| path.extend(scopes.borrow().iter().cloned()));
+    if path.is_empty() {
+        None
+    } else {
+        Some(path.join("/"))
+    }
+}
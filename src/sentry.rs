@@ -0,0 +1,107 @@
+//! Reporting records to [Sentry][sentry].
+//!
+//! Errors are sent as Sentry events, and less severe records are added as breadcrumbs, so each
+//! event comes with the records which led to it. The records go through the client the
+//! application set up with `sentry::init`, and are dropped while there is none; the guard it
+//! returns sends the pending events when dropped. Added with
+//! [`add_sink`](crate::Builder::add_sink), the sink leaves the console output as it is.
+//!
+//! ```no_run
+//! use log::LevelFilter;
+//! use pretty_flexible_env_logger::sentry::Sentry;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! // let _guard = sentry::init("https://key@sentry.io/42");
+//! let sentry = Sentry::new().events(LevelFilter::Warn);
+//! Builder::with("info").add_sink(Box::new(sentry)).init();
+//!
+//! log::info!("connecting to the database"); // a breadcrumb
+//! log::error!("connection refused"); // an event
+//! ```
+//!
+//! [sentry]: https://sentry.io
+
+use std::io;
+
+use log::{Level, LevelFilter, Record};
+use sentry_core::protocol::{Breadcrumb, Event, Map, Value};
+
+use crate::kv;
+use crate::sink::Sink;
+
+/// A [`Sink`] reporting records to Sentry, see the [module](self) documentation.
+pub struct Sentry {
+    events: LevelFilter,
+    breadcrumbs: LevelFilter,
+}
+
+impl Sentry {
+    /// Creates a sink sending errors as events, and other records down to info as breadcrumbs.
+    pub fn new() -> Sentry {
+        Sentry {
+            events: LevelFilter::Error,
+            breadcrumbs: LevelFilter::Info,
+        }
+    }
+
+    /// Sets the least severe level of the records sent as events, error by default.
+    pub fn events(mut self, level: LevelFilter) -> Sentry {
+        self.events = level;
+        self
+    }
+
+    /// Sets the least severe level of the records added as breadcrumbs, info by default.
+    pub fn breadcrumbs(mut self, level: LevelFilter) -> Sentry {
+        self.breadcrumbs = level;
+        self
+    }
+}
+
+impl Default for Sentry {
+    fn default() -> Sentry {
+        Sentry::new()
+    }
+}
+
+impl Sink for Sentry {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        if record.level() <= self.events {
+            sentry_core::capture_event(Event {
+                level: level(record.level()),
+                message: Some(record.args().to_string()),
+                logger: Some(record.target().to_string()),
+                culprit: record.module_path().map(str::to_string),
+                timestamp: crate::fmt::now(),
+                extra: fields(record),
+                ..Event::default()
+            });
+        } else if record.level() <= self.breadcrumbs {
+            sentry_core::add_breadcrumb(Breadcrumb {
+                ty: "log".to_string(),
+                level: level(record.level()),
+                category: Some(record.target().to_string()),
+                message: Some(record.args().to_string()),
+                timestamp: crate::fmt::now(),
+                data: fields(record),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn level(level: Level) -> sentry_core::Level {
+    match level {
+        Level::Error => sentry_core::Level::Error,
+        Level::Warn => sentry_core::Level::Warning,
+        Level::Info => sentry_core::Level::Info,
+        Level::Debug | Level::Trace => sentry_core::Level::Debug,
+    }
+}
+
+/// The diagnostic context and key-values of a record.
+fn fields(record: &Record) -> Map<String, Value> {
+    kv::collect(record)
+        .into_iter()
+        .map(|(key, value)| (key, Value::String(value)))
+        .collect()
+}
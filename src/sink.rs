@@ -0,0 +1,294 @@
+//! Destinations records are delivered to once they pass the filters.
+
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+use env_logger::WriteStyle;
+use log::{Level, Log, Record};
+use termcolor::Buffer;
+
+use crate::fmt::Formatter;
+use crate::writer::Writer;
+
+/// A destination for log records.
+///
+/// Sinks receive every record which passed the filters of the [`Logger`](crate::Logger) they are
+/// attached to, and are responsible for rendering it in whatever form their backend expects.
+/// Plain `Write` implementations get the pretty format through
+/// [`Builder::writer`](crate::Builder::writer) instead.
+///
+/// Records logged while a sink writes or flushes, by the sink or the libraries it uses, and by
+/// the threads of the logger, are dropped, as they would otherwise loop back to the sink.
+pub trait Sink: Send + Sync {
+    /// Writes a single record.
+    fn write(&self, record: &Record) -> io::Result<()>;
+
+    /// Flushes any buffered records.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Makes the records written so far durable, for instance by syncing a file to disk, after
+    /// the records selected with [`Builder::sync_on`](crate::Builder::sync_on). Flushes by
+    /// default.
+    fn sync(&self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+thread_local! {
+    /// Whether the current thread is writing records to the sinks.
+    static INSIDE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` as part of writing records to the sinks, the records it logs being dropped.
+pub(crate) fn inside<R>(f: impl FnOnce() -> R) -> R {
+    let previous = INSIDE.with(|inside| inside.replace(true));
+    let result = f();
+    INSIDE.with(|inside| inside.set(previous));
+    result
+}
+
+/// Whether the current thread is writing records to the sinks, see [`inside`].
+pub(crate) fn is_inside() -> bool {
+    INSIDE.try_with(Cell::get).unwrap_or(false)
+}
+
+/// How records rendered in the pretty format are handed to the standard error or a custom
+/// writer, set with [`Builder::buffering`](crate::Builder::buffering).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Buffering {
+    /// Every record is written and the writer flushed straight away.
+    Unbuffered,
+    /// Every record is written straight away, in a single write.
+    #[default]
+    Line,
+    /// Records are gathered into blocks of a few kilobytes, written when full, on
+    /// [`flush`](log::Log::flush), or once the oldest record gathered is `max_delay` old.
+    Block {
+        /// How long a record may stay in the block before being written.
+        max_delay: Duration,
+    },
+}
+
+/// Size above which a block of records is written.
+const BLOCK_SIZE: usize = 8 * 1024;
+
+/// A sink rendering records in the pretty format into a byte stream.
+pub(crate) struct Stream {
+    writer: Writer,
+    format: Arc<Formatter>,
+    buffering: Buffering,
+    /// The records gathered with block buffering, and when the first of them was.
+    block: Mutex<(Buffer, Option<Instant>)>,
+}
+
+impl Stream {
+    pub(crate) fn new(writer: Writer, format: Arc<Formatter>, buffering: Buffering) -> Stream {
+        let block = Mutex::new((writer.buffer(), None));
+        Stream {
+            writer,
+            format,
+            buffering,
+            block,
+        }
+    }
+
+    fn block(&self) -> MutexGuard<'_, (Buffer, Option<Instant>)> {
+        self.block.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Writes out the records gathered so far.
+    fn write_block(&self, block: &mut (Buffer, Option<Instant>)) -> io::Result<()> {
+        let (buf, since) = block;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let result = self.writer.print(buf);
+        buf.clear();
+        *since = None;
+        result
+    }
+}
+
+impl Sink for Stream {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        match self.buffering {
+            Buffering::Unbuffered => {
+                self.format.print(&self.writer, record)?;
+                self.writer.flush()
+            }
+            Buffering::Line => self.format.print(&self.writer, record),
+            Buffering::Block { max_delay } => {
+                let mut block = self.block();
+                let since = *block.1.get_or_insert_with(Instant::now);
+                self.format.render_for(&self.writer, &mut block.0, record)?;
+                if block.0.len() >= BLOCK_SIZE || since.elapsed() >= max_delay {
+                    self.write_block(&mut block)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.write_block(&mut self.block())?;
+        self.writer.flush()
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.write_block(&mut self.block())?;
+        self.writer.sync()
+    }
+}
+
+/// Flushes the outputs every `interval` on a background thread, for as long as they are in use,
+/// so records gathered into blocks are written even when no more records come in.
+pub(crate) fn flush_periodically(outputs: Weak<Outputs>, interval: Duration) {
+    // Where threads are not available, blocks are only written by the records which follow.
+    let _ = std::thread::Builder::new()
+        .name("log-flusher".to_string())
+        .spawn(move || {
+            inside(|| loop {
+                std::thread::sleep(interval);
+                match outputs.upgrade() {
+                    Some(outputs) => outputs.flush(),
+                    None => break,
+                }
+            })
+        });
+}
+
+/// A sink dropping every record, for when output is disabled.
+pub(crate) struct Discard;
+
+impl Sink for Discard {
+    fn write(&self, _: &Record) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A sink handing records over to another logger, see
+/// [`Builder::also_log_to`](crate::Builder::also_log_to).
+pub(crate) struct Forward(pub(crate) Box<dyn Log>);
+
+impl Sink for Forward {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        if self.0.enabled(record.metadata()) {
+            self.0.log(record);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.0.flush();
+        Ok(())
+    }
+}
+
+/// The sinks of a logger: the main one, the ones selected by target prefix, and the
+/// additional ones receiving every record.
+pub(crate) struct Outputs {
+    pub(crate) sink: Box<dyn Sink>,
+    pub(crate) routes: Vec<(String, Box<dyn Sink>)>,
+    pub(crate) sinks: Vec<Box<dyn Sink>>,
+    /// The least severe level of the records after which the sinks are synced.
+    pub(crate) sync_on: Option<Level>,
+}
+
+impl Outputs {
+    pub(crate) fn write(&self, record: &Record) {
+        let sync = self.sync_on.is_some_and(|level| record.level() <= level);
+        let write = |sink: &dyn Sink| {
+            // Errors are ignored, there is nowhere sensible to report them.
+            let _ = sink.write(record);
+            if sync {
+                let _ = sink.sync();
+            }
+        };
+        inside(|| {
+            write(self.sink_for(record.target()));
+            for sink in &self.sinks {
+                write(&**sink);
+            }
+        });
+    }
+
+    fn sink_for(&self, target: &str) -> &dyn Sink {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map_or(&*self.sink, |(_, sink)| &**sink)
+    }
+
+    pub(crate) fn flush(&self) {
+        inside(|| {
+            let _ = self.sink.flush();
+            for (_, sink) in &self.routes {
+                let _ = sink.flush();
+            }
+            for sink in &self.sinks {
+                let _ = sink.flush();
+            }
+        });
+    }
+}
+
+/// A sink as configured on the [`Builder`](crate::Builder), before the logger is built.
+pub(crate) enum Destination {
+    Writer(Box<dyn Write + Send>),
+    Stdout,
+    File(File),
+    Sink(Box<dyn Sink>),
+    #[cfg(feature = "indicatif")]
+    Progress(indicatif::MultiProgress),
+}
+
+impl Destination {
+    pub(crate) fn into_sink(
+        self,
+        write_style: WriteStyle,
+        buffering: Buffering,
+        format: &Arc<Formatter>,
+    ) -> Box<dyn Sink> {
+        match self {
+            Destination::Writer(writer) => Box::new(Stream::new(
+                Writer::pipe(writer, write_style),
+                format.clone(),
+                buffering,
+            )),
+            Destination::Stdout => Box::new(Stream::new(
+                Writer::stdout(write_style),
+                format.clone(),
+                buffering,
+            )),
+            Destination::File(file) => Box::new(Stream::new(
+                Writer::file(file, write_style),
+                format.clone(),
+                buffering,
+            )),
+            Destination::Sink(sink) => sink,
+            #[cfg(feature = "indicatif")]
+            Destination::Progress(bars) => Box::new(Stream::new(
+                Writer::progress(bars, write_style),
+                format.clone(),
+                buffering,
+            )),
+        }
+    }
+}
+
+/// Maps a level to the severity shared by syslog and the systemd journal.
+#[cfg(any(feature = "syslog", feature = "journald", feature = "gelf"))]
+pub(crate) fn syslog_severity(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => 3,
+        log::Level::Warn => 4,
+        log::Level::Info => 6,
+        log::Level::Debug | log::Level::Trace => 7,
+    }
+}
@@ -0,0 +1,116 @@
+//! Writing [`slog`][slog] records through the logger, in the same format as `log` records.
+//!
+//! The [`Drain`] turns every record into a record for the global logger, so the directives,
+//! hooks, formats and sinks configured with the [`Builder`](crate::Builder) apply to `slog`
+//! records too, and codebases moving from one crate to the other get uniform output. The
+//! key-values of the `slog` loggers are written before the ones of the record.
+//!
+//! ```
+//! use pretty_flexible_env_logger::slog::Drain;
+//! use slog::{info, o, Logger};
+//!
+//! pretty_flexible_env_logger::init_with("info");
+//! let root = Logger::root(Drain::new(), o!("service" => "api"));
+//!
+//! info!(root, "query done"; "rows" => 3); // INFO  app > query done service=api rows=3
+//! log::info!("from log");
+//! ```
+//!
+//! [slog]: https://docs.rs/slog
+
+use std::fmt;
+
+use log::kv::{Error, Key, Source, Value, VisitSource};
+use log::Record;
+use slog::{Level, Never, OwnedKVList, Serializer, KV};
+
+/// A `slog` drain writing records to the global logger, see the [module](self) documentation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Drain {
+    _private: (),
+}
+
+impl Drain {
+    /// Creates a drain writing to the global logger, installed with this crate or any other.
+    pub fn new() -> Drain {
+        Drain { _private: () }
+    }
+}
+
+impl slog::Drain for Drain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &slog::Record<'_>, values: &OwnedKVList) -> Result<(), Never> {
+        let target = match record.tag() {
+            "" => record.module(),
+            tag => tag,
+        };
+        let metadata = log::Metadata::builder()
+            .level(level(record.level()))
+            .target(target)
+            .build();
+        let logger = log::logger();
+        if !logger.enabled(&metadata) {
+            return Ok(());
+        }
+
+        // Serializing never fails, the values are only rendered.
+        let mut fields = Fields::default();
+        let _ = values.serialize(record, &mut fields);
+        let outer = fields.0.len();
+        let _ = record.kv().serialize(record, &mut fields);
+        // `slog` serializes the most recently added values first.
+        fields.0[..outer].reverse();
+        fields.0[outer..].reverse();
+
+        logger.log(
+            &Record::builder()
+                .metadata(metadata)
+                .module_path(Some(record.module()))
+                .file(Some(record.file()))
+                .line(Some(record.line()))
+                .args(*record.msg())
+                .key_values(&Pairs(&fields.0))
+                .build(),
+        );
+        Ok(())
+    }
+
+    fn is_enabled(&self, level: Level) -> bool {
+        self::level(level) <= log::max_level()
+    }
+}
+
+fn level(level: Level) -> log::Level {
+    match level {
+        Level::Critical | Level::Error => log::Level::Error,
+        Level::Warning => log::Level::Warn,
+        Level::Info => log::Level::Info,
+        Level::Debug => log::Level::Debug,
+        Level::Trace => log::Level::Trace,
+    }
+}
+
+/// The key-values of a record and its loggers, rendered as strings.
+#[derive(Default)]
+struct Fields(Vec<(String, String)>);
+
+impl Serializer for Fields {
+    fn emit_arguments(&mut self, key: slog::Key, value: &fmt::Arguments<'_>) -> slog::Result {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Key-values rendered as strings.
+struct Pairs<'a>(&'a [(String, String)]);
+
+impl Source for Pairs<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        for (key, value) in self.0 {
+            visitor.visit_pair(Key::from_str(key), Value::from(value.as_str()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! Storing records in a local SQLite database.
+//!
+//! Records are inserted into a `logs` table, which makes post-mortem analysis a matter of
+//! running `sqlite3 app-logs.db "SELECT * FROM logs WHERE level = 'ERROR'"`. The database is
+//! opened in WAL mode, so it can be queried while the application keeps writing to it, and the
+//! table can be capped to a number of rows, so it does not grow forever on users' machines.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::sqlite::Sqlite;
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let sqlite = Sqlite::open("app-logs.db")
+//!     .expect("cannot open the log database")
+//!     .max_rows(100_000);
+//! Builder::with("debug").add_sink(Box::new(sqlite)).init();
+//! ```
+
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::Record;
+use rusqlite::{params, Connection};
+
+use crate::fmt::timestamp;
+use crate::kv;
+use crate::sink::Sink;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS logs (
+    id INTEGER PRIMARY KEY,
+    timestamp TEXT,
+    level TEXT NOT NULL,
+    target TEXT NOT NULL,
+    message TEXT NOT NULL,
+    file TEXT,
+    line INTEGER,
+    kvs TEXT
+)";
+
+/// How many inserts happen between two checks of the row cap.
+const CAP_INTERVAL: u64 = 100;
+
+/// A [`Sink`] inserting records into a SQLite database.
+pub struct Sqlite {
+    state: Mutex<State>,
+    max_rows: Option<u64>,
+}
+
+struct State {
+    connection: Connection,
+    inserts: u64,
+}
+
+impl Sqlite {
+    /// Opens or creates the database at `path`, creating the `logs` table if needed.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the database cannot be opened or initialized.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Sqlite> {
+        let connection = Connection::open(path).map_err(io::Error::other)?;
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .and_then(|_| connection.pragma_update(None, "synchronous", "NORMAL"))
+            .and_then(|_| connection.execute(SCHEMA, []))
+            .map_err(io::Error::other)?;
+        Ok(Sqlite {
+            state: Mutex::new(State {
+                connection,
+                inserts: 0,
+            }),
+            max_rows: None,
+        })
+    }
+
+    /// Caps the table to the most recent `max_rows` records.
+    ///
+    /// The cap is enforced every hundred records, so the table may briefly hold a few more.
+    pub fn max_rows(mut self, max_rows: u64) -> Sqlite {
+        self.max_rows = Some(max_rows);
+        self
+    }
+}
+
+impl Sink for Sqlite {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .connection
+            .prepare_cached(
+                "INSERT INTO logs (timestamp, level, target, message, file, line, kvs) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )
+            .and_then(|mut insert| {
+                insert.execute(params![
+                    timestamp().map(|now| now.to_string()),
+                    record.level().as_str(),
+                    record.target(),
+                    record.args().to_string(),
+                    record.file(),
+                    record.line(),
+                    kv::to_json(record),
+                ])
+            })
+            .map_err(io::Error::other)?;
+
+        state.inserts += 1;
+        if let Some(max_rows) = self.max_rows {
+            if state.inserts.is_multiple_of(CAP_INTERVAL) {
+                state
+                    .connection
+                    .execute(
+                        "DELETE FROM logs WHERE id <= (SELECT MAX(id) FROM logs) - ?1",
+                        [i64::try_from(max_rows).unwrap_or(i64::MAX)],
+                    )
+                    .map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+//! Counting the records going through the logger.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use log::{Level, Record};
+
+use crate::builder;
+use crate::fmt::timestamp;
+
+/// Live counters of a logger.
+#[derive(Default)]
+pub(crate) struct Counters {
+    emitted: [AtomicU64; 5],
+    filtered: [AtomicU64; 5],
+    dropped: [AtomicU64; 5],
+    targets: Mutex<HashMap<String, u64>>,
+    /// The time and message of the first error.
+    first_error: OnceLock<(String, String)>,
+}
+
+impl Counters {
+    /// Counts a record written to the sinks.
+    pub(crate) fn emitted(&self, record: &Record) {
+        self.emitted[record.level() as usize - 1].fetch_add(1, Ordering::Relaxed);
+        if record.level() == Level::Error && self.first_error.get().is_none() {
+            let time = timestamp().map_or_else(String::new, |now| now.to_string());
+            let _ = self.first_error.set((time, record.args().to_string()));
+        }
+        let mut targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+        match targets.get_mut(record.target()) {
+            Some(count) => *count += 1,
+            None => {
+                targets.insert(record.target().to_string(), 1);
+            }
+        }
+    }
+
+    /// Counts a record left out by the filters.
+    pub(crate) fn filtered(&self, level: Level) {
+        self.filtered[level as usize - 1].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a record which passed the filters but was discarded, for instance by a rate limit.
+    pub(crate) fn dropped(&self, level: Level) {
+        self.dropped[level as usize - 1].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> LogStats {
+        let load =
+            |counters: &[AtomicU64; 5]| counters.each_ref().map(|c| c.load(Ordering::Relaxed));
+        let mut targets: Vec<_> = self
+            .targets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(target, count)| (target.clone(), *count))
+            .collect();
+        targets.sort();
+        LogStats {
+            emitted: load(&self.emitted),
+            filtered: load(&self.filtered),
+            dropped: load(&self.dropped),
+            targets,
+            first_error: self.first_error.get().cloned(),
+        }
+    }
+}
+
+/// A snapshot of the number of records which went through a logger since it was built.
+///
+/// Records left out because of the global maximum level, set with `log::set_max_level`, never
+/// reach the logger and are not counted.
+///
+/// ```
+/// use log::Level;
+///
+/// pretty_flexible_env_logger::init_with("info");
+///
+/// log::error!("disk full");
+/// log::debug!("ignored");
+///
+/// let stats = pretty_flexible_env_logger::stats();
+/// assert_eq!(stats.emitted(Level::Error), 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LogStats {
+    emitted: [u64; 5],
+    filtered: [u64; 5],
+    dropped: [u64; 5],
+    targets: Vec<(String, u64)>,
+    first_error: Option<(String, String)>,
+}
+
+impl LogStats {
+    /// Number of records of `level` written to the sinks.
+    pub fn emitted(&self, level: Level) -> u64 {
+        self.emitted[level as usize - 1]
+    }
+
+    /// Number of records of `level` left out by the filters.
+    pub fn filtered(&self, level: Level) -> u64 {
+        self.filtered[level as usize - 1]
+    }
+
+    /// Number of records of `level` which passed the filters but were discarded by sampling,
+    /// rate limits, deduplication or a full writer queue.
+    pub fn dropped(&self, level: Level) -> u64 {
+        self.dropped[level as usize - 1]
+    }
+
+    /// Number of records written to the sinks, whatever their level.
+    pub fn total_emitted(&self) -> u64 {
+        self.emitted.iter().sum()
+    }
+
+    /// The time and message of the first error record written to the sinks, if any.
+    pub fn first_error(&self) -> Option<(&str, &str)> {
+        self.first_error
+            .as_ref()
+            .map(|(time, message)| (time.as_str(), message.as_str()))
+    }
+
+    /// A one-line account of the warnings and errors, as in
+    /// `42 warnings, 3 errors during this run, first error at 2024-05-01T12:00:00.000Z: disk full`.
+    pub fn summary(&self) -> String {
+        let count = |n: u64, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+        let mut summary = format!(
+            "{}, {} during this run",
+            count(self.emitted(Level::Warn), "warning"),
+            count(self.emitted(Level::Error), "error"),
+        );
+        if let Some((time, message)) = self.first_error() {
+            summary.push_str(&format!(", first error at {}: {}", time, message));
+        }
+        summary
+    }
+
+    /// Number of records written to the sinks for each target, sorted by target.
+    pub fn targets(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.targets
+            .iter()
+            .map(|(target, count)| (target.as_str(), *count))
+    }
+}
+
+/// Returns the counters of the logger installed by this crate, or empty ones if none was.
+pub fn stats() -> LogStats {
+    builder::installed().map_or_else(LogStats::default, |logger| logger.stats())
+}
@@ -0,0 +1,334 @@
+//! Delivery of records to a syslog daemon.
+//!
+//! Records are rendered according to [RFC 3164][rfc3164] (the default) or [RFC 5424][rfc5424],
+//! with the severity derived from the record level, and sent to the local daemon through its
+//! Unix socket, or to a remote endpoint over UDP or TCP. The Unix socket is reconnected when
+//! the daemon restarts, and TCP connections are handled as described in the
+//! [`net`](crate::net) module.
+//!
+//! ```no_run
+//! use pretty_flexible_env_logger::syslog::{Facility, Syslog};
+//! use pretty_flexible_env_logger::Builder;
+//!
+//! let syslog = Syslog::local(Facility::Daemon).expect("no syslog daemon running");
+//! Builder::with("info").sink(Box::new(syslog)).init();
+//! ```
+//!
+//! [rfc3164]: https://www.rfc-editor.org/rfc/rfc3164
+//! [rfc5424]: https://www.rfc-editor.org/rfc/rfc5424
+
+use std::io::{self, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{Level, Record};
+
+use crate::fmt::timestamp;
+use crate::net::{self, TcpWriter};
+use crate::process::{app_name, hostname};
+use crate::sink::{syslog_severity, Sink};
+
+/// Paths the local syslog daemon usually listens on.
+#[cfg(unix)]
+const LOCAL_PATHS: [&str; 3] = ["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+/// The syslog facility, identifying the kind of program emitting the records.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    AuthPriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// The message format used on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// The BSD syslog protocol, understood by every daemon.
+    Rfc3164,
+    /// The structured syslog protocol.
+    Rfc5424,
+}
+
+enum Transport {
+    #[cfg(unix)]
+    Unix(Mutex<UnixDatagram>, PathBuf),
+    Udp(UdpSocket),
+    Tcp(Mutex<TcpWriter>),
+}
+
+/// A [`Sink`] sending records to a syslog daemon.
+pub struct Syslog {
+    transport: Transport,
+    facility: Facility,
+    protocol: Protocol,
+    hostname: String,
+    app_name: String,
+    pid: u32,
+}
+
+impl Syslog {
+    /// Connects to the local syslog daemon through one of the well-known socket paths.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if none of the sockets accepts the connection.
+    #[cfg(unix)]
+    pub fn local(facility: Facility) -> io::Result<Syslog> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no syslog socket found");
+        for path in LOCAL_PATHS {
+            match Syslog::unix(path, facility) {
+                Ok(syslog) => return Ok(syslog),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Connects to a syslog daemon listening on the given Unix datagram socket.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the socket cannot be connected to.
+    #[cfg(unix)]
+    pub fn unix<P: AsRef<Path>>(path: P, facility: Facility) -> io::Result<Syslog> {
+        let path = path.as_ref().to_path_buf();
+        let socket = connect_unix(&path)?;
+        Ok(Syslog::new(
+            Transport::Unix(Mutex::new(socket), path),
+            facility,
+        ))
+    }
+
+    /// Sends records to a remote syslog endpoint over UDP.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the address cannot be resolved or a local socket cannot be bound.
+    pub fn udp<A: ToSocketAddrs>(server: A, facility: Facility) -> io::Result<Syslog> {
+        let socket = net::udp_socket(server)?;
+        Ok(Syslog::new(Transport::Udp(socket), facility))
+    }
+
+    /// Sends records to a remote syslog endpoint over TCP, framed as described in RFC 6587.
+    ///
+    /// The connection is made on first use, and made again when it drops.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the address cannot be resolved.
+    pub fn tcp<A: ToSocketAddrs>(server: A, facility: Facility) -> io::Result<Syslog> {
+        let writer = TcpWriter::new(&net::resolve(server)?.to_string());
+        Ok(Syslog::new(Transport::Tcp(Mutex::new(writer)), facility))
+    }
+
+    fn new(transport: Transport, facility: Facility) -> Syslog {
+        Syslog {
+            transport,
+            facility,
+            protocol: Protocol::Rfc3164,
+            hostname: hostname().to_string(),
+            app_name: app_name(),
+            pid: std::process::id(),
+        }
+    }
+
+    /// Sets the message format, RFC 3164 by default.
+    pub fn protocol(mut self, protocol: Protocol) -> Syslog {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Sets the application name (the `TAG` of RFC 3164), the executable name by default.
+    pub fn app_name(mut self, app_name: &str) -> Syslog {
+        self.app_name = app_name.to_string();
+        self
+    }
+
+    fn priority(&self, level: Level) -> u8 {
+        (self.facility as u8) << 3 | syslog_severity(level)
+    }
+
+    fn render(&self, record: &Record) -> String {
+        let now = timestamp().map(|now| now.to_string());
+        match self.protocol {
+            // Without a timestamp the header is left out, and the daemon fills it in.
+            Protocol::Rfc3164 => format!(
+                "<{}>{}{}[{}]: {} > {}",
+                self.priority(record.level()),
+                now.map_or_else(String::new, |now| format!(
+                    "{} {} ",
+                    bsd_timestamp(&now),
+                    self.hostname
+                )),
+                self.app_name,
+                self.pid,
+                record.target(),
+                record.args(),
+            ),
+            Protocol::Rfc5424 => format!(
+                "<{}>1 {} {} {} {} - - {} > {}",
+                self.priority(record.level()),
+                now.as_deref().unwrap_or("-"),
+                self.hostname,
+                self.app_name,
+                self.pid,
+                record.target(),
+                record.args(),
+            ),
+        }
+    }
+}
+
+impl Sink for Syslog {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let message = self.render(record);
+        match &self.transport {
+            #[cfg(unix)]
+            Transport::Unix(socket, path) => {
+                let mut socket = socket.lock().unwrap_or_else(|e| e.into_inner());
+                if let Err(e) = socket.send(message.as_bytes()) {
+                    // The daemon may have restarted, listening on a new socket at the same path.
+                    *socket = connect_unix(path).map_err(|_| e)?;
+                    socket.send(message.as_bytes())?;
+                }
+                Ok(())
+            }
+            Transport::Udp(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            Transport::Tcp(writer) => {
+                // Sent as a single write, so a reconnection never splits a frame.
+                let frame = match self.protocol {
+                    // Records are newline terminated, so line breaks within them are escaped.
+                    Protocol::Rfc3164 => format!("{}\n", escape_newlines(&message)),
+                    Protocol::Rfc5424 => format!("{} {}", message.len(), message),
+                };
+                let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+                writer.write_all(frame.as_bytes())
+            }
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        match &self.transport {
+            Transport::Tcp(stream) => stream.lock().unwrap_or_else(|e| e.into_inner()).flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &Path) -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+fn escape_newlines(message: &str) -> String {
+    message.replace('\r', "\\r").replace('\n', "\\n")
+}
+
+/// Converts an RFC 3339 timestamp to the `Mmm dd hh:mm:ss` form of RFC 3164.
+fn bsd_timestamp(rfc3339: &str) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month: usize = rfc3339[5..7].parse().unwrap_or(1);
+    let day: u8 = rfc3339[8..10].parse().unwrap_or(1);
+    format!("{} {:>2} {}", MONTHS[month - 1], day, &rfc3339[11..19])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn record<'a>(args: std::fmt::Arguments<'a>) -> Record<'a> {
+        Record::builder()
+            .level(Level::Warn)
+            .target("syslog")
+            .args(args)
+            .build()
+    }
+
+    #[test]
+    fn escapes_line_breaks_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let syslog = Syslog::tcp(listener.local_addr().unwrap(), Facility::Daemon).unwrap();
+        syslog
+            .write(&record(format_args!("first line\nsecond line")))
+            .unwrap();
+        syslog.flush().unwrap();
+        drop(syslog);
+
+        let mut received = String::new();
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_to_string(&mut received).unwrap();
+        assert!(received.starts_with("<28>"), "{}", received);
+        assert!(
+            received.ends_with("syslog > first line\\nsecond line\n"),
+            "{}",
+            received
+        );
+    }
+
+    #[test]
+    fn binds_udp_to_the_family_of_the_server() {
+        // Hosts without IPv6, such as some containers, only check IPv4.
+        let servers = ["127.0.0.1:0", "[::1]:0"].map(UdpSocket::bind);
+        for server in servers.iter().flatten() {
+            let syslog = Syslog::udp(server.local_addr().unwrap(), Facility::User).unwrap();
+            syslog.write(&record(format_args!("over udp"))).unwrap();
+
+            let mut buf = [0; 1024];
+            let len = server.recv(&mut buf).unwrap();
+            assert!(String::from_utf8_lossy(&buf[..len]).ends_with("syslog > over udp"));
+        }
+        assert!(servers[0].is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reconnects_when_the_daemon_restarts() {
+        let dir = std::env::temp_dir().join(format!("syslog-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log");
+        let _ = std::fs::remove_file(&path);
+
+        let daemon = UnixDatagram::bind(&path).unwrap();
+        let syslog = Syslog::unix(&path, Facility::User).unwrap();
+        drop(daemon);
+        std::fs::remove_file(&path).unwrap();
+        let daemon = UnixDatagram::bind(&path).unwrap();
+
+        syslog
+            .write(&record(format_args!("after restart")))
+            .unwrap();
+        let mut buf = [0; 1024];
+        let len = daemon.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).ends_with("syslog > after restart"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
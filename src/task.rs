@@ -0,0 +1,74 @@
+//! A task-local diagnostic context and scopes for async code running on tokio.
+//!
+//! Thread-local context from the [`mdc`](crate::mdc) module does not follow tasks moving between
+//! worker threads across `.await` points. Pairs set with [`with_context`] are instead attached
+//! to every record logged while the given future runs, wherever it is polled.
+//!
+//! ```
+//! use pretty_flexible_env_logger::task::with_context;
+//!
+//! # let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+//! # runtime.block_on(async {
+//! with_context([("request_id", 42)], async {
+//!     log::info!("handling request"); // ... request_id=42
+//! })
+//! .await;
+//! # });
+//! ```
+
+use std::future::Future;
+
+tokio::task_local! {
+    static CONTEXT: Vec<(String, String)>;
+    static SCOPES: Vec<String>;
+}
+
+/// Runs `future` with the given pairs added to the context of the current task.
+///
+/// Contexts nest: pairs of enclosing calls are kept, unless replaced by pairs with the same key.
+pub fn with_context<I, K, V, F>(pairs: I, future: F) -> impl Future<Output = F::Output>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: ToString,
+    V: ToString,
+    F: Future,
+{
+    let mut context = CONTEXT.try_with(Clone::clone).unwrap_or_default();
+    for (key, value) in pairs {
+        let (key, value) = (key.to_string(), value.to_string());
+        match context.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => context.push((key, value)),
+        }
+    }
+    CONTEXT.scope(context, future)
+}
+
+/// Runs `future` within a named scope, prefixing the message of every record it logs with the
+/// name.
+///
+/// This is the async variant of [`with_scope`](crate::with_scope), following the task across
+/// `.await` points.
+pub fn with_scope<F: Future>(name: &str, future: F) -> impl Future<Output = F::Output> {
+    let mut scopes = SCOPES.try_with(Clone::clone).unwrap_or_default();
+    scopes.push(name.to_string());
+    SCOPES.scope(scopes, future)
+}
+
+/// Calls `f` with each scope of the current task, outermost first.
+pub(crate) fn for_each_scope(mut f: impl FnMut(&str)) {
+    let _ = SCOPES.try_with(|scopes| {
+        for name in scopes {
+            f(name);
+        }
+    });
+}
+
+/// Calls `f` with each pair in the context of the current task, if any.
+pub(crate) fn for_each(mut f: impl FnMut(&str, &str)) {
+    let _ = CONTEXT.try_with(|context| {
+        for (key, value) in context {
+            f(key, value);
+        }
+    });
+}
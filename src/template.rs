@@ -0,0 +1,280 @@
+//! Line layouts described by template strings.
+//!
+//! Templates are made of literal text and fields in braces, optionally followed by an alignment
+//! and a width in the same form as `std::fmt`: `{timestamp} [{level:>5}] {target} — {message}`.
+//! Braces are escaped by doubling them.
+//!
+//! The following fields are available:
+//!
+//! * `timestamp` - the time the record was written, in RFC 3339 format,
+//! * `level` - the level of the record, colored when the destination supports it,
+//! * `target` - the target of the record,
+//! * `message` - the message of the record,
+//! * `module_path` - the module path the record originates from,
+//! * `file` - the source file the record originates from,
+//! * `line` - the line in the source file the record originates from,
+//! * `thread` - the name of the current thread, or its ID if it is unnamed,
+//! * `pid` - the process ID,
+//! * `hostname` - the name of the machine,
+//! * `kvs` - the key-values attached to the record, as space-separated `key=value` pairs.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use log::Record;
+use termcolor::{ColorSpec, WriteColor};
+
+use crate::fmt::{level_style, thread_name, timestamp};
+use crate::kv;
+use crate::process::hostname;
+
+/// A parsed template, rendering records one line at a time.
+pub(crate) struct Template {
+    pieces: Vec<Piece>,
+}
+
+enum Piece {
+    Literal(String),
+    Field {
+        field: Field,
+        align: Align,
+        width: usize,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Timestamp,
+    Level,
+    Target,
+    Message,
+    ModulePath,
+    File,
+    Line,
+    Thread,
+    Pid,
+    Hostname,
+    Kvs,
+}
+
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// The error returned when a template string cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    message: String,
+}
+
+impl TemplateError {
+    fn new(message: String) -> TemplateError {
+        TemplateError { message }
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid template: {}", self.message)
+    }
+}
+
+impl Error for TemplateError {}
+
+impl Template {
+    pub(crate) fn parse(template: &str) -> Result<Template, TemplateError> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => {
+                                return Err(TemplateError::new(format!(
+                                    "unclosed field `{{{}`",
+                                    spec
+                                )))
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(parse_field(&spec)?);
+                }
+                '}' => return Err(TemplateError::new("unmatched `}`".to_string())),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Ok(Template { pieces })
+    }
+
+    pub(crate) fn write(&self, buf: &mut dyn WriteColor, record: &Record) -> io::Result<()> {
+        for piece in &self.pieces {
+            let (field, align, width) = match piece {
+                Piece::Literal(text) => {
+                    buf.write_all(text.as_bytes())?;
+                    continue;
+                }
+                Piece::Field {
+                    field,
+                    align,
+                    width,
+                } => (*field, *align, *width),
+            };
+
+            let value = match field {
+                Field::Timestamp => timestamp().map(|now| now.to_string()),
+                Field::Level => Some(record.level().to_string()),
+                Field::Target => Some(record.target().to_string()),
+                Field::Message => Some(record.args().to_string()),
+                Field::ModulePath => record.module_path().map(str::to_string),
+                Field::File => record.file().map(str::to_string),
+                Field::Line => record.line().map(|line| line.to_string()),
+                Field::Thread => Some(thread_name()),
+                Field::Pid => Some(std::process::id().to_string()),
+                Field::Hostname => Some(hostname().to_string()),
+                Field::Kvs => Some(
+                    kv::collect(record)
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, value))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+            };
+            let value = value.unwrap_or_default();
+
+            if let Field::Level = field {
+                let (_, color) = level_style(record.level());
+                buf.set_color(ColorSpec::new().set_fg(Some(color)))?;
+            }
+            match align {
+                Align::Left => write!(buf, "{:<width$}", value, width = width)?,
+                Align::Center => write!(buf, "{:^width$}", value, width = width)?,
+                Align::Right => write!(buf, "{:>width$}", value, width = width)?,
+            }
+            if let Field::Level = field {
+                buf.reset()?;
+            }
+        }
+        writeln!(buf)
+    }
+}
+
+fn parse_field(spec: &str) -> Result<Piece, TemplateError> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name.trim(), format.trim()),
+        None => (spec.trim(), ""),
+    };
+
+    let field = match name {
+        "timestamp" => Field::Timestamp,
+        "level" => Field::Level,
+        "target" => Field::Target,
+        "message" => Field::Message,
+        "module_path" => Field::ModulePath,
+        "file" => Field::File,
+        "line" => Field::Line,
+        "thread" => Field::Thread,
+        "pid" => Field::Pid,
+        "hostname" => Field::Hostname,
+        "kvs" => Field::Kvs,
+        _ => return Err(TemplateError::new(format!("unknown field `{}`", name))),
+    };
+
+    let (align, width) = match format.chars().next() {
+        Some('<') => (Align::Left, &format[1..]),
+        Some('^') => (Align::Center, &format[1..]),
+        Some('>') => (Align::Right, &format[1..]),
+        _ => (Align::Left, format),
+    };
+    let width = match width {
+        "" => 0,
+        width => width
+            .parse()
+            .map_err(|_| TemplateError::new(format!("invalid width in `{{{}}}`", spec)))?,
+    };
+
+    Ok(Piece::Field {
+        field,
+        align,
+        width,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+    use termcolor::Buffer;
+
+    use super::*;
+
+    fn render(template: &str, record: &Record) -> String {
+        let mut buf = Buffer::no_color();
+        Template::parse(template)
+            .unwrap()
+            .write(&mut buf, record)
+            .unwrap();
+        String::from_utf8(buf.into_inner()).unwrap()
+    }
+
+    fn error(template: &str) -> String {
+        match Template::parse(template) {
+            Ok(_) => panic!("`{}` was parsed", template),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn aligns_fields_and_unescapes_braces() {
+        let kvs = [("user", "ana"), ("attempt", "2")];
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("auth")
+            .args(format_args!("login failed"))
+            .line(Some(42))
+            .key_values(&kvs)
+            .build();
+        assert_eq!(
+            render(
+                "[{level:>5}] {{{target:<6}}} {line:^6}|{ message } {kvs}",
+                &record
+            ),
+            "[ WARN] {auth  }   42  |login failed user=ana attempt=2\n"
+        );
+        assert_eq!(render("{file}|{module_path}", &record), "|\n");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert_eq!(error("{level"), "invalid template: unclosed field `{level`");
+        assert_eq!(error("level}"), "invalid template: unmatched `}`");
+        assert_eq!(error("{lvl}"), "invalid template: unknown field `lvl`");
+        assert_eq!(
+            error("{level:>five}"),
+            "invalid template: invalid width in `{level:>five}`"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Overriding the filters for the current thread.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::{Mutex, MutexGuard};
+
+use env_logger::filter::{self, Filter};
+use log::{LevelFilter, Metadata, Record};
+
+use crate::builder;
+
+thread_local! {
+    /// The filters of the current thread, keeping the global maximum level raised to theirs.
+    static OVERRIDE: RefCell<Option<(Override, Raised)>> = const { RefCell::new(None) };
+}
+
+/// The levels the global maximum is kept raised to, by the thread filters and the captures
+/// alive in any thread.
+static RAISED: Mutex<Vec<LevelFilter>> = Mutex::new(Vec::new());
+
+/// What the records logged by a thread are filtered by instead of the directives.
+#[derive(Clone)]
+enum Override {
+    Level(LevelFilter),
+    Filter(Rc<Filter>),
+}
+
+/// Filters the records logged by the current thread by `level` alone, instead of the directives,
+/// so a single worker under investigation can be made verbose, or silenced, without affecting
+/// its siblings.
+///
+/// Raising the level above the global maximum raises the maximum too, which lets records of
+/// the other threads reach the logger before being filtered out by the directives.
+///
+/// ```
+/// use log::LevelFilter;
+/// use pretty_flexible_env_logger::{reset_thread_level, set_thread_level};
+///
+/// pretty_flexible_env_logger::init_with("info");
+///
+/// std::thread::spawn(|| {
+///     set_thread_level(LevelFilter::Trace);
+///     log::trace!("written, from this thread only");
+///     reset_thread_level();
+/// });
+/// ```
+pub fn set_thread_level(level: LevelFilter) {
+    replace(Some(Override::Level(level)));
+}
+
+/// Goes back to filtering the records logged by the current thread with the directives.
+pub fn reset_thread_level() {
+    replace(None);
+}
+
+/// Filters the records logged by the current thread with other directives until the returned
+/// guard is dropped, installing a logger following `RUST_LOG` first if there is none, in
+/// [test mode](crate::Builder::is_test).
+///
+/// Tests run on threads of their own, so each of them can opt into verbose logging without
+/// affecting the others, nor fighting over which one initializes the logger. The directives
+/// are resolved as with [`init_with`](crate::init_with), and dropping the guard restores the
+/// filters the thread had before, and flushes the logger.
+///
+/// ```
+/// // In a test:
+/// let _guard = pretty_flexible_env_logger::scoped("trace");
+///
+/// log::trace!("written while the guard lives");
+/// ```
+pub fn scoped(environment_or_inline_value: &str) -> ScopedGuard {
+    crate::__init_test();
+    let filter = filter::Builder::new()
+        .parse(&crate::resolve(environment_or_inline_value))
+        .build();
+    ScopedGuard {
+        previous: replace(Some(Override::Filter(Rc::new(filter)))),
+        _thread: PhantomData,
+    }
+}
+
+/// Restores the filters of the thread when dropped, see [`scoped`].
+#[must_use = "the filters are restored when the guard is dropped"]
+pub struct ScopedGuard {
+    previous: Option<Override>,
+    // The filters belong to the thread the guard was created on.
+    _thread: PhantomData<Rc<()>>,
+}
+
+impl Drop for ScopedGuard {
+    fn drop(&mut self) {
+        replace(self.previous.take());
+        log::logger().flush();
+    }
+}
+
+/// Whether the filters set for the current thread, if any, let records with this metadata
+/// through.
+pub(crate) fn enabled(metadata: &Metadata) -> Option<bool> {
+    with_current(|current| match current {
+        Override::Level(level) => metadata.level() <= *level,
+        Override::Filter(filter) => filter.enabled(metadata),
+    })
+}
+
+/// Whether the filters set for the current thread, if any, let the record through.
+pub(crate) fn matches(record: &Record) -> Option<bool> {
+    with_current(|current| match current {
+        Override::Level(level) => record.level() <= *level,
+        Override::Filter(filter) => filter.matches(record),
+    })
+}
+
+fn with_current(f: impl FnOnce(&Override) -> bool) -> Option<bool> {
+    OVERRIDE
+        .try_with(|current| current.borrow().as_ref().map(|(current, _)| f(current)))
+        .ok()
+        .flatten()
+}
+
+/// Sets the filters of the current thread, returning the previous ones.
+fn replace(filters: Option<Override>) -> Option<Override> {
+    let filters = filters.map(|filters| {
+        let raised = raise(filters.level());
+        (filters, raised)
+    });
+    OVERRIDE
+        .with(|current| current.replace(filters))
+        .map(|(previous, _)| previous)
+}
+
+impl Override {
+    /// The most verbose level let through.
+    fn level(&self) -> LevelFilter {
+        match self {
+            Override::Level(level) => *level,
+            Override::Filter(filter) => filter.filter(),
+        }
+    }
+}
+
+/// Keeps the global maximum level raised to a level while alive, so the records of a thread
+/// filtered on its own reach the logger, see [`raise`].
+pub(crate) struct Raised(LevelFilter);
+
+/// Raises the global maximum level to `level` until the returned value is dropped.
+pub(crate) fn raise(level: LevelFilter) -> Raised {
+    let mut raised = lock_raised();
+    raised.push(level);
+    if level > log::max_level() {
+        log::set_max_level(level);
+    }
+    Raised(level)
+}
+
+impl Drop for Raised {
+    fn drop(&mut self) {
+        let mut raised = lock_raised();
+        if let Some(i) = raised.iter().position(|level| *level == self.0) {
+            raised.swap_remove(i);
+        }
+        // Only the logger of this crate is known to leave out more verbose records.
+        if let Some(logger) = builder::installed() {
+            log::set_max_level(max_of(&raised, logger.filter()));
+        }
+    }
+}
+
+/// Sets the global maximum level to `level`, or to the most verbose level the thread filters
+/// and the captures alive rely on.
+pub(crate) fn set_max_level(level: LevelFilter) {
+    let raised = lock_raised();
+    log::set_max_level(max_of(&raised, level));
+}
+
+fn max_of(raised: &[LevelFilter], level: LevelFilter) -> LevelFilter {
+    raised.iter().fold(level, |max, level| max.max(*level))
+}
+
+fn lock_raised() -> MutexGuard<'static, Vec<LevelFilter>> {
+    RAISED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Builder;
+
+    #[test]
+    fn swap_keeps_the_max_level_of_thread_filters() {
+        set_thread_level(LevelFilter::Trace);
+        crate::swap(Builder::with("error").writer(Box::new(std::io::sink()))).unwrap();
+        assert_eq!(log::max_level(), LevelFilter::Trace);
+        assert_eq!(
+            matches(&Record::builder().level(log::Level::Trace).build()),
+            Some(true)
+        );
+        reset_thread_level();
+        assert_eq!(matches(&Record::builder().build()), None);
+    }
+}
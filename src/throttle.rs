@@ -0,0 +1,318 @@
+//! Keeping floods of records from reaching the sinks.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{Level, Record};
+
+/// A limit of records per window of time, for the targets starting with a prefix.
+pub(crate) struct RateLimit {
+    target: String,
+    max: u32,
+    window: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    start: Instant,
+    count: u32,
+    suppressed: u64,
+    /// When the suppressed records were last reported.
+    reported: Instant,
+}
+
+impl RateLimit {
+    pub(crate) fn new(target: &str, max: u32, window: Duration) -> RateLimit {
+        let now = Instant::now();
+        RateLimit {
+            target: target.to_string(),
+            max,
+            window,
+            state: Mutex::new(State {
+                start: now,
+                count: 0,
+                suppressed: 0,
+                reported: now,
+            }),
+        }
+    }
+
+    pub(crate) fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Counts a record against the limit, returning whether it is written, and the records
+    /// suppressed since the last report when this record starts a new window.
+    pub(crate) fn admit(&self) -> (bool, Option<Suppressed>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let mut suppressed = None;
+        if now.duration_since(state.start) >= self.window {
+            suppressed = state.take(now);
+            state.start = now;
+            state.count = 0;
+        }
+        if state.count < self.max {
+            state.count += 1;
+            (true, suppressed)
+        } else {
+            state.suppressed += 1;
+            (false, suppressed)
+        }
+    }
+
+    /// Returns the records suppressed since the last report, if any, so they are reported even
+    /// when no record follows them.
+    pub(crate) fn take(&self) -> Option<Suppressed> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.take(Instant::now())
+    }
+}
+
+impl State {
+    fn take(&mut self, now: Instant) -> Option<Suppressed> {
+        if self.suppressed == 0 {
+            return None;
+        }
+        let suppressed = Suppressed {
+            count: std::mem::take(&mut self.suppressed),
+            elapsed: now.duration_since(self.reported),
+        };
+        self.reported = now;
+        Some(suppressed)
+    }
+}
+
+/// The records suppressed by a [`RateLimit`] since its last report.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Suppressed {
+    pub(crate) count: u64,
+    /// The time elapsed since the last report, over which the records were suppressed.
+    pub(crate) elapsed: Duration,
+}
+
+/// Collapses runs of identical consecutive records.
+#[derive(Default)]
+pub(crate) struct Dedup {
+    last: Mutex<Option<Repeated>>,
+}
+
+/// The last record written, and how many times it was repeated since.
+pub(crate) struct Repeated {
+    pub(crate) level: Level,
+    pub(crate) target: String,
+    message: String,
+    pub(crate) count: u64,
+}
+
+impl Dedup {
+    /// Checks whether the record repeats the previous one, returning whether it is written, and
+    /// the previous record if it was repeated and the run ends with this record.
+    pub(crate) fn admit(&self, record: &Record) -> (bool, Option<Repeated>) {
+        let message = record.args().to_string();
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(last) = last.as_mut() {
+            if last.level == record.level()
+                && last.target == record.target()
+                && last.message == message
+            {
+                last.count += 1;
+                return (false, None);
+            }
+        }
+        let previous = last.replace(Repeated {
+            level: record.level(),
+            target: record.target().to_string(),
+            message,
+            count: 0,
+        });
+        (true, previous.filter(|previous| previous.count > 0))
+    }
+
+    /// Ends the current run, returning the last record if it was repeated.
+    pub(crate) fn take(&self) -> Option<Repeated> {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let repeated = last.as_mut().filter(|last| last.count > 0)?;
+        let count = std::mem::take(&mut repeated.count);
+        Some(Repeated {
+            level: repeated.level,
+            target: repeated.target.clone(),
+            message: String::new(),
+            count,
+        })
+    }
+}
+
+/// Keeps a random fraction of the records of a level.
+pub(crate) struct Sampler {
+    rate: f64,
+    /// Records dropped since the last one kept.
+    dropped: AtomicU64,
+    /// State of the random number generator.
+    state: AtomicU64,
+}
+
+impl Sampler {
+    pub(crate) fn new(rate: f64) -> Sampler {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_nanos() as u64);
+        Sampler {
+            rate: rate.clamp(0.0, 1.0),
+            dropped: AtomicU64::new(0),
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Decides whether a record is kept, returning the number of records it stands for, itself
+    /// included, if so.
+    pub(crate) fn admit(&self) -> Option<u64> {
+        if self.random() < self.rate {
+            Some(self.dropped.swap(0, Ordering::Relaxed) + 1)
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+
+    /// Returns a number in `[0, 1)`, from the SplitMix64 generator.
+    fn random(&self) -> f64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Counts the records discarded without a summary of their own, to be reported periodically.
+pub(crate) struct LossReport {
+    interval: Duration,
+    sampled: AtomicU64,
+    overflowed: AtomicU64,
+    last: Mutex<Instant>,
+}
+
+impl LossReport {
+    pub(crate) fn new(interval: Duration) -> LossReport {
+        LossReport {
+            interval,
+            sampled: AtomicU64::new(0),
+            overflowed: AtomicU64::new(0),
+            last: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Counts a record left out by sampling.
+    pub(crate) fn sampled(&self) {
+        self.sampled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a record dropped because the queue of the background writer was full.
+    pub(crate) fn overflowed(&self) {
+        self.overflowed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of records lost by sampling and by overflow since the last report and
+    /// the time elapsed since, if any were lost and the interval elapsed, or `force` is set.
+    pub(crate) fn due(&self, force: bool) -> Option<(u64, u64, Duration)> {
+        if self.sampled.load(Ordering::Relaxed) == 0 && self.overflowed.load(Ordering::Relaxed) == 0
+        {
+            return None;
+        }
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if !force && now.duration_since(*last) < self.interval {
+            return None;
+        }
+        let elapsed = now.duration_since(*last);
+        *last = now;
+        let sampled = self.sampled.swap(0, Ordering::Relaxed);
+        let overflowed = self.overflowed.swap(0, Ordering::Relaxed);
+        (sampled + overflowed > 0).then_some((sampled, overflowed, elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn admit(dedup: &Dedup, message: &str) -> (bool, Option<u64>) {
+        let (admitted, repeated) = dedup.admit(
+            &Record::builder()
+                .level(Level::Info)
+                .target("dedup")
+                .args(format_args!("{}", message))
+                .build(),
+        );
+        (admitted, repeated.map(|repeated| repeated.count))
+    }
+
+    #[test]
+    fn rate_limit_reports_the_suppressed_records_once() {
+        let limit = RateLimit::new("storm", 2, Duration::from_millis(50));
+        assert_eq!(limit.admit(), (true, None));
+        assert_eq!(limit.admit(), (true, None));
+        assert_eq!(limit.admit(), (false, None));
+        assert_eq!(limit.admit(), (false, None));
+
+        thread::sleep(Duration::from_millis(60));
+        let (admitted, suppressed) = limit.admit();
+        assert!(admitted);
+        let suppressed = suppressed.unwrap();
+        assert_eq!(suppressed.count, 2);
+        assert!(suppressed.elapsed >= Duration::from_millis(50));
+        assert_eq!(limit.take(), None);
+    }
+
+    #[test]
+    fn rate_limit_reports_the_suppressed_records_without_a_next_record() {
+        let limit = RateLimit::new("storm", 1, Duration::from_secs(3600));
+        assert_eq!(limit.admit(), (true, None));
+        assert_eq!(limit.admit(), (false, None));
+        assert_eq!(limit.take().map(|suppressed| suppressed.count), Some(1));
+        assert_eq!(limit.take(), None);
+
+        // The window is still the same, so the limit still applies.
+        assert_eq!(limit.admit(), (false, None));
+        assert_eq!(limit.take().map(|suppressed| suppressed.count), Some(1));
+    }
+
+    #[test]
+    fn dedup_collapses_runs_of_identical_records() {
+        let dedup = Dedup::default();
+        assert_eq!(admit(&dedup, "connection refused"), (true, None));
+        assert_eq!(admit(&dedup, "connection refused"), (false, None));
+        assert_eq!(admit(&dedup, "connection refused"), (false, None));
+        assert_eq!(admit(&dedup, "connected"), (true, Some(2)));
+        assert_eq!(admit(&dedup, "connection refused"), (true, None));
+        assert!(dedup.take().is_none());
+
+        assert_eq!(admit(&dedup, "connection refused"), (false, None));
+        let repeated = dedup.take().unwrap();
+        assert_eq!((repeated.level, repeated.count), (Level::Info, 1));
+        assert_eq!(repeated.target, "dedup");
+        assert!(dedup.take().is_none());
+    }
+
+    #[test]
+    fn sampler_accounts_for_every_record() {
+        let all = Sampler::new(1.0);
+        assert!((0..100).all(|_| all.admit() == Some(1)));
+
+        let none = Sampler::new(0.0);
+        assert!((0..100).all(|_| none.admit().is_none()));
+
+        let half = Sampler::new(0.5);
+        let kept: Vec<u64> = (0..10_000).filter_map(|_| half.admit()).collect();
+        assert!((4_500..5_500).contains(&kept.len()), "{}", kept.len());
+        let dropped = half.dropped.load(Ordering::Relaxed);
+        assert_eq!(kept.iter().sum::<u64>() + dropped, 10_000);
+    }
+}
@@ -0,0 +1,280 @@
+//! Writing [`tracing`][tracing] events through the logger, in the same format as `log` records.
+//!
+//! The [`Subscriber`] turns every event into a record for the global logger, so the directives,
+//! hooks, formats and sinks configured with the [`Builder`](crate::Builder) apply to `tracing`
+//! events too, and codebases mixing both crates get uniform output. The names of the spans an
+//! event occurred in prefix its message, as in `request:db: query failed`, and their fields
+//! are written before the fields of the event.
+//!
+//! The subscriber can be installed by hand, or with
+//! [`Builder::capture_tracing`](crate::Builder::capture_tracing).
+//!
+//! ```
+//! use pretty_flexible_env_logger::tracing::Subscriber;
+//!
+//! pretty_flexible_env_logger::init_with("info");
+//! tracing::subscriber::set_global_default(Subscriber::new()).unwrap();
+//!
+//! let _span = tracing::info_span!("request", id = 7).entered();
+//! tracing::info!(rows = 3, "query done"); // INFO  app > request: query done id=7 rows=3
+//! log::info!("from log");
+//! ```
+//!
+//! [tracing]: https://docs.rs/tracing
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use log::kv::{Error, Key, Source, Value, VisitSource};
+use log::Record;
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Current, Id, Record as Values};
+use tracing_core::{Event, Level, LevelFilter, Metadata};
+
+thread_local! {
+    /// The spans entered on the current thread, innermost last.
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A `tracing` subscriber writing events to the global logger, see the [module](self)
+/// documentation.
+pub struct Subscriber {
+    spans: Mutex<HashMap<u64, Span>>,
+    next_id: AtomicU64,
+}
+
+struct Span {
+    metadata: &'static Metadata<'static>,
+    fields: Vec<(String, String)>,
+    parent: Option<u64>,
+    refs: usize,
+}
+
+impl Subscriber {
+    /// Creates a subscriber writing to the global logger, installed with this crate or any
+    /// other.
+    pub fn new() -> Subscriber {
+        Subscriber {
+            spans: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn spans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Span>> {
+        self.spans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The span new spans and events belong to when not given explicitly.
+    fn current(&self) -> Option<u64> {
+        ENTERED
+            .try_with(|entered| entered.borrow().last().copied())
+            .ok()
+            .flatten()
+    }
+}
+
+impl Default for Subscriber {
+    fn default() -> Subscriber {
+        Subscriber::new()
+    }
+}
+
+impl tracing_core::Subscriber for Subscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        log::logger().enabled(
+            &log::Metadata::builder()
+                .level(level(metadata.level()))
+                .target(metadata.target())
+                .build(),
+        )
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(match log::max_level().to_level() {
+            Some(max) => LevelFilter::from_level(match max {
+                log::Level::Error => Level::ERROR,
+                log::Level::Warn => Level::WARN,
+                log::Level::Info => Level::INFO,
+                log::Level::Debug => Level::DEBUG,
+                log::Level::Trace => Level::TRACE,
+            }),
+            None => LevelFilter::OFF,
+        })
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        attributes.record(&mut fields);
+        let parent = match attributes.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if attributes.is_contextual() => self.current(),
+            None => None,
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut spans = self.spans();
+        if let Some(parent) = parent.and_then(|parent| spans.get_mut(&parent)) {
+            parent.refs += 1;
+        }
+        spans.insert(
+            id,
+            Span {
+                metadata: attributes.metadata(),
+                fields: fields.fields,
+                parent,
+                refs: 1,
+            },
+        );
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Values<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        if let Some(span) = self.spans().get_mut(&span.into_u64()) {
+            span.fields.extend(fields.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let leaf = match event.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if event.is_contextual() => self.current(),
+            None => None,
+        };
+        let mut path = Vec::new();
+        let mut context = Vec::new();
+        {
+            let spans = self.spans();
+            let mut next = leaf;
+            while let Some(span) = next.and_then(|id| spans.get(&id)) {
+                path.push(span.metadata.name());
+                context.splice(0..0, span.fields.iter().cloned());
+                next = span.parent;
+            }
+        }
+        let mut message = String::new();
+        for name in path.iter().rev() {
+            let _ = write!(message, "{}:", name);
+        }
+        if !message.is_empty() {
+            message.push(' ');
+        }
+        message.push_str(&fields.message);
+        context.extend(fields.fields);
+
+        let metadata = event.metadata();
+        log::logger().log(
+            &Record::builder()
+                .level(level(metadata.level()))
+                .target(metadata.target())
+                .module_path(metadata.module_path())
+                .file(metadata.file())
+                .line(metadata.line())
+                .args(format_args!("{}", message))
+                .key_values(&Pairs(&context))
+                .build(),
+        );
+    }
+
+    fn enter(&self, span: &Id) {
+        let _ = ENTERED.try_with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        let _ = ENTERED.try_with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(i) = entered.iter().rposition(|&id| id == span.into_u64()) {
+                entered.remove(i);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(span) = self.spans().get_mut(&span.into_u64()) {
+            span.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let closing = span.into_u64();
+        let mut spans = self.spans();
+        let mut next = Some(closing);
+        let mut closed = false;
+        // Closing a span releases its hold on its parent.
+        while let Some(id) = next {
+            let Some(span) = spans.get_mut(&id) else {
+                break;
+            };
+            span.refs -= 1;
+            if span.refs > 0 {
+                break;
+            }
+            next = span.parent;
+            spans.remove(&id);
+            closed |= id == closing;
+        }
+        closed
+    }
+
+    fn current_span(&self) -> Current {
+        let spans = self.spans();
+        match self.current().and_then(|id| Some((id, spans.get(&id)?))) {
+            Some((id, span)) => Current::new(Id::from_u64(id), span.metadata),
+            None => Current::none(),
+        }
+    }
+}
+
+fn level(level: &Level) -> log::Level {
+    match *level {
+        Level::ERROR => log::Level::Error,
+        Level::WARN => log::Level::Warn,
+        Level::INFO => log::Level::Info,
+        Level::DEBUG => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+/// The fields of a span or an event, with the message of events kept apart.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            name => self.fields.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            name => self.fields.push((name.to_string(), format!("{:?}", value))),
+        }
+    }
+}
+
+/// Key-values rendered as strings.
+struct Pairs<'a>(&'a [(String, String)]);
+
+impl Source for Pairs<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        for (key, value) in self.0 {
+            visitor.visit_pair(Key::from_str(key), Value::from(value.as_str()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+//! Logging to the browser console.
+//!
+//! On `wasm32` targets with the `wasm` feature enabled, this is the default sink, so the usual
+//! `init*` functions work unchanged. Records are sent to `console.error`, `console.warn`,
+//! `console.info` or `console.debug` depending on their level, with the level label styled with
+//! CSS instead of ANSI escape codes.
+
+use std::io;
+
+use log::{Level, Record};
+use wasm_bindgen::JsValue;
+use web_sys::console;
+
+use crate::sink::Sink;
+
+/// A [`Sink`] writing records to the browser console.
+pub struct Console;
+
+impl Sink for Console {
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let (label, color) = match record.level() {
+            Level::Trace => ("TRACE", "magenta"),
+            Level::Debug => ("DEBUG", "blue"),
+            Level::Info => ("INFO ", "green"),
+            Level::Warn => ("WARN ", "orange"),
+            Level::Error => ("ERROR", "red"),
+        };
+        let format = JsValue::from(format!(
+            "%c{}%c {}%c > {}",
+            label,
+            escape(record.target()),
+            escape(&record.args().to_string()),
+        ));
+        let level_style = JsValue::from(format!("color: {}", color));
+        let target_style = JsValue::from("font-weight: bold");
+        let message_style = JsValue::from("");
+
+        let log = match record.level() {
+            Level::Error => console::error_4,
+            Level::Warn => console::warn_4,
+            Level::Info => console::info_4,
+            Level::Debug | Level::Trace => console::debug_4,
+        };
+        log(&format, &level_style, &target_style, &message_style);
+        Ok(())
+    }
+}
+
+/// Escapes `%`, which the console treats as a format specifier.
+fn escape(s: &str) -> String {
+    s.replace('%', "%%")
+}
@@ -0,0 +1,409 @@
+//! Writing records on a background thread, so logging never blocks on slow destinations.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use log::{Level, Record};
+
+use crate::owned::OwnedRecord;
+use crate::sink::Outputs;
+use crate::stats::Counters;
+use crate::throttle::LossReport;
+
+/// What the [`non_blocking`](crate::Builder::non_blocking) writer does with records logged
+/// while its queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Overflow {
+    /// The logging thread waits for room in the queue, so no record is lost.
+    #[default]
+    Block,
+    /// The record being logged is dropped.
+    DropNewest,
+    /// The oldest queued record is dropped to make room for the one being logged.
+    DropOldest,
+}
+
+thread_local! {
+    /// Whether records are queued whatever the overflow policy, set while the logger writes
+    /// records about itself.
+    static BLOCKING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, the records it logs waiting for room in the queue rather than being dropped, so
+/// reports about dropped records are never dropped themselves.
+pub(crate) fn blocking<R>(f: impl FnOnce() -> R) -> R {
+    let previous = BLOCKING.with(|blocking| blocking.replace(true));
+    let result = f();
+    BLOCKING.with(|blocking| blocking.set(previous));
+    result
+}
+
+/// A thread writing the records it is sent to the outputs.
+pub(crate) struct Worker {
+    queue: Arc<Queue>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// What happens to records which did not make it to the outputs.
+pub(crate) struct Losses {
+    pub(crate) stats: Arc<Counters>,
+    pub(crate) report: Option<Arc<LossReport>>,
+}
+
+enum Message {
+    Record(OwnedRecord),
+    Flush(mpsc::Sender<()>),
+    Stop,
+}
+
+/// A queue of messages for the thread, bounded for records.
+struct Queue {
+    state: Mutex<State>,
+    /// Signaled when a message is pushed.
+    pushed: Condvar,
+    /// Signaled when a message is popped.
+    popped: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+    losses: Losses,
+}
+
+struct State {
+    messages: VecDeque<Message>,
+    /// Records in `messages`.
+    records: usize,
+    stopped: bool,
+}
+
+impl Worker {
+    /// Starts the thread, with a queue of up to `capacity` records, or returns `None` where
+    /// threads are not available.
+    pub(crate) fn spawn(
+        outputs: Arc<Outputs>,
+        capacity: usize,
+        overflow: Overflow,
+        losses: Losses,
+    ) -> Option<Worker> {
+        let queue = Arc::new(Queue {
+            state: Mutex::new(State {
+                messages: VecDeque::new(),
+                records: 0,
+                stopped: false,
+            }),
+            pushed: Condvar::new(),
+            popped: Condvar::new(),
+            capacity: capacity.max(1),
+            overflow,
+            losses,
+        });
+        let handle = std::thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn({
+                let queue = queue.clone();
+                move || crate::sink::inside(|| run(&queue, &outputs))
+            })
+            .ok()?;
+        Some(Worker {
+            queue,
+            handle: Mutex::new(Some(handle)),
+        })
+    }
+
+    /// Queues the record, applying the overflow policy if the queue is full. Returns `false`
+    /// if the thread is gone, in which case the caller writes the record itself.
+    pub(crate) fn send(&self, record: &Record) -> bool {
+        let queue = &self.queue;
+        let mut state = queue.lock();
+        if state.records >= queue.capacity {
+            let overflow = match BLOCKING.with(Cell::get) {
+                true => Overflow::Block,
+                false => queue.overflow,
+            };
+            match overflow {
+                Overflow::Block => {
+                    while state.records >= queue.capacity && !state.stopped {
+                        state = queue.popped.wait(state).unwrap_or_else(|e| e.into_inner());
+                    }
+                }
+                Overflow::DropNewest if !state.stopped => {
+                    queue.lost(record.level());
+                    return true;
+                }
+                Overflow::DropNewest => {}
+                Overflow::DropOldest => {
+                    let oldest = state
+                        .messages
+                        .iter()
+                        .position(|message| matches!(message, Message::Record(_)));
+                    if let Some(Message::Record(oldest)) =
+                        oldest.and_then(|i| state.messages.remove(i))
+                    {
+                        state.records -= 1;
+                        queue.lost(oldest.level());
+                    }
+                }
+            }
+        }
+        if state.stopped {
+            return false;
+        }
+        state.records += 1;
+        state
+            .messages
+            .push_back(Message::Record(OwnedRecord::new(record)));
+        queue.pushed.notify_one();
+        true
+    }
+
+    /// Waits until the queued records are written and the outputs flushed.
+    pub(crate) fn flush(&self) -> bool {
+        let (done, flushed) = mpsc::channel();
+        self.queue.push(Message::Flush(done)) && flushed.recv().is_ok()
+    }
+
+    /// Writes the queued records and stops the thread. Records sent afterwards are left to the
+    /// caller.
+    pub(crate) fn join(&self) {
+        let handle = self.handle.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(handle) = handle {
+            self.queue.push(Message::Stop);
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Queue {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queues a control message, whatever the capacity.
+    fn push(&self, message: Message) -> bool {
+        let mut state = self.lock();
+        if state.stopped {
+            return false;
+        }
+        state.messages.push_back(message);
+        self.pushed.notify_one();
+        true
+    }
+
+    fn pop(&self) -> Message {
+        let mut state = self.lock();
+        loop {
+            if let Some(message) = state.messages.pop_front() {
+                if let Message::Record(_) = message {
+                    state.records -= 1;
+                }
+                if let Message::Stop = message {
+                    state.stopped = true;
+                }
+                self.popped.notify_all();
+                return message;
+            }
+            state = self.pushed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Counts a record dropped because the queue was full.
+    fn lost(&self, level: Level) {
+        self.losses.stats.dropped(level);
+        if let Some(report) = &self.losses.report {
+            report.overflowed();
+        }
+    }
+}
+
+fn run(queue: &Queue, outputs: &Outputs) {
+    loop {
+        match queue.pop() {
+            Message::Record(record) => write(&record, outputs, &queue.losses.stats),
+            Message::Flush(done) => {
+                guarded(|| outputs.flush());
+                let _ = done.send(());
+            }
+            Message::Stop => break,
+        }
+    }
+    // Messages pushed before the queue was marked as stopped.
+    let rest: Vec<_> = queue.lock().messages.drain(..).collect();
+    for message in rest {
+        match message {
+            Message::Record(record) => write(&record, outputs, &queue.losses.stats),
+            Message::Flush(done) => {
+                let _ = done.send(());
+            }
+            Message::Stop => {}
+        }
+    }
+    guarded(|| outputs.flush());
+}
+
+fn write(record: &OwnedRecord, outputs: &Outputs, stats: &Counters) {
+    record.replay(|record| {
+        stats.emitted(record);
+        guarded(|| outputs.write(record));
+    });
+}
+
+/// Runs `f`, a panicking sink losing the record it was writing rather than the thread, which
+/// flushes and blocked callers would otherwise wait for forever.
+fn guarded(f: impl FnOnce()) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(f));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::sink::Sink;
+
+    /// A sink holding the records it writes until it is opened.
+    #[derive(Clone, Default)]
+    struct Gate(Arc<GateState>);
+
+    #[derive(Default)]
+    struct GateState {
+        /// Whether the gate is open, and the records written so far.
+        state: Mutex<(bool, Vec<String>)>,
+        changed: Condvar,
+    }
+
+    impl Sink for Gate {
+        fn write(&self, record: &Record) -> io::Result<()> {
+            let mut state = self.0.state.lock().unwrap();
+            state.1.push(record.args().to_string());
+            self.0.changed.notify_all();
+            while !state.0 {
+                state = self.0.changed.wait(state).unwrap();
+            }
+            Ok(())
+        }
+    }
+
+    impl Gate {
+        /// Waits until `count` records were written or are being written.
+        fn wait(&self, count: usize) {
+            let mut state = self.0.state.lock().unwrap();
+            while state.1.len() < count {
+                state = self.0.changed.wait(state).unwrap();
+            }
+        }
+
+        fn open(&self) {
+            self.0.state.lock().unwrap().0 = true;
+            self.0.changed.notify_all();
+        }
+
+        fn written(&self) -> Vec<String> {
+            self.0.state.lock().unwrap().1.clone()
+        }
+    }
+
+    /// Sends four records to a worker with room for two, while the first one is being written.
+    fn overflow(overflow: Overflow) -> (Vec<String>, u64) {
+        let gate = Gate::default();
+        let stats = Arc::new(Counters::default());
+        let outputs = Arc::new(Outputs {
+            sink: Box::new(gate.clone()),
+            routes: Vec::new(),
+            sinks: Vec::new(),
+            sync_on: None,
+        });
+        let losses = Losses {
+            stats: stats.clone(),
+            report: None,
+        };
+        let worker = Arc::new(Worker::spawn(outputs, 2, overflow, losses).unwrap());
+        let send = |worker: &Worker, i: usize| {
+            assert!(worker.send(
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("{}", i))
+                    .build()
+            ));
+        };
+
+        send(&worker, 0);
+        gate.wait(1);
+        send(&worker, 1);
+        send(&worker, 2);
+        let sent = Arc::new(AtomicBool::new(false));
+        let last = thread::spawn({
+            let (worker, sent) = (worker.clone(), sent.clone());
+            move || {
+                send(&worker, 3);
+                sent.store(true, Ordering::SeqCst);
+            }
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(sent.load(Ordering::SeqCst), overflow != Overflow::Block);
+
+        gate.open();
+        last.join().unwrap();
+        assert!(worker.flush());
+        (gate.written(), stats.snapshot().dropped(Level::Info))
+    }
+
+    /// A sink panicking on the records it is given.
+    struct Panicking(Gate);
+
+    impl Sink for Panicking {
+        fn write(&self, record: &Record) -> io::Result<()> {
+            if record.args().to_string() == "boom" {
+                panic!("sink failed");
+            }
+            self.0.write(record)
+        }
+    }
+
+    #[test]
+    fn survives_panicking_sinks() {
+        let gate = Gate::default();
+        gate.open();
+        let outputs = Arc::new(Outputs {
+            sink: Box::new(Panicking(gate.clone())),
+            routes: Vec::new(),
+            sinks: Vec::new(),
+            sync_on: None,
+        });
+        let losses = Losses {
+            stats: Arc::new(Counters::default()),
+            report: None,
+        };
+        let worker = Worker::spawn(outputs, 1, Overflow::Block, losses).unwrap();
+        for message in ["before", "boom", "after", "again"] {
+            assert!(worker.send(
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("{}", message))
+                    .build()
+            ));
+        }
+
+        assert!(worker.flush());
+        assert_eq!(gate.written(), ["before", "after", "again"]);
+    }
+
+    #[test]
+    fn applies_the_overflow_policy_when_full() {
+        let (written, dropped) = overflow(Overflow::Block);
+        assert_eq!(written, ["0", "1", "2", "3"]);
+        assert_eq!(dropped, 0);
+        let (written, dropped) = overflow(Overflow::DropNewest);
+        assert_eq!(written, ["0", "1", "2"]);
+        assert_eq!(dropped, 1);
+        let (written, dropped) = overflow(Overflow::DropOldest);
+        assert_eq!(written, ["0", "2", "3"]);
+        assert_eq!(dropped, 1);
+    }
+}
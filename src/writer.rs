@@ -0,0 +1,332 @@
+//! Destinations formatted records are written to.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use env_logger::WriteStyle;
+use termcolor::{Buffer, BufferWriter, ColorChoice};
+
+/// A sink accepting fully formatted records.
+///
+/// Records are written with a single call, under the lock of the destination, so records
+/// written from several threads never interleave.
+///
+/// Once the reader of the standard error or output is gone, as when output is piped into
+/// `head`, the writer goes quiet instead of failing on every record, and the program carries
+/// on. Other writers keep being written to, as they may reconnect.
+pub(crate) struct Writer {
+    target: Target,
+    /// Whether a closed reading end silences the writer.
+    standard: bool,
+    /// Whether a write failed because the reading end was closed.
+    closed: AtomicBool,
+    /// Whether the header of the format was written, or found at the start of the file.
+    header_written: AtomicBool,
+}
+
+enum Target {
+    /// Standard error, with colors handled by `termcolor` (including the Windows console).
+    #[cfg_attr(all(target_arch = "wasm32", feature = "wasm"), allow(dead_code))]
+    Stderr {
+        writer: BufferWriter,
+        choice: ColorChoice,
+        terminal: bool,
+        /// Progress bars drawn on standard error, cleared while records are written.
+        #[cfg(feature = "indicatif")]
+        progress: Option<indicatif::MultiProgress>,
+    },
+    /// Standard error through `eprint!`, so the test harness captures records.
+    #[cfg_attr(all(target_arch = "wasm32", feature = "wasm"), allow(dead_code))]
+    Test { colored: bool },
+    /// Any user supplied `Write` implementation.
+    Pipe {
+        inner: Mutex<Box<dyn Write + Send>>,
+        colored: bool,
+    },
+    /// A file, which can be synced to disk.
+    File { inner: Mutex<File>, colored: bool },
+}
+
+impl Writer {
+    fn new(target: Target) -> Writer {
+        Writer {
+            standard: matches!(target, Target::Stderr { .. } | Target::Test { .. }),
+            target,
+            closed: AtomicBool::new(false),
+            header_written: AtomicBool::new(false),
+        }
+    }
+
+    #[cfg_attr(all(target_arch = "wasm32", feature = "wasm"), allow(dead_code))]
+    pub(crate) fn stderr(write_style: WriteStyle) -> Writer {
+        // Terminal probing is unreliable on WASI, where colors are left off unless requested.
+        let terminal = !cfg!(target_os = "wasi") && io::stderr().is_terminal();
+        let choice = match write_style {
+            WriteStyle::Auto if terminal => ColorChoice::Auto,
+            WriteStyle::Auto | WriteStyle::Never => ColorChoice::Never,
+            WriteStyle::Always => ColorChoice::Always,
+        };
+        Writer::new(Target::Stderr {
+            writer: BufferWriter::stderr(choice),
+            choice,
+            terminal,
+            #[cfg(feature = "indicatif")]
+            progress: None,
+        })
+    }
+
+    /// Standard error, written through `eprint!` so records logged by tests are only shown for
+    /// the failing ones.
+    #[cfg_attr(all(target_arch = "wasm32", feature = "wasm"), allow(dead_code))]
+    pub(crate) fn test(write_style: WriteStyle) -> Writer {
+        let colored = match write_style {
+            WriteStyle::Auto => !cfg!(target_os = "wasi") && io::stderr().is_terminal(),
+            WriteStyle::Always => true,
+            WriteStyle::Never => false,
+        };
+        Writer::new(Target::Test { colored })
+    }
+
+    /// Standard error, with the progress bars drawn there cleared while records are written and
+    /// drawn again below them.
+    #[cfg(feature = "indicatif")]
+    pub(crate) fn progress(bars: indicatif::MultiProgress, write_style: WriteStyle) -> Writer {
+        let mut writer = Writer::stderr(write_style);
+        if let Target::Stderr { progress, .. } = &mut writer.target {
+            *progress = Some(bars);
+        }
+        writer
+    }
+
+    pub(crate) fn pipe(inner: Box<dyn Write + Send>, write_style: WriteStyle) -> Writer {
+        Writer::new(Target::Pipe {
+            inner: Mutex::new(inner),
+            colored: matches!(write_style, WriteStyle::Always),
+        })
+    }
+
+    /// The standard output, silenced like the standard error once its reader is gone.
+    pub(crate) fn stdout(write_style: WriteStyle) -> Writer {
+        let mut writer = Writer::pipe(Box::new(io::stdout()), write_style);
+        writer.standard = true;
+        writer
+    }
+
+    pub(crate) fn file(file: File, write_style: WriteStyle) -> Writer {
+        Writer::new(Target::File {
+            inner: Mutex::new(file),
+            colored: matches!(write_style, WriteStyle::Always),
+        })
+    }
+
+    /// Changes whether colors are written, following the same rules as the constructors.
+    pub(crate) fn with_style(self, write_style: WriteStyle) -> Writer {
+        let standard = self.standard;
+        let mut writer = match self.target {
+            #[cfg(feature = "indicatif")]
+            Target::Stderr {
+                progress: Some(bars),
+                ..
+            } => Writer::progress(bars, write_style),
+            Target::Stderr { .. } => Writer::stderr(write_style),
+            Target::Test { .. } => Writer::test(write_style),
+            Target::Pipe { inner, .. } => Writer::new(Target::Pipe {
+                inner,
+                colored: matches!(write_style, WriteStyle::Always),
+            }),
+            Target::File { inner, .. } => Writer::new(Target::File {
+                inner,
+                colored: matches!(write_style, WriteStyle::Always),
+            }),
+        };
+        writer.standard = standard;
+        writer
+    }
+
+    /// Whether the header of the format is to be written before the record about to be, which
+    /// is only the case for the first record, unless appending to a file which is not empty.
+    pub(crate) fn take_header(&self) -> bool {
+        !self.header_written.swap(true, Ordering::Relaxed) && !self.has_content()
+    }
+
+    /// Whether records are appended to a file which is not empty.
+    fn has_content(&self) -> bool {
+        match &self.target {
+            Target::File { inner, .. } => lock(inner).metadata().is_ok_and(|m| m.len() > 0),
+            _ => false,
+        }
+    }
+
+    /// Whether records end up on a terminal, where control characters are expected.
+    pub(crate) fn is_terminal(&self) -> bool {
+        match &self.target {
+            Target::Stderr { terminal, .. } => *terminal,
+            Target::Test { .. } | Target::Pipe { .. } | Target::File { .. } => false,
+        }
+    }
+
+    /// Number of columns of the terminal records end up on, if any.
+    ///
+    /// The size is looked up on every call, so resizing the terminal is picked up by the next
+    /// record. The `COLUMNS` environment variable is used where the terminal can't be queried.
+    pub(crate) fn width(&self) -> Option<usize> {
+        if !self.is_terminal() {
+            return None;
+        }
+        terminal_width().or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+    }
+
+    /// Runs `f` with an empty buffer matching the color capabilities of this writer, reused
+    /// across the records of the calling thread.
+    pub(crate) fn with_buffer<R>(&self, f: impl FnOnce(&mut Buffer) -> R) -> R {
+        let kind = match &self.target {
+            Target::Stderr { choice, .. } => Kind::Stderr(*choice),
+            Target::Test { colored: true }
+            | Target::Pipe { colored: true, .. }
+            | Target::File { colored: true, .. } => Kind::Ansi,
+            Target::Test { colored: false }
+            | Target::Pipe { colored: false, .. }
+            | Target::File { colored: false, .. } => Kind::NoColor,
+        };
+        with_pooled(kind, || self.buffer(), f)
+    }
+
+    /// Creates an empty buffer matching the color capabilities of this writer.
+    pub(crate) fn buffer(&self) -> Buffer {
+        match &self.target {
+            Target::Stderr { writer, .. } => writer.buffer(),
+            Target::Test { colored: true }
+            | Target::Pipe { colored: true, .. }
+            | Target::File { colored: true, .. } => Buffer::ansi(),
+            Target::Test { colored: false }
+            | Target::Pipe { colored: false, .. }
+            | Target::File { colored: false, .. } => Buffer::no_color(),
+        }
+    }
+
+    pub(crate) fn print(&self, buf: &Buffer) -> io::Result<()> {
+        self.unless_closed(|| match &self.target {
+            #[cfg(feature = "indicatif")]
+            Target::Stderr {
+                writer,
+                progress: Some(bars),
+                ..
+            } => bars.suspend(|| writer.print(buf)),
+            Target::Stderr { writer, .. } => writer.print(buf),
+            Target::Test { .. } => {
+                eprint!("{}", String::from_utf8_lossy(buf.as_slice()));
+                Ok(())
+            }
+            Target::Pipe { inner, .. } => lock(inner).write_all(buf.as_slice()),
+            Target::File { inner, .. } => lock(inner).write_all(buf.as_slice()),
+        })
+    }
+
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        self.unless_closed(|| match &self.target {
+            Target::Stderr { .. } | Target::Test { .. } => io::stderr().flush(),
+            Target::Pipe { inner, .. } => lock(inner).flush(),
+            Target::File { inner, .. } => lock(inner).flush(),
+        })
+    }
+
+    /// Flushes, and makes sure what was written to a file reached the disk.
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        match &self.target {
+            Target::File { inner, .. } => {
+                let mut file = lock(inner);
+                file.flush()?;
+                file.sync_data()
+            }
+            _ => self.flush(),
+        }
+    }
+
+    /// Runs `write` unless the reading end of a standard stream was found closed, which
+    /// silences the writer.
+    fn unless_closed(&self, write: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        if self.closed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        match write() {
+            Err(e) if self.standard && e.kind() == io::ErrorKind::BrokenPipe => {
+                self.closed.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+/// What a buffer can be reused for: buffers from `termcolor` writers may be tied to the
+/// Windows console, and plain ones write colors as ANSI sequences or not at all.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Stderr(ColorChoice),
+    Ansi,
+    NoColor,
+}
+
+/// Buffers larger than this are not kept for reuse, so a single huge record doesn't hold on
+/// to its memory for the lifetime of the thread.
+const MAX_POOLED_LEN: usize = 64 * 1024;
+
+/// Buffers kept for reuse by thread.
+const MAX_POOLED: usize = 4;
+
+thread_local! {
+    /// Buffers left over by the previous records of the thread.
+    static BUFFERS: RefCell<Vec<(Kind, Buffer)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with an empty buffer without colors, reused across the records of the calling
+/// thread.
+pub(crate) fn with_plain_buffer<R>(f: impl FnOnce(&mut Buffer) -> R) -> R {
+    with_pooled(Kind::NoColor, Buffer::no_color, f)
+}
+
+/// Runs `f` with an empty buffer of the given kind, taken from the buffers of the thread, or
+/// created with `new` if there is none, for instance when rendering a record logs another one.
+fn with_pooled<R>(kind: Kind, new: impl FnOnce() -> Buffer, f: impl FnOnce(&mut Buffer) -> R) -> R {
+    let pooled = BUFFERS
+        .try_with(|buffers| {
+            let mut buffers = buffers.borrow_mut();
+            let i = buffers.iter().position(|(k, _)| *k == kind)?;
+            Some(buffers.swap_remove(i).1)
+        })
+        .ok()
+        .flatten();
+    let mut buf = pooled.unwrap_or_else(new);
+    let result = f(&mut buf);
+    if buf.len() <= MAX_POOLED_LEN {
+        buf.clear();
+        let _ = BUFFERS.try_with(|buffers| {
+            let mut buffers = buffers.borrow_mut();
+            if buffers.len() < MAX_POOLED {
+                buffers.push((kind, buf));
+            }
+        });
+    }
+    result
+}
+
+#[cfg(unix)]
+fn terminal_width() -> Option<usize> {
+    // SAFETY: `winsize` is plain old data, and `ioctl` only writes to it.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let r = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) };
+    (r == 0 && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+#[cfg(not(unix))]
+fn terminal_width() -> Option<usize> {
+    None
+}
+
+/// Locks the writer, recovering it if a thread panicked while holding the lock.
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::{Level, Log, Record};
+use pretty_flexible_env_logger::{Builder, Logger};
+
+const THREADS: usize = 16;
+const RECORDS: usize = 500;
+
+/// A writer accepting a few bytes per call, so a record takes many writes to go through.
+#[derive(Clone, Default)]
+struct Trickle(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(7);
+        self.0.lock().unwrap().extend_from_slice(&buf[..n]);
+        thread::yield_now();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn hammer(logger: Logger) {
+    let logger = Arc::new(logger);
+    let threads: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let logger = logger.clone();
+            thread::spawn(move || {
+                for i in 0..RECORDS {
+                    logger.log(
+                        &Record::builder()
+                            .level(Level::Info)
+                            .target("hammer")
+                            .args(format_args!("start {}:{} {} end", t, i, "-".repeat(i % 64)))
+                            .build(),
+                    );
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    logger.flush();
+}
+
+fn check(output: &Trickle) {
+    let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    let mut seen = HashSet::new();
+    for line in output.lines() {
+        assert_eq!(line.matches("start").count(), 1, "interleaved: {:?}", line);
+        assert!(line.ends_with(" end"), "interleaved: {:?}", line);
+        let id = line
+            .split("start ")
+            .nth(1)
+            .unwrap()
+            .split(' ')
+            .next()
+            .unwrap();
+        assert!(seen.insert(id.to_string()), "duplicated: {:?}", line);
+    }
+    assert_eq!(seen.len(), THREADS * RECORDS);
+}
+
+#[test]
+fn records_are_never_interleaved() {
+    let output = Trickle::default();
+    hammer(
+        Builder::with("info")
+            .writer(Box::new(output.clone()))
+            .build(),
+    );
+    check(&output);
+}
+
+#[test]
+fn records_are_never_interleaved_in_blocks() {
+    let output = Trickle::default();
+    hammer(
+        Builder::with("info")
+            .writer(Box::new(output.clone()))
+            .buffering(pretty_flexible_env_logger::Buffering::Block {
+                max_delay: std::time::Duration::from_millis(1),
+            })
+            .build(),
+    );
+    check(&output);
+}
+
+#[test]
+fn records_are_never_interleaved_from_the_background_writer() {
+    let output = Trickle::default();
+    hammer(
+        Builder::with("info")
+            .writer(Box::new(output.clone()))
+            .non_blocking(64)
+            .build(),
+    );
+    check(&output);
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "macros")]
+
+use log::LevelFilter;
+use pretty_flexible_env_logger as renamed;
+use pretty_flexible_env_logger::pretty_log_test;
+
+#[pretty_log_test]
+fn initializes_the_logger() {
+    assert_ne!(log::max_level(), LevelFilter::Off);
+    log::error!("captured by the test harness");
+}
+
+#[pretty_log_test(core::prelude::v1::test)]
+fn takes_the_test_attribute() {
+    assert_ne!(log::max_level(), LevelFilter::Off);
+}
+
+#[pretty_log_test(crate = renamed)]
+fn reaches_renamed_crates() {
+    assert_ne!(log::max_level(), LevelFilter::Off);
+}
+
+#[pretty_log_test(core::prelude::v1::test, crate = crate::renamed)]
+fn takes_both_arguments() -> Result<(), String> {
+    assert_ne!(log::max_level(), LevelFilter::Off);
+    Ok(())
+}